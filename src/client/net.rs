@@ -1,17 +1,87 @@
 use crate::client::{RingParams, ShmParams};
-use crate::interface::{DriverClient, NetDriver};
-use crate::protocol::net::MacAddress;
+use crate::interface::{DriverClient, NetDriver, NetIo};
+use crate::net::checksum;
+use crate::protocol::net::{LinkStatus, MacAddress, NetStats, QueueCaps, TxOffload, WolConfig};
 use crate::protocol::{NET_PROTO, net};
-use core::sync::atomic::{AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use glenda::cap::{Endpoint, Frame};
 use glenda::client::ResourceClient;
 use glenda::error::Error;
 use glenda::interface::MemoryService;
-use glenda::io::uring::{IoUringBuffer, IoUringClient};
+use glenda::io::uring::{IoUringBuffer, IoUringClient, IoUringCqe};
+use glenda::ipc::IPC_BUFFER_SIZE;
 use glenda::ipc::{Badge, MsgFlags, MsgTag, UTCB};
 use glenda::mem::shm::SharedMemory;
 
+use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+/// Callback invoked when the driver reports a link change.
+pub type LinkCallback = Arc<dyn Fn(LinkStatus) + Send + Sync>;
+
+/// Completions popped off a ring while waiting for a different one, shared between the clones
+/// driving that ring. `peek_cqe` hands them out before reading the ring again.
+type CqeBacklog = Arc<Mutex<VecDeque<IoUringCqe>>>;
+
+/// Traffic counters as observed by the client, shared between clones.
+#[derive(Default)]
+struct Counters {
+    rx_packets: AtomicU64,
+    tx_packets: AtomicU64,
+    rx_bytes: AtomicU64,
+    tx_bytes: AtomicU64,
+    rx_errors: AtomicU64,
+    tx_errors: AtomicU64,
+    rx_crc_errors: AtomicU64,
+}
+
+impl Counters {
+    fn record_tx(&self, res: i32, len: usize) {
+        if res < 0 {
+            self.tx_errors.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.tx_packets.fetch_add(1, Ordering::Relaxed);
+            self.tx_bytes.fetch_add(len as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn record_rx(&self, cqe: &IoUringCqe) {
+        if cqe.res < 0 {
+            self.rx_errors.fetch_add(1, Ordering::Relaxed);
+        } else if cqe.flags & net::RX_CSUM_BAD != 0 {
+            self.rx_crc_errors.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.rx_packets.fetch_add(1, Ordering::Relaxed);
+            self.rx_bytes.fetch_add(cqe.res as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Account a popped completion and strip the classification bits from its `user_data`.
+    fn reap(&self, mut cqe: IoUringCqe) -> IoUringCqe {
+        if cqe.user_data & net::USER_DATA_RX != 0 {
+            self.record_rx(&cqe);
+        } else if cqe.user_data & net::USER_DATA_TX != 0 {
+            self.record_tx(cqe.res, cqe.res.max(0) as usize);
+        }
+        cqe.user_data &= !(net::USER_DATA_RX | net::USER_DATA_TX);
+        cqe
+    }
+
+    fn snapshot(&self) -> NetStats {
+        NetStats {
+            rx_packets: self.rx_packets.load(Ordering::Relaxed),
+            tx_packets: self.tx_packets.load(Ordering::Relaxed),
+            rx_bytes: self.rx_bytes.load(Ordering::Relaxed),
+            tx_bytes: self.tx_bytes.load(Ordering::Relaxed),
+            rx_errors: self.rx_errors.load(Ordering::Relaxed),
+            tx_errors: self.tx_errors.load(Ordering::Relaxed),
+            rx_crc_errors: self.rx_crc_errors.load(Ordering::Relaxed),
+            ..Default::default()
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct NetClient {
@@ -20,7 +90,13 @@ pub struct NetClient {
     ring: Option<IoUringClient>,
     shm: Option<SharedMemory>,
     next_id: Arc<AtomicU64>,
+    backlog: CqeBacklog,
     mac: Option<MacAddress>,
+    link: Arc<AtomicU64>,
+    link_changed: Arc<AtomicBool>,
+    link_cb: Arc<Mutex<Option<LinkCallback>>>,
+    offload_caps: u32,
+    counters: Arc<Counters>,
     ring_params: RingParams,
     shm_params: ShmParams,
     res_client: ResourceClient,
@@ -30,6 +106,10 @@ impl DriverClient for NetClient {
     fn connect(&mut self) -> Result<(), Error> {
         let mac = self.mac_address();
         self.mac = Some(mac);
+        if let Ok(link) = self.link_status() {
+            self.link.store(pack_link(link), Ordering::SeqCst);
+        }
+        self.offload_caps = self.query_offload_caps().unwrap_or(0);
 
         self.setup_ring_internal()?;
         self.setup_shm_internal()?;
@@ -55,7 +135,13 @@ impl NetClient {
             ring: None,
             shm: None,
             next_id: Arc::new(AtomicU64::new(0x1000)),
+            backlog: CqeBacklog::default(),
             mac: None,
+            link: Arc::new(AtomicU64::new(0)),
+            link_changed: Arc::new(AtomicBool::new(false)),
+            link_cb: Arc::new(Mutex::new(None)),
+            offload_caps: 0,
+            counters: Arc::new(Counters::default()),
             ring_params,
             shm_params,
             res_client: res_client.clone(),
@@ -83,62 +169,314 @@ impl NetClient {
         self.ring.as_ref()
     }
 
+    /// Last link state reported by the driver, either at connect time or via `NOTIFY_LINK`.
+    pub fn link_state(&self) -> LinkStatus {
+        unpack_link(self.link.load(Ordering::SeqCst))
+    }
+
+    /// Return the current link state if it changed since the last call.
+    pub fn take_link_change(&self) -> Option<LinkStatus> {
+        if self.link_changed.swap(false, Ordering::SeqCst) { Some(self.link_state()) } else { None }
+    }
+
+    /// Register a callback run from `handle_notify` on every link change. The callback is
+    /// shared by all clones and replaces any set through another clone.
+    pub fn on_link_change(&mut self, cb: LinkCallback) {
+        *self.link_cb.lock() = Some(cb);
+    }
+
+    /// Dispatch an asynchronous message received on the notify endpoint.
+    /// Returns true if the message was a net notification consumed here.
+    pub fn handle_notify(&self, utcb: &UTCB) -> bool {
+        if utcb.get_msg_tag().label() != net::NOTIFY_LINK {
+            return false;
+        }
+        let link = LinkStatus {
+            up: utcb.get_mr(0) != 0,
+            speed_mbps: utcb.get_mr(1) as u32,
+            duplex: utcb.get_mr(2) as u8,
+        };
+        self.link.store(pack_link(link), Ordering::SeqCst);
+        self.link_changed.store(true, Ordering::SeqCst);
+        // Call outside the lock so the callback may re-register itself.
+        let cb = self.link_cb.lock().clone();
+        if let Some(cb) = cb {
+            cb(link);
+        }
+        true
+    }
+
     fn next_user_data(&self) -> u64 {
         self.next_id.fetch_add(1, Ordering::SeqCst)
     }
 
+    fn ring_addr(&self, ptr: *const u8) -> u64 {
+        shm_ring_addr(self.shm.as_ref(), ptr)
+    }
+
     pub fn send_packet(&self, buf: &[u8]) -> Result<(), Error> {
         let ring = self.ring.as_ref().ok_or(Error::NotInitialized)?;
         let id = self.next_user_data();
 
-        // Use SHM address if buffer is within SHM
-        let addr = if let Some(shm) = &self.shm {
-            if shm.contains_ptr(buf.as_ptr()) {
-                shm.client_vaddr_at(buf.as_ptr()) as u64
-            } else {
-                buf.as_ptr() as u64
-            }
-        } else {
-            buf.as_ptr() as u64
-        };
-
+        let addr = self.ring_addr(buf.as_ptr());
         let sqe = net::sqe_send(addr, buf.len() as u32, id);
 
         ring.submit(sqe)?;
 
         let wait_ep = self.notify_ep.as_ref().unwrap_or(&self.endpoint);
-        loop {
-            if let Some(cqe) = ring.peek_completion() {
-                if cqe.user_data == id {
-                    if cqe.res < 0 {
-                        return Err(Error::Generic);
-                    }
-                    return Ok(());
-                }
-            }
-            ring.wait_for_completions(wait_ep)?;
+        wait_send(ring, wait_ep, id, buf.len(), &self.counters, &self.backlog)?;
+        Ok(())
+    }
+
+    /// Send a packet, asking the driver for the offloads in `offload`.
+    /// Checksums the NIC cannot compute are filled in software before submission.
+    pub fn send_packet_offload(&self, buf: &mut [u8], offload: TxOffload) -> Result<(), Error> {
+        let offload = prepare_offload(self.offload_caps, buf, offload)?;
+        let ring = self.ring.as_ref().ok_or(Error::NotInitialized)?;
+        let id = self.next_user_data();
+
+        let addr = self.ring_addr(buf.as_ptr());
+        let sqe = net::sqe_send_offload(addr, buf.len() as u32, id, offload);
+        ring.submit(sqe)?;
+
+        let wait_ep = self.notify_ep.as_ref().unwrap_or(&self.endpoint);
+        wait_send(ring, wait_ep, id, buf.len(), &self.counters, &self.backlog)?;
+        Ok(())
+    }
+
+    /// Send the first `len` bytes of `buf` and return the hardware transmit timestamp.
+    /// `buf` needs `net::TIMESTAMP_LEN` spare bytes past the frame for the driver to fill in.
+    pub fn send_packet_timestamped(&self, buf: &mut [u8], len: usize) -> Result<u64, Error> {
+        if self.offload_caps & net::OFFLOAD_TIMESTAMP == 0 || buf.len() < len + net::TIMESTAMP_LEN {
+            return Err(Error::InvalidArgs);
         }
+        let ring = self.ring.as_ref().ok_or(Error::NotInitialized)?;
+        let id = self.next_user_data();
+
+        let addr = self.ring_addr(buf.as_ptr());
+        let offload = TxOffload { flags: net::TX_TIMESTAMP, ..Default::default() };
+        ring.submit(net::sqe_send_offload(addr, len as u32, id, offload))?;
+
+        let wait_ep = self.notify_ep.as_ref().unwrap_or(&self.endpoint);
+        let cqe = wait_send(ring, wait_ep, id, len, &self.counters, &self.backlog)?;
+        net::cqe_timestamp(&cqe, buf).ok_or(Error::Generic)
     }
 
-    pub fn submit_recv(&self, buf: &mut [u8], id: u64) -> Result<(), Error> {
+    /// Queue a packet for transmission without waiting for its completion.
+    /// The buffer must stay valid until a CQE carrying `id` is observed.
+    /// The top two bits of `id` are reserved (see `net::USER_DATA_TX`).
+    pub fn submit_send(&self, buf: &[u8], id: u64) -> Result<(), Error> {
+        self.submit_send_offload(buf, id, TxOffload::default())
+    }
+
+    /// Like `submit_send`, but passes `offload` to the driver as-is.
+    /// Only request offloads advertised by `offload_caps()`.
+    pub fn submit_send_offload(&self, buf: &[u8], id: u64, offload: TxOffload) -> Result<(), Error> {
         let ring = self.ring.as_ref().ok_or(Error::NotInitialized)?;
-        let addr = if let Some(shm) = &self.shm {
-            if shm.contains_ptr(buf.as_ptr()) {
-                shm.client_vaddr_at(buf.as_ptr()) as u64
-            } else {
-                buf.as_ptr() as u64
-            }
-        } else {
-            buf.as_ptr() as u64
-        };
+        let addr = self.ring_addr(buf.as_ptr());
+        let sqe = net::sqe_send_offload(addr, buf.len() as u32, id | net::USER_DATA_TX, offload);
+        ring.submit(sqe)
+    }
+
+    /// Offload capabilities (`net::OFFLOAD_*`) reported by the driver at connect time.
+    pub fn offload_caps(&self) -> u32 {
+        self.offload_caps
+    }
+
+    /// Number of queue pairs and RSS table sizes supported by the driver.
+    pub fn queue_caps(&self) -> Result<QueueCaps, Error> {
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(NET_PROTO, net::GET_QUEUE_CAPS, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::Generic);
+        }
+
+        Ok(QueueCaps {
+            max_queues: utcb.get_mr(0) as u16,
+            rss_key_size: utcb.get_mr(1) as u16,
+            rss_indir_size: utcb.get_mr(2) as u16,
+        })
+    }
+
+    /// Set up an additional queue pair with its own ring and notify endpoint.
+    /// Queue 0 is the primary queue driven by this client and cannot be set up again.
+    /// The queue shares the packet buffer with the client; callers partition it between queues.
+    pub fn setup_queue(&mut self, index: u16, params: RingParams) -> Result<NetQueue, Error> {
+        if index == 0 || index >= self.queue_caps()?.max_queues {
+            return Err(Error::InvalidArgs);
+        }
+        let ring = self.map_ring(&params, index)?;
+        Ok(NetQueue {
+            index,
+            ring,
+            notify_ep: params.notify_ep,
+            shm: self.shm.clone(),
+            next_id: self.next_id.clone(),
+            backlog: CqeBacklog::default(),
+            offload_caps: self.offload_caps,
+            counters: self.counters.clone(),
+        })
+    }
+
+    /// Program the RSS hash key. Its length must match `QueueCaps::rss_key_size`.
+    pub fn set_rss_key(&mut self, key: &[u8]) -> Result<(), Error> {
+        if key.is_empty() || key.len() > IPC_BUFFER_SIZE {
+            return Err(Error::InvalidArgs);
+        }
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        utcb.ipc_buffer()[..key.len()].copy_from_slice(key);
+        utcb.set_size(key.len());
+        let tag = MsgTag::new(NET_PROTO, net::SET_RSS_KEY, MsgFlags::HAS_BUFFER);
+        utcb.set_msg_tag(tag);
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::InvalidArgs);
+        }
+        Ok(())
+    }
+
+    /// Program the RSS indirection table, mapping hash buckets to queue indices.
+    /// Its length must match `QueueCaps::rss_indir_size`.
+    pub fn set_rss_indirection(&mut self, table: &[u16]) -> Result<(), Error> {
+        let size = table.len() * 2;
+        if table.is_empty() || size > IPC_BUFFER_SIZE {
+            return Err(Error::InvalidArgs);
+        }
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let buf = utcb.ipc_buffer();
+        for (i, queue) in table.iter().enumerate() {
+            buf[i * 2..i * 2 + 2].copy_from_slice(&queue.to_le_bytes());
+        }
+        utcb.set_size(size);
+        let tag = MsgTag::new(NET_PROTO, net::SET_RSS_INDIR, MsgFlags::HAS_BUFFER);
+        utcb.set_msg_tag(tag);
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::InvalidArgs);
+        }
+        Ok(())
+    }
+
+    /// Spread the indirection table round-robin over queues `0..queues`.
+    pub fn set_rss_queues(&mut self, queues: u16) -> Result<(), Error> {
+        let caps = self.queue_caps()?;
+        if queues == 0 || queues > caps.max_queues || caps.rss_indir_size == 0 {
+            return Err(Error::InvalidArgs);
+        }
+        let table: Vec<u16> = (0..caps.rss_indir_size).map(|i| i % queues).collect();
+        self.set_rss_indirection(&table)
+    }
+
+    /// Select the header fields hashed by RSS (`net::RSS_HASH_*`).
+    pub fn set_rss_hash(&mut self, types: u32) -> Result<(), Error> {
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(NET_PROTO, net::SET_RSS_HASH, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        utcb.set_mr(0, types as usize);
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::InvalidArgs);
+        }
+        Ok(())
+    }
 
-        let sqe = net::sqe_recv(addr, buf.len() as u32, id);
+    /// Post a receive buffer. The top two bits of `id` are reserved (see `net::USER_DATA_RX`).
+    ///
+    /// # Safety
+    ///
+    /// The driver keeps a pointer to `buf` and fills it in later. `buf` must stay valid, and
+    /// must not be accessed through any other reference, until its completion has been reaped
+    /// or the client and all of its clones have been dropped.
+    pub unsafe fn submit_recv(&self, buf: &mut [u8], id: u64) -> Result<(), Error> {
+        let ring = self.ring.as_ref().ok_or(Error::NotInitialized)?;
+        let addr = self.ring_addr(buf.as_ptr());
+        let sqe = net::sqe_recv(addr, buf.len() as u32, id | net::USER_DATA_RX);
         ring.submit(sqe)?;
         Ok(())
     }
 
-    pub fn peek_cqe(&self) -> Option<glenda::io::uring::IoUringCqe> {
-        self.ring.as_ref()?.peek_completion()
+    /// Pop the next completion, updating the client counters for submitted sends and receives.
+    pub fn peek_cqe(&self) -> Option<IoUringCqe> {
+        let queued = self.backlog.lock().pop_front();
+        let cqe = match queued {
+            Some(cqe) => cqe,
+            None => self.ring.as_ref()?.peek_completion()?,
+        };
+        Some(self.counters.reap(cqe))
+    }
+
+    /// Take the completion of the receive buffer posted as `id`, leaving others for
+    /// `peek_cqe`.
+    pub fn take_recv_cqe(&self, id: u64) -> Option<IoUringCqe> {
+        let ring = self.ring.as_ref()?;
+        let cqe = take_completion(ring, id | net::USER_DATA_RX, &self.backlog)?;
+        Some(self.counters.reap(cqe))
+    }
+
+    /// Packet and error counters as seen by this client (and its clones).
+    pub fn client_stats(&self) -> NetStats {
+        self.counters.snapshot()
+    }
+
+    /// Interface statistics maintained by the driver.
+    pub fn stats(&self) -> Result<NetStats, Error> {
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(NET_PROTO, net::GET_STATS, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::Generic);
+        }
+
+        unsafe { utcb.read_obj::<NetStats>() }
+    }
+
+    pub fn wait_for_completions(&self) -> Result<(), Error> {
+        let ring = self.ring.as_ref().ok_or(Error::NotInitialized)?;
+        let ep = self.notify_ep.as_ref().unwrap_or(&self.endpoint);
+        ring.wait_for_completions(ep)
+    }
+}
+
+impl NetIo for NetClient {
+    fn send_packet(&self, buf: &[u8]) -> Result<(), Error> {
+        NetClient::send_packet(self, buf)
+    }
+
+    unsafe fn submit_recv(&self, buf: &mut [u8], id: u64) -> Result<(), Error> {
+        unsafe { NetClient::submit_recv(self, buf, id) }
+    }
+
+    fn peek_cqe(&self) -> Option<IoUringCqe> {
+        NetClient::peek_cqe(self)
+    }
+
+    fn take_recv_cqe(&self, id: u64) -> Option<IoUringCqe> {
+        NetClient::take_recv_cqe(self, id)
+    }
+
+    fn offload_caps(&self) -> u32 {
+        self.offload_caps
+    }
+
+    fn send_packet_offload(&self, buf: &mut [u8], offload: TxOffload) -> Result<(), Error> {
+        NetClient::send_packet_offload(self, buf, offload)
+    }
+
+    fn send_packet_timestamped(&self, buf: &mut [u8], len: usize) -> Result<u64, Error> {
+        NetClient::send_packet_timestamped(self, buf, len)
     }
 }
 
@@ -158,18 +496,312 @@ impl NetDriver for NetClient {
             MacAddress { octets: [0; 6] }
         }
     }
+
+    fn link_status(&self) -> Result<LinkStatus, Error> {
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(NET_PROTO, net::GET_LINK_STATUS, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::Generic);
+        }
+
+        Ok(LinkStatus {
+            up: utcb.get_mr(0) != 0,
+            speed_mbps: utcb.get_mr(1) as u32,
+            duplex: utcb.get_mr(2) as u8,
+        })
+    }
+
+    fn mtu(&self) -> Result<u32, Error> {
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(NET_PROTO, net::GET_MTU, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::Generic);
+        }
+
+        Ok(utcb.get_mr(0) as u32)
+    }
+
+    fn set_mtu(&mut self, mtu: u32) -> Result<(), Error> {
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(NET_PROTO, net::SET_MTU, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        utcb.set_mr(0, mtu as usize);
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::InvalidArgs);
+        }
+        Ok(())
+    }
+
+    fn set_mac_address(&mut self, mac: MacAddress) -> Result<(), Error> {
+        if !mac.is_unicast() {
+            return Err(Error::InvalidArgs);
+        }
+        self.call_with_mac(net::SET_MAC, mac)?;
+        self.mac = Some(mac);
+        Ok(())
+    }
+
+    fn add_multicast(&mut self, mac: MacAddress) -> Result<(), Error> {
+        if !mac.is_multicast() {
+            return Err(Error::InvalidArgs);
+        }
+        self.call_with_mac(net::ADD_MULTICAST, mac)
+    }
+
+    fn remove_multicast(&mut self, mac: MacAddress) -> Result<(), Error> {
+        if !mac.is_multicast() {
+            return Err(Error::InvalidArgs);
+        }
+        self.call_with_mac(net::DEL_MULTICAST, mac)
+    }
+
+    fn set_promiscuous(&mut self, enable: bool) -> Result<(), Error> {
+        self.call_with_flag(net::SET_PROMISC, enable)
+    }
+
+    fn set_all_multicast(&mut self, enable: bool) -> Result<(), Error> {
+        self.call_with_flag(net::SET_ALLMULTI, enable)
+    }
+
+    fn ptp_time(&self) -> Result<u64, Error> {
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(NET_PROTO, net::PTP_GET_TIME, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::Generic);
+        }
+
+        Ok(utcb.get_mr(0) as u64)
+    }
+
+    fn set_ptp_time(&mut self, ns: u64) -> Result<(), Error> {
+        self.call_with_arg(net::PTP_SET_TIME, ns as usize)
+    }
+
+    fn adjust_ptp_time(&mut self, delta_ns: i64) -> Result<(), Error> {
+        self.call_with_arg(net::PTP_ADJ_TIME, delta_ns as usize)
+    }
+
+    fn adjust_ptp_frequency(&mut self, ppb: i64) -> Result<(), Error> {
+        self.call_with_arg(net::PTP_ADJ_FREQ, ppb as usize)
+    }
+
+    fn set_rx_timestamping(&mut self, mode: u8) -> Result<(), Error> {
+        if mode > net::RX_TIMESTAMP_ALL {
+            return Err(Error::InvalidArgs);
+        }
+        self.call_with_arg(net::SET_RX_TIMESTAMP, mode as usize)
+    }
+
+    fn wake_on_lan(&self) -> Result<WolConfig, Error> {
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(NET_PROTO, net::GET_WOL, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::Generic);
+        }
+
+        Ok(WolConfig { supported: utcb.get_mr(0) as u32, enabled: utcb.get_mr(1) as u32 })
+    }
+
+    fn set_wake_on_lan(&mut self, mut modes: u32, secure_on: Option<[u8; 6]>) -> Result<(), Error> {
+        if modes & net::WOL_MAGIC_SECURE != 0 {
+            if secure_on.is_none() {
+                return Err(Error::InvalidArgs);
+            }
+            modes |= net::WOL_MAGIC;
+        }
+        if modes & !self.wake_on_lan()?.supported != 0 {
+            return Err(Error::InvalidArgs);
+        }
+
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(NET_PROTO, net::SET_WOL, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        utcb.set_mr(0, modes as usize);
+        if let Some(password) = secure_on {
+            for (i, b) in password.iter().enumerate() {
+                utcb.set_mr(1 + i, *b as usize);
+            }
+        }
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::InvalidArgs);
+        }
+        Ok(())
+    }
+}
+
+/// Strip the offloads the NIC cannot do from `offload`, doing checksums in software instead.
+fn prepare_offload(caps: u32, buf: &mut [u8], mut offload: TxOffload) -> Result<TxOffload, Error> {
+    if offload.flags & net::TX_TSO != 0 && caps & net::OFFLOAD_TSO == 0 {
+        return Err(Error::InvalidArgs);
+    }
+    if offload.flags & net::TX_VLAN != 0 && caps & net::OFFLOAD_VLAN_TX == 0 {
+        return Err(Error::InvalidArgs);
+    }
+
+    let l4_caps = net::OFFLOAD_TX_CSUM_TCP | net::OFFLOAD_TX_CSUM_UDP;
+    let sw_l3 = offload.flags & net::TX_CSUM_L3 != 0 && caps & net::OFFLOAD_TX_CSUM_IPV4 == 0;
+    let sw_l4 = offload.flags & net::TX_CSUM_L4 != 0 && caps & l4_caps != l4_caps;
+    if sw_l3 || sw_l4 {
+        if !checksum::fill_checksums(buf, sw_l3, sw_l4) {
+            return Err(Error::InvalidArgs);
+        }
+        if sw_l3 {
+            offload.flags &= !net::TX_CSUM_L3;
+        }
+        if sw_l4 {
+            offload.flags &= !net::TX_CSUM_L4;
+        }
+    }
+    Ok(offload)
+}
+
+/// Translate a buffer pointer into the address the driver expects on the ring.
+/// Buffers inside the SHM region are rebased onto the client view of the SHM.
+fn shm_ring_addr(shm: Option<&SharedMemory>, ptr: *const u8) -> u64 {
+    match shm {
+        Some(shm) if shm.contains_ptr(ptr) => shm.client_vaddr_at(ptr) as u64,
+        _ => ptr as u64,
+    }
+}
+
+/// Find the completion whose raw `user_data` is `user_data`, queueing other completions read
+/// off the ring on `backlog` for `peek_cqe`.
+fn take_completion(
+    ring: &IoUringClient,
+    user_data: u64,
+    backlog: &Mutex<VecDeque<IoUringCqe>>,
+) -> Option<IoUringCqe> {
+    let mut backlog = backlog.lock();
+    // A clone reading the same ring may already have queued it.
+    if let Some(pos) = backlog.iter().position(|cqe| cqe.user_data == user_data) {
+        return backlog.remove(pos);
+    }
+    while let Some(cqe) = ring.peek_completion() {
+        if cqe.user_data == user_data {
+            return Some(cqe);
+        }
+        backlog.push_back(cqe);
+    }
+    None
+}
+
+/// Block until the send submitted as `id` completes, returning its completion.
+/// Other completions read off the ring meanwhile are queued on `backlog` for `peek_cqe`.
+fn wait_send(
+    ring: &IoUringClient,
+    wait_ep: &Endpoint,
+    id: u64,
+    len: usize,
+    counters: &Counters,
+    backlog: &Mutex<VecDeque<IoUringCqe>>,
+) -> Result<IoUringCqe, Error> {
+    loop {
+        if let Some(cqe) = take_completion(ring, id, backlog) {
+            counters.record_tx(cqe.res, len);
+            if cqe.res < 0 {
+                return Err(Error::Generic);
+            }
+            return Ok(cqe);
+        }
+        ring.wait_for_completions(wait_ep)?;
+    }
+}
+
+fn pack_link(link: LinkStatus) -> u64 {
+    ((link.speed_mbps as u64) << 32) | ((link.duplex as u64) << 8) | link.up as u64
+}
+
+fn unpack_link(v: u64) -> LinkStatus {
+    LinkStatus { up: v & 1 != 0, speed_mbps: (v >> 32) as u32, duplex: (v >> 8) as u8 }
 }
 
 impl NetClient {
+    fn call_with_mac(&self, label: usize, mac: MacAddress) -> Result<(), Error> {
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(NET_PROTO, label, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        for (i, b) in mac.octets.iter().enumerate() {
+            utcb.set_mr(i, *b as usize);
+        }
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::Generic);
+        }
+        Ok(())
+    }
+
+    fn call_with_flag(&self, label: usize, enable: bool) -> Result<(), Error> {
+        self.call_with_arg(label, enable as usize)
+    }
+
+    fn call_with_arg(&self, label: usize, arg: usize) -> Result<(), Error> {
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(NET_PROTO, label, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        utcb.set_mr(0, arg);
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::Generic);
+        }
+        Ok(())
+    }
+
+    fn query_offload_caps(&self) -> Result<u32, Error> {
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(NET_PROTO, net::GET_OFFLOAD_CAPS, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::Generic);
+        }
+
+        Ok(utcb.get_mr(0) as u32)
+    }
+
     fn setup_ring_internal(&mut self) -> Result<(), Error> {
-        let sq_entries = self.ring_params.sq_entries;
-        let cq_entries = self.ring_params.cq_entries;
-        let notify_ep = self.ring_params.notify_ep;
-        let recv = self.ring_params.recv_slot;
-        let vaddr = self.ring_params.vaddr;
-        let size = self.ring_params.size;
+        let params = self.ring_params.clone();
+        self.notify_ep = Some(params.notify_ep);
+        self.ring = Some(self.map_ring(&params, 0)?);
+        Ok(())
+    }
+
+    /// Ask the driver for the ring of queue `index` and map it at `params.vaddr`.
+    fn map_ring(&mut self, params: &RingParams, index: u16) -> Result<IoUringClient, Error> {
+        let sq_entries = params.sq_entries;
+        let cq_entries = params.cq_entries;
+        let notify_ep = params.notify_ep;
+        let recv = params.recv_slot;
+        let vaddr = params.vaddr;
+        let size = params.size;
 
-        self.notify_ep = Some(notify_ep);
         let mut utcb = unsafe { UTCB::new() };
         utcb.clear();
         utcb.set_recv_window(recv);
@@ -177,6 +809,7 @@ impl NetClient {
         let tag = MsgTag::new(NET_PROTO, net::SETUP_RING, MsgFlags::HAS_CAP);
         utcb.set_mr(0, sq_entries as usize);
         utcb.set_mr(1, cq_entries as usize);
+        utcb.set_mr(2, index as usize);
         utcb.set_msg_tag(tag);
 
         self.endpoint.call(&mut utcb)?;
@@ -185,8 +818,7 @@ impl NetClient {
         self.res_client.mmap(Badge::null(), frame.clone(), vaddr, size)?;
         let ring_buf =
             unsafe { IoUringBuffer::new(vaddr as *mut u8, size, sq_entries as u32, cq_entries as u32) };
-        self.ring = Some(IoUringClient::new(ring_buf));
-        Ok(())
+        Ok(IoUringClient::new(ring_buf))
     }
 
     fn setup_shm_internal(&mut self) -> Result<(), Error> {
@@ -214,3 +846,135 @@ impl NetClient {
         Ok(())
     }
 }
+
+/// An additional RX/TX queue pair of a multi-queue NIC, created by `NetClient::setup_queue`.
+///
+/// Each queue has its own ring and notify endpoint, so queues can be driven from different
+/// threads. RSS decides which queue receives a flow; sends may go out on any queue.
+/// Counters are shared with the owning client.
+#[derive(Clone)]
+pub struct NetQueue {
+    index: u16,
+    ring: IoUringClient,
+    notify_ep: Endpoint,
+    shm: Option<SharedMemory>,
+    next_id: Arc<AtomicU64>,
+    backlog: CqeBacklog,
+    offload_caps: u32,
+    counters: Arc<Counters>,
+}
+
+impl NetQueue {
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    pub fn ring(&self) -> &IoUringClient {
+        &self.ring
+    }
+
+    pub fn notify_endpoint(&self) -> Endpoint {
+        self.notify_ep
+    }
+
+    pub fn send_packet(&self, buf: &[u8]) -> Result<(), Error> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let addr = shm_ring_addr(self.shm.as_ref(), buf.as_ptr());
+        self.ring.submit(net::sqe_send(addr, buf.len() as u32, id))?;
+        wait_send(&self.ring, &self.notify_ep, id, buf.len(), &self.counters, &self.backlog)?;
+        Ok(())
+    }
+
+    /// See `NetClient::send_packet_offload`.
+    pub fn send_packet_offload(&self, buf: &mut [u8], offload: TxOffload) -> Result<(), Error> {
+        let offload = prepare_offload(self.offload_caps, buf, offload)?;
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let addr = shm_ring_addr(self.shm.as_ref(), buf.as_ptr());
+        self.ring.submit(net::sqe_send_offload(addr, buf.len() as u32, id, offload))?;
+        wait_send(&self.ring, &self.notify_ep, id, buf.len(), &self.counters, &self.backlog)?;
+        Ok(())
+    }
+
+    /// See `NetClient::send_packet_timestamped`.
+    pub fn send_packet_timestamped(&self, buf: &mut [u8], len: usize) -> Result<u64, Error> {
+        if self.offload_caps & net::OFFLOAD_TIMESTAMP == 0 || buf.len() < len + net::TIMESTAMP_LEN {
+            return Err(Error::InvalidArgs);
+        }
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let addr = shm_ring_addr(self.shm.as_ref(), buf.as_ptr());
+        let offload = TxOffload { flags: net::TX_TIMESTAMP, ..Default::default() };
+        self.ring.submit(net::sqe_send_offload(addr, len as u32, id, offload))?;
+        let cqe = wait_send(&self.ring, &self.notify_ep, id, len, &self.counters, &self.backlog)?;
+        net::cqe_timestamp(&cqe, buf).ok_or(Error::Generic)
+    }
+
+    /// See `NetClient::submit_send`.
+    pub fn submit_send(&self, buf: &[u8], id: u64) -> Result<(), Error> {
+        self.submit_send_offload(buf, id, TxOffload::default())
+    }
+
+    pub fn submit_send_offload(&self, buf: &[u8], id: u64, offload: TxOffload) -> Result<(), Error> {
+        let addr = shm_ring_addr(self.shm.as_ref(), buf.as_ptr());
+        let sqe = net::sqe_send_offload(addr, buf.len() as u32, id | net::USER_DATA_TX, offload);
+        self.ring.submit(sqe)
+    }
+
+    /// See `NetClient::submit_recv`.
+    ///
+    /// # Safety
+    ///
+    /// Same contract as `NetClient::submit_recv`.
+    pub unsafe fn submit_recv(&self, buf: &mut [u8], id: u64) -> Result<(), Error> {
+        let addr = shm_ring_addr(self.shm.as_ref(), buf.as_ptr());
+        self.ring.submit(net::sqe_recv(addr, buf.len() as u32, id | net::USER_DATA_RX))
+    }
+
+    pub fn peek_cqe(&self) -> Option<IoUringCqe> {
+        let queued = self.backlog.lock().pop_front();
+        let cqe = match queued {
+            Some(cqe) => cqe,
+            None => self.ring.peek_completion()?,
+        };
+        Some(self.counters.reap(cqe))
+    }
+
+    /// See `NetClient::take_recv_cqe`.
+    pub fn take_recv_cqe(&self, id: u64) -> Option<IoUringCqe> {
+        let cqe = take_completion(&self.ring, id | net::USER_DATA_RX, &self.backlog)?;
+        Some(self.counters.reap(cqe))
+    }
+
+    pub fn wait_for_completions(&self) -> Result<(), Error> {
+        self.ring.wait_for_completions(&self.notify_ep)
+    }
+}
+
+impl NetIo for NetQueue {
+    fn send_packet(&self, buf: &[u8]) -> Result<(), Error> {
+        NetQueue::send_packet(self, buf)
+    }
+
+    unsafe fn submit_recv(&self, buf: &mut [u8], id: u64) -> Result<(), Error> {
+        unsafe { NetQueue::submit_recv(self, buf, id) }
+    }
+
+    fn peek_cqe(&self) -> Option<IoUringCqe> {
+        NetQueue::peek_cqe(self)
+    }
+
+    fn take_recv_cqe(&self, id: u64) -> Option<IoUringCqe> {
+        NetQueue::take_recv_cqe(self, id)
+    }
+
+    fn offload_caps(&self) -> u32 {
+        self.offload_caps
+    }
+
+    fn send_packet_offload(&self, buf: &mut [u8], offload: TxOffload) -> Result<(), Error> {
+        NetQueue::send_packet_offload(self, buf, offload)
+    }
+
+    fn send_packet_timestamped(&self, buf: &mut [u8], len: usize) -> Result<u64, Error> {
+        NetQueue::send_packet_timestamped(self, buf, len)
+    }
+}
@@ -1,7 +1,8 @@
 use crate::client::{RingParams, ShmParams};
 use crate::interface::{DriverClient, UartDriver};
+use crate::protocol::uart::{Rs485Config, UartConfig, UartEvent, UartStats};
 use crate::protocol::{UART_PROTO, uart};
-use core::sync::atomic::{AtomicU64, Ordering};
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use glenda::cap::{Endpoint, Frame};
 use glenda::client::ResourceClient;
 use glenda::error::Error;
@@ -12,6 +13,10 @@ use glenda::ipc::{Badge, MsgFlags, MsgTag, UTCB};
 use glenda::mem::shm::SharedMemory;
 
 use alloc::sync::Arc;
+use spin::Mutex;
+
+/// Callback invoked when the driver reports a line event.
+pub type UartEventCallback = Arc<dyn Fn(UartEvent) + Send + Sync>;
 
 #[derive(Clone)]
 pub struct UartClient {
@@ -20,6 +25,10 @@ pub struct UartClient {
     ring: Option<IoUringClient>,
     shm: Option<SharedMemory>,
     next_id: Arc<AtomicU64>,
+    lines: Arc<AtomicU32>,
+    pending_events: Arc<AtomicU32>,
+    lost: Arc<AtomicU64>,
+    event_cb: Arc<Mutex<Option<UartEventCallback>>>,
     ring_params: RingParams,
     shm_params: ShmParams,
     res_client: ResourceClient,
@@ -51,6 +60,10 @@ impl UartClient {
             ring: None,
             shm: None,
             next_id: Arc::new(AtomicU64::new(0x1000)),
+            lines: Arc::new(AtomicU32::new(0)),
+            pending_events: Arc::new(AtomicU32::new(0)),
+            lost: Arc::new(AtomicU64::new(0)),
+            event_cb: Arc::new(Mutex::new(None)),
             ring_params,
             shm_params,
             res_client: res_client.clone(),
@@ -65,11 +78,58 @@ impl UartClient {
         self.shm = Some(shm);
     }
 
+    pub fn shm(&self) -> Option<&SharedMemory> {
+        self.shm.as_ref()
+    }
+
     pub fn set_ring(&mut self, mut ring: IoUringClient) {
         ring.set_server_notify(self.endpoint);
         self.ring = Some(ring);
     }
 
+    /// Modem line levels from the last `NOTIFY_EVENT`.
+    pub fn last_modem_lines(&self) -> u32 {
+        self.lines.load(Ordering::SeqCst)
+    }
+
+    /// Return and clear the `uart::EVENT_*` bits reported since the last call.
+    pub fn take_events(&self) -> u32 {
+        self.pending_events.swap(0, Ordering::SeqCst)
+    }
+
+    /// Total bytes the driver reported lost through `NOTIFY_EVENT`.
+    pub fn lost_bytes(&self) -> u64 {
+        self.lost.load(Ordering::SeqCst)
+    }
+
+    /// Register a callback run from `handle_notify` on every line event. The callback is
+    /// shared by all clones and replaces any set through another clone.
+    pub fn on_event(&mut self, cb: UartEventCallback) {
+        *self.event_cb.lock() = Some(cb);
+    }
+
+    /// Dispatch an asynchronous message received on the notify endpoint.
+    /// Returns true if the message was a UART event consumed here.
+    pub fn handle_notify(&self, utcb: &UTCB) -> bool {
+        if utcb.get_msg_tag().label() != uart::NOTIFY_EVENT {
+            return false;
+        }
+        let event = UartEvent {
+            lines: utcb.get_mr(0) as u32,
+            events: utcb.get_mr(1) as u32,
+            lost: utcb.get_mr(2) as u32,
+        };
+        self.lines.store(event.lines, Ordering::SeqCst);
+        self.lost.fetch_add(event.lost as u64, Ordering::SeqCst);
+        self.pending_events.fetch_or(event.events, Ordering::SeqCst);
+        // Call outside the lock so the callback may re-register itself.
+        let cb = self.event_cb.lock().clone();
+        if let Some(cb) = cb {
+            cb(event);
+        }
+        true
+    }
+
     fn next_user_data(&self) -> u64 {
         self.next_id.fetch_add(1, Ordering::SeqCst)
     }
@@ -198,4 +258,182 @@ impl UartDriver for UartClient {
 
         let _ = self.endpoint.call(&mut utcb);
     }
+
+    fn config(&self) -> Result<UartConfig, Error> {
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(UART_PROTO, uart::GET_CONFIG, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::Generic);
+        }
+
+        Ok(UartConfig {
+            baud_rate: utcb.get_mr(0) as u32,
+            data_bits: utcb.get_mr(1) as u8,
+            stop_bits: (utcb.get_mr(2) as u8).try_into()?,
+            parity: (utcb.get_mr(3) as u8).try_into()?,
+            flow_control: (utcb.get_mr(4) as u8).try_into()?,
+        })
+    }
+
+    fn set_config(&mut self, config: &UartConfig) -> Result<(), Error> {
+        if config.baud_rate == 0 || !(5..=8).contains(&config.data_bits) {
+            return Err(Error::InvalidArgs);
+        }
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(UART_PROTO, uart::SET_CONFIG, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        utcb.set_mr(0, config.baud_rate as usize);
+        utcb.set_mr(1, config.data_bits as usize);
+        utcb.set_mr(2, config.stop_bits as usize);
+        utcb.set_mr(3, config.parity as usize);
+        utcb.set_mr(4, config.flow_control as usize);
+        self.endpoint.call(&mut utcb)?;
+
+        // The driver refuses settings the hardware cannot do.
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::InvalidArgs);
+        }
+        Ok(())
+    }
+
+    fn modem_lines(&self) -> Result<u32, Error> {
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(UART_PROTO, uart::GET_MODEM, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::Generic);
+        }
+
+        Ok(utcb.get_mr(0) as u32)
+    }
+
+    fn set_modem_lines(&mut self, mask: u32, levels: u32) -> Result<(), Error> {
+        if mask & !(uart::MODEM_DTR | uart::MODEM_RTS) != 0 {
+            return Err(Error::InvalidArgs);
+        }
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(UART_PROTO, uart::SET_MODEM, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        utcb.set_mr(0, mask as usize);
+        utcb.set_mr(1, (levels & mask) as usize);
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::InvalidArgs);
+        }
+        Ok(())
+    }
+
+    fn send_break(&mut self, duration_ms: u32) -> Result<(), Error> {
+        if duration_ms == 0 {
+            return Err(Error::InvalidArgs);
+        }
+        self.call_with_arg(uart::SEND_BREAK, duration_ms as usize)
+    }
+
+    fn set_break(&mut self, enable: bool) -> Result<(), Error> {
+        self.call_with_arg(uart::SET_BREAK, enable as usize)
+    }
+
+    fn stats(&self) -> Result<UartStats, Error> {
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(UART_PROTO, uart::GET_STATS, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::Generic);
+        }
+
+        unsafe { utcb.read_obj::<UartStats>() }
+    }
+
+    fn set_event_mask(&mut self, mask: u32) -> Result<(), Error> {
+        self.call_with_arg(uart::SET_EVENT_MASK, mask as usize)
+    }
+
+    fn set_rx_threshold(&mut self, bytes: u32) -> Result<(), Error> {
+        self.call_with_arg(uart::SET_RX_THRESHOLD, bytes as usize)
+    }
+
+    fn rs485_config(&self) -> Result<Rs485Config, Error> {
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(UART_PROTO, uart::GET_RS485, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::Generic);
+        }
+
+        Ok(Rs485Config::from_flags(
+            utcb.get_mr(0) as u32,
+            utcb.get_mr(1) as u32,
+            utcb.get_mr(2) as u32,
+            utcb.get_mr(3) as u8,
+        ))
+    }
+
+    fn set_rs485_config(&mut self, config: &Rs485Config) -> Result<(), Error> {
+        if config.address_filter.is_some() && !config.address_mode {
+            return Err(Error::InvalidArgs);
+        }
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(UART_PROTO, uart::SET_RS485, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        utcb.set_mr(0, config.flags() as usize);
+        utcb.set_mr(1, config.delay_before_send_us as usize);
+        utcb.set_mr(2, config.delay_after_send_us as usize);
+        utcb.set_mr(3, config.address_filter.unwrap_or(0) as usize);
+        self.endpoint.call(&mut utcb)?;
+
+        // The driver refuses settings the hardware cannot do.
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::InvalidArgs);
+        }
+        Ok(())
+    }
+
+    fn send_address(&mut self, address: u8) -> Result<(), Error> {
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(UART_PROTO, uart::SEND_ADDRESS, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        utcb.set_mr(0, address as usize);
+        self.endpoint.call(&mut utcb)?;
+
+        // Refused unless the port is in RS-485 address mode.
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::InvalidArgs);
+        }
+        Ok(())
+    }
+}
+
+impl UartClient {
+    fn call_with_arg(&self, label: usize, arg: usize) -> Result<(), Error> {
+        let mut utcb = unsafe { UTCB::new() };
+        utcb.clear();
+        let tag = MsgTag::new(UART_PROTO, label, MsgFlags::NONE);
+        utcb.set_msg_tag(tag);
+        utcb.set_mr(0, arg);
+        self.endpoint.call(&mut utcb)?;
+
+        if !utcb.get_msg_tag().flags().contains(MsgFlags::OK) {
+            return Err(Error::Generic);
+        }
+        Ok(())
+    }
 }
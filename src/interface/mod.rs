@@ -1,14 +1,16 @@
 use crate::protocol::fb::FbInfo;
 use crate::protocol::input::InputEvent;
-use crate::protocol::net::MacAddress;
+use crate::protocol::net::{LinkStatus, MacAddress, TxOffload, WolConfig};
 use crate::protocol::pci::PciAddress;
 use crate::protocol::sdio::SdioCommand;
 use crate::protocol::thermal::ThermalZones;
+use crate::protocol::uart::{MODEM_DTR, MODEM_RTS, Rs485Config, UartConfig, UartStats};
 use crate::protocol::usb::UsbSetupPacket;
 use crate::protocol::wifi::WifiApInfo;
 use alloc::string::String;
 use alloc::vec::Vec;
 use glenda::error::Error;
+use glenda::io::uring::IoUringCqe;
 use glenda::ipc::Badge;
 use glenda::protocol::device::DeviceDescNode;
 pub trait DriverService {
@@ -39,16 +41,216 @@ pub trait PciDriver {
 }
 
 /// NetDriver provides metadata and asynchronous IO ring setup for network packet transmission.
+/// Optional controls default to `Error::InvalidArgs` for drivers that lack them.
 pub trait NetDriver {
     fn mac_address(&self) -> MacAddress;
+
+    /// Get carrier state and the negotiated speed/duplex.
+    fn link_status(&self) -> Result<LinkStatus, Error> {
+        Err(Error::InvalidArgs)
+    }
+
+    /// Get the MTU in bytes, excluding the Ethernet header.
+    fn mtu(&self) -> Result<u32, Error> {
+        Err(Error::InvalidArgs)
+    }
+
+    fn set_mtu(&mut self, mtu: u32) -> Result<(), Error> {
+        let _ = mtu;
+        Err(Error::InvalidArgs)
+    }
+
+    fn set_mac_address(&mut self, mac: MacAddress) -> Result<(), Error> {
+        let _ = mac;
+        Err(Error::InvalidArgs)
+    }
+
+    /// Join a multicast group; `mac` must have the group bit set.
+    fn add_multicast(&mut self, mac: MacAddress) -> Result<(), Error> {
+        let _ = mac;
+        Err(Error::InvalidArgs)
+    }
+
+    fn remove_multicast(&mut self, mac: MacAddress) -> Result<(), Error> {
+        let _ = mac;
+        Err(Error::InvalidArgs)
+    }
+
+    fn set_promiscuous(&mut self, enable: bool) -> Result<(), Error> {
+        let _ = enable;
+        Err(Error::InvalidArgs)
+    }
+
+    fn set_all_multicast(&mut self, enable: bool) -> Result<(), Error> {
+        let _ = enable;
+        Err(Error::InvalidArgs)
+    }
+
+    /// Read the PTP hardware clock, in nanoseconds.
+    fn ptp_time(&self) -> Result<u64, Error> {
+        Err(Error::InvalidArgs)
+    }
+
+    fn set_ptp_time(&mut self, ns: u64) -> Result<(), Error> {
+        let _ = ns;
+        Err(Error::InvalidArgs)
+    }
+
+    /// Step the PTP hardware clock by `delta_ns`.
+    fn adjust_ptp_time(&mut self, delta_ns: i64) -> Result<(), Error> {
+        let _ = delta_ns;
+        Err(Error::InvalidArgs)
+    }
+
+    /// Set the PTP hardware clock frequency offset from nominal, in parts per billion.
+    fn adjust_ptp_frequency(&mut self, ppb: i64) -> Result<(), Error> {
+        let _ = ppb;
+        Err(Error::InvalidArgs)
+    }
+
+    /// Select which received frames are timestamped (`net::RX_TIMESTAMP_*`).
+    fn set_rx_timestamping(&mut self, mode: u8) -> Result<(), Error> {
+        let _ = mode;
+        Err(Error::InvalidArgs)
+    }
+
+    /// Get the Wake-on-LAN modes supported and armed.
+    fn wake_on_lan(&self) -> Result<WolConfig, Error> {
+        Err(Error::InvalidArgs)
+    }
+
+    /// Arm Wake-on-LAN for `modes` (`net::WOL_*`; 0 disables it) before suspending through
+    /// `PlatformDriver::set_sleep_state`. `secure_on` is required with `net::WOL_MAGIC_SECURE`.
+    fn set_wake_on_lan(&mut self, modes: u32, secure_on: Option<[u8; 6]>) -> Result<(), Error> {
+        let _ = (modes, secure_on);
+        Err(Error::InvalidArgs)
+    }
+}
+
+/// NetIo is the frame-level send/recv surface shared by `NetClient` and software net devices.
+pub trait NetIo {
+    /// Send a frame, returning once the device has accepted it.
+    fn send_packet(&self, buf: &[u8]) -> Result<(), Error>;
+    /// Post a receive buffer. Its completion is reported by `peek_cqe` with `id` as user_data
+    /// and the frame length as the result.
+    ///
+    /// # Safety
+    ///
+    /// The device keeps a pointer to `buf` and fills it in later. `buf` must stay valid, and
+    /// must not be accessed through any other reference, until `peek_cqe` has returned its
+    /// completion or the device and all of its clones have been dropped.
+    unsafe fn submit_recv(&self, buf: &mut [u8], id: u64) -> Result<(), Error>;
+    fn peek_cqe(&self) -> Option<IoUringCqe>;
+    /// Take the completion of the receive buffer posted as `id`, if it has arrived. Other
+    /// completions stay queued for `peek_cqe` and `take_recv_cqe` callers.
+    fn take_recv_cqe(&self, id: u64) -> Option<IoUringCqe>;
+
+    /// Offload capabilities (`net::OFFLOAD_*`) of the device.
+    fn offload_caps(&self) -> u32 {
+        0
+    }
+
+    /// Send a frame with an offload request. Devices without offloads accept only an empty one.
+    fn send_packet_offload(&self, buf: &mut [u8], offload: TxOffload) -> Result<(), Error> {
+        if offload.flags != 0 {
+            return Err(Error::InvalidArgs);
+        }
+        self.send_packet(buf)
+    }
+
+    /// Send the first `len` bytes of `buf` and return the hardware transmit timestamp in PTP
+    /// clock nanoseconds. `buf` needs `net::TIMESTAMP_LEN` spare bytes past the frame.
+    /// Devices without hardware timestamping reject this.
+    fn send_packet_timestamped(&self, buf: &mut [u8], len: usize) -> Result<u64, Error> {
+        let _ = (buf, len);
+        Err(Error::InvalidArgs)
+    }
 }
 
 /// UartDriver provides serial communication.
+/// Optional controls default to `Error::InvalidArgs` for drivers that lack them.
 pub trait UartDriver {
     fn put_char(&mut self, c: u8);
     fn get_char(&mut self) -> Option<u8>;
     fn put_str(&mut self, s: &str);
     fn set_baud_rate(&mut self, baud: u32);
+
+    /// Get the current line configuration.
+    fn config(&self) -> Result<UartConfig, Error> {
+        Err(Error::InvalidArgs)
+    }
+
+    /// Apply a line configuration. Fails without changing anything if the hardware cannot
+    /// do one of the settings.
+    fn set_config(&mut self, config: &UartConfig) -> Result<(), Error> {
+        let _ = config;
+        Err(Error::InvalidArgs)
+    }
+
+    /// Get the modem control line levels (`uart::MODEM_*`).
+    fn modem_lines(&self) -> Result<u32, Error> {
+        Err(Error::InvalidArgs)
+    }
+
+    /// Drive the output lines in `mask` (`uart::MODEM_DTR`, `uart::MODEM_RTS`) to `levels`.
+    fn set_modem_lines(&mut self, mask: u32, levels: u32) -> Result<(), Error> {
+        let _ = (mask, levels);
+        Err(Error::InvalidArgs)
+    }
+
+    /// Transmit a break condition for `duration_ms`.
+    fn send_break(&mut self, duration_ms: u32) -> Result<(), Error> {
+        let _ = duration_ms;
+        Err(Error::InvalidArgs)
+    }
+
+    /// Hold (or release) a break condition on the TX line.
+    fn set_break(&mut self, enable: bool) -> Result<(), Error> {
+        let _ = enable;
+        Err(Error::InvalidArgs)
+    }
+
+    /// Byte and error counters since the driver started.
+    fn stats(&self) -> Result<UartStats, Error> {
+        Err(Error::InvalidArgs)
+    }
+
+    /// Choose which `uart::EVENT_*` events are reported.
+    fn set_event_mask(&mut self, mask: u32) -> Result<(), Error> {
+        let _ = mask;
+        Err(Error::InvalidArgs)
+    }
+
+    /// Report `uart::EVENT_RX_THRESHOLD` once `bytes` are buffered. Zero disables it.
+    fn set_rx_threshold(&mut self, bytes: u32) -> Result<(), Error> {
+        let _ = bytes;
+        Err(Error::InvalidArgs)
+    }
+
+    /// Get the RS-485 half-duplex configuration.
+    fn rs485_config(&self) -> Result<Rs485Config, Error> {
+        Err(Error::InvalidArgs)
+    }
+
+    /// Apply an RS-485 configuration. Fails if the hardware cannot do one of the settings.
+    fn set_rs485_config(&mut self, config: &Rs485Config) -> Result<(), Error> {
+        let _ = config;
+        Err(Error::InvalidArgs)
+    }
+
+    /// Send `address` with the 9th bit set. Requires RS-485 address mode.
+    fn send_address(&mut self, address: u8) -> Result<(), Error> {
+        let _ = address;
+        Err(Error::InvalidArgs)
+    }
+
+    fn set_dtr(&mut self, level: bool) -> Result<(), Error> {
+        self.set_modem_lines(MODEM_DTR, if level { MODEM_DTR } else { 0 })
+    }
+
+    fn set_rts(&mut self, level: bool) -> Result<(), Error> {
+        self.set_modem_lines(MODEM_RTS, if level { MODEM_RTS } else { 0 })
+    }
 }
 
 /// WifiDriver provides wireless network management.
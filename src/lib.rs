@@ -4,4 +4,7 @@ extern crate alloc;
 
 pub mod client;
 pub mod interface;
+pub mod net;
 pub mod protocol;
+pub mod sink;
+pub mod uart;
@@ -0,0 +1,426 @@
+//! Software learning bridge forwarding Ethernet frames between [`NetIo`] ports.
+//!
+//! The bridge learns source addresses per VLAN, ages them out after `aging_time` ticks, and
+//! floods frames to unknown, multicast or broadcast destinations. Each port has an access VLAN
+//! (`pvid`) for untagged traffic and a set of tagged VLANs it trunks.
+//!
+//! Ports receive into heap buffers, so they must be software devices such as
+//! [`VirtNet`](super::virt::VirtNet) or [`VlanInterface`](super::vlan::VlanInterface). A
+//! `NetClient` driver can only write into the client's shared memory.
+
+use crate::interface::NetIo;
+use crate::net::MAX_TAGGED_FRAME;
+use crate::net::packet::ethernet::{self, VLAN_TAG_LEN};
+use crate::net::packet::{EthernetFrame, VlanTag};
+use crate::net::recv::RxSlot;
+use crate::protocol::net::MacAddress;
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use glenda::error::Error;
+
+/// Default MAC table aging time in ticks (300 s with a 1 s tick).
+pub const DEFAULT_AGING_TIME: u64 = 300;
+pub const DEFAULT_VLAN: u16 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortConfig {
+    /// VLAN assigned to untagged ingress frames and sent untagged on egress; 0 drops untagged.
+    pub pvid: u16,
+    /// VLANs carried tagged on this port.
+    pub tagged: Vec<u16>,
+}
+
+impl Default for PortConfig {
+    fn default() -> Self {
+        Self { pvid: DEFAULT_VLAN, tagged: Vec::new() }
+    }
+}
+
+impl PortConfig {
+    fn is_member(&self, vid: u16) -> bool {
+        (self.pvid != 0 && self.pvid == vid) || self.tagged.contains(&vid)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MacEntry {
+    pub port: usize,
+    pub last_seen: u64,
+}
+
+struct Port<D: NetIo> {
+    dev: D,
+    config: PortConfig,
+    rx: RxSlot,
+    /// Last receive or transmit error, kept until `take_port_error`.
+    error: Option<Error>,
+}
+
+pub struct Bridge<D: NetIo> {
+    ports: Vec<Port<D>>,
+    table: BTreeMap<(u16, [u8; 6]), MacEntry>,
+    aging_time: u64,
+    max_entries: usize,
+    scratch: Vec<u8>,
+}
+
+impl<D: NetIo> Default for Bridge<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D: NetIo> Bridge<D> {
+    pub fn new() -> Self {
+        Self {
+            ports: Vec::new(),
+            table: BTreeMap::new(),
+            aging_time: DEFAULT_AGING_TIME,
+            max_entries: 4096,
+            scratch: Vec::new(),
+        }
+    }
+
+    pub fn set_aging_time(&mut self, ticks: u64) {
+        self.aging_time = ticks;
+    }
+
+    /// Cap the MAC table size; new addresses are not learned once it is full.
+    pub fn set_max_entries(&mut self, n: usize) {
+        self.max_entries = n;
+    }
+
+    /// Attach a port and return its index.
+    pub fn add_port(&mut self, dev: D, config: PortConfig) -> usize {
+        self.ports.push(Port { dev, config, rx: RxSlot::new(MAX_TAGGED_FRAME), error: None });
+        self.ports.len() - 1
+    }
+
+    pub fn port_count(&self) -> usize {
+        self.ports.len()
+    }
+
+    pub fn port(&self, index: usize) -> Option<&D> {
+        self.ports.get(index).map(|p| &p.dev)
+    }
+
+    pub fn set_port_config(&mut self, index: usize, config: PortConfig) -> Result<(), Error> {
+        let port = self.ports.get_mut(index).ok_or(Error::InvalidArgs)?;
+        port.config = config;
+        // Learned addresses may no longer be valid for the new membership.
+        self.table.retain(|_, e| e.port != index);
+        Ok(())
+    }
+
+    /// Take the last error seen on a port. A failing port does not stop the others from being
+    /// polled or forwarded to.
+    pub fn take_port_error(&mut self, index: usize) -> Option<Error> {
+        self.ports.get_mut(index)?.error.take()
+    }
+
+    pub fn lookup(&self, vid: u16, mac: MacAddress) -> Option<MacEntry> {
+        self.table.get(&(vid, mac.octets)).copied()
+    }
+
+    /// Iterate over learned entries as `(vlan, mac, entry)`.
+    pub fn mac_table(&self) -> impl Iterator<Item = (u16, MacAddress, MacEntry)> + '_ {
+        self.table.iter().map(|((vid, mac), e)| (*vid, MacAddress { octets: *mac }, *e))
+    }
+
+    pub fn flush_table(&mut self) {
+        self.table.clear();
+    }
+
+    /// Drop entries not refreshed within the aging time.
+    pub fn age(&mut self, now: u64) {
+        let aging = self.aging_time;
+        self.table.retain(|_, e| now.saturating_sub(e.last_seen) < aging);
+    }
+
+    /// Forward every frame currently pending on any port. `now` is the current tick, used for
+    /// learning and aging. Returns the number of frames processed. Port errors are recorded
+    /// for `take_port_error`; a port that failed to post its receive buffer is retried on the
+    /// next poll.
+    pub fn poll(&mut self, now: u64) -> usize {
+        self.age(now);
+        let mut processed = 0;
+        for i in 0..self.ports.len() {
+            loop {
+                let port = &mut self.ports[i];
+                let cqe = match port.rx.poll(&port.dev, i as u64) {
+                    Ok(Some(cqe)) => cqe,
+                    Ok(None) => break,
+                    Err(e) => {
+                        port.error = Some(e);
+                        break;
+                    }
+                };
+                self.scratch.clear();
+                self.scratch.extend_from_slice(port.rx.frame(&cqe));
+                self.forward(i, now);
+                processed += 1;
+            }
+        }
+        processed
+    }
+
+    /// Forward the frame in `scratch`, received on port `ingress`.
+    fn forward(&mut self, ingress: usize, now: u64) {
+        let (src, dst, tag) = {
+            let Ok(frame) = EthernetFrame::new_checked(&self.scratch[..]) else {
+                return;
+            };
+            let cfg = &self.ports[ingress].config;
+            // Priority-tagged frames keep their PCP and DEI on the port VLAN.
+            let tag = match frame.vlan() {
+                Some(tag) if tag.vid != 0 => tag,
+                Some(tag) if cfg.pvid != 0 => VlanTag { vid: cfg.pvid, ..tag },
+                None if cfg.pvid != 0 => VlanTag::new(cfg.pvid),
+                _ => return,
+            };
+            if !cfg.is_member(tag.vid) {
+                return;
+            }
+            (frame.src(), frame.dst(), tag)
+        };
+        let vid = tag.vid;
+
+        // Normalize to untagged; the tag is re-added per egress port as needed.
+        let mut len = self.scratch.len();
+        if let Some((n, _)) = ethernet::strip_vlan_tag(&mut self.scratch, len) {
+            len = n;
+            self.scratch.truncate(len);
+        }
+
+        if src.is_unicast() {
+            let key = (vid, src.octets);
+            if self.table.contains_key(&key) || self.table.len() < self.max_entries {
+                self.table.insert(key, MacEntry { port: ingress, last_seen: now });
+            }
+        }
+
+        let target = if dst.is_multicast() { None } else { self.lookup(vid, dst) };
+        match target {
+            Some(entry) if entry.port == ingress => {}
+            Some(entry) => self.transmit(entry.port, tag),
+            None => {
+                for p in 0..self.ports.len() {
+                    if p != ingress {
+                        self.transmit(p, tag);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Send the untagged frame in `scratch` on `port` as a member of `tag.vid`, recording any
+    /// error on the port. Tagged egress carries the ingress priority bits.
+    fn transmit(&mut self, port: usize, tag: VlanTag) {
+        if let Err(e) = self.send_on(port, tag) {
+            self.ports[port].error = Some(e);
+        }
+    }
+
+    fn send_on(&self, port: usize, tag: VlanTag) -> Result<(), Error> {
+        let p = &self.ports[port];
+        if p.config.pvid != 0 && p.config.pvid == tag.vid {
+            return p.dev.send_packet(&self.scratch);
+        }
+        if !p.config.tagged.contains(&tag.vid) {
+            return Ok(());
+        }
+        let len = self.scratch.len();
+        let mut tagged = vec![0u8; len + VLAN_TAG_LEN];
+        tagged[..len].copy_from_slice(&self.scratch);
+        let n = ethernet::insert_vlan_tag(&mut tagged, len, tag)?;
+        p.dev.send_packet(&tagged[..n])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::virt::{LinkConfig, VirtNet};
+
+    const ETHERTYPE: u16 = 0x88b5;
+
+    fn mac(n: u8) -> MacAddress {
+        MacAddress::new([0x02, 0, 0, 0, 0, n])
+    }
+
+    fn frame(dst: MacAddress, src: MacAddress, vlan: Option<VlanTag>) -> Vec<u8> {
+        let mut buf = vec![0u8; 64];
+        ethernet::emit_header(&mut buf, dst, src, vlan, ETHERTYPE).unwrap();
+        buf
+    }
+
+    /// A station on the far end of a bridge port.
+    struct Host {
+        dev: VirtNet,
+        rx: RxSlot,
+    }
+
+    impl Host {
+        fn send(&self, dst: MacAddress, src: MacAddress, vlan: Option<VlanTag>) {
+            self.dev.send_packet(&frame(dst, src, vlan)).unwrap();
+        }
+
+        /// Take the next frame, returning its source and VLAN tag.
+        fn recv(&mut self) -> Option<(MacAddress, Option<VlanTag>)> {
+            let cqe = self.rx.poll(&self.dev, 0).unwrap()?;
+            let eth = EthernetFrame::new_checked(self.rx.frame(&cqe)).unwrap();
+            assert_eq!(eth.ethertype(), ETHERTYPE);
+            Some((eth.src(), eth.vlan()))
+        }
+    }
+
+    fn bridge(configs: &[PortConfig]) -> (Bridge<VirtNet>, Vec<Host>) {
+        let mut br = Bridge::new();
+        let mut hosts = Vec::new();
+        for (i, config) in configs.iter().enumerate() {
+            let (dev, port) =
+                VirtNet::pair(mac(i as u8 + 1), mac(i as u8 + 101), LinkConfig::default());
+            br.add_port(port, config.clone());
+            hosts.push(Host { dev, rx: RxSlot::new(MAX_TAGGED_FRAME) });
+        }
+        (br, hosts)
+    }
+
+    fn trunk(tagged: &[u16]) -> PortConfig {
+        PortConfig { pvid: 0, tagged: tagged.to_vec() }
+    }
+
+    #[test]
+    fn learns_source_addresses() {
+        let (mut br, mut hosts) = bridge(&vec![PortConfig::default(); 3]);
+        let (a, b) = (mac(1), mac(2));
+
+        hosts[0].send(b, a, None);
+        assert_eq!(br.poll(0), 1);
+        assert_eq!(br.lookup(DEFAULT_VLAN, a), Some(MacEntry { port: 0, last_seen: 0 }));
+        assert_eq!(hosts[1].recv(), Some((a, None)));
+        assert_eq!(hosts[2].recv(), Some((a, None)));
+
+        // Both ends are known now, so the reply and later frames go to one port only.
+        hosts[1].send(a, b, None);
+        assert_eq!(br.poll(1), 1);
+        hosts[0].send(b, a, None);
+        assert_eq!(br.poll(1), 1);
+        assert_eq!(hosts[0].recv(), Some((b, None)));
+        assert_eq!(hosts[1].recv(), Some((a, None)));
+        assert_eq!(hosts[2].recv(), None);
+        assert_eq!(br.lookup(DEFAULT_VLAN, a), Some(MacEntry { port: 0, last_seen: 1 }));
+        assert_eq!(br.mac_table().count(), 2);
+
+        // A station that moves is learned on its new port.
+        hosts[2].send(MacAddress::BROADCAST, b, None);
+        br.poll(2);
+        assert_eq!(br.lookup(DEFAULT_VLAN, b).unwrap().port, 2);
+
+        assert_eq!(hosts[0].recv(), Some((b, None)));
+        assert_eq!(hosts[1].recv(), Some((b, None)));
+
+        // Frames for a station on the ingress port are filtered.
+        hosts[0].send(a, mac(9), None);
+        br.poll(3);
+        assert_eq!(br.lookup(DEFAULT_VLAN, mac(9)).unwrap().port, 0);
+        assert!(hosts.iter_mut().all(|h| h.recv().is_none()));
+    }
+
+    #[test]
+    fn entries_age_out() {
+        let (mut br, mut hosts) = bridge(&vec![PortConfig::default(); 3]);
+        br.set_aging_time(10);
+        let (a, b) = (mac(1), mac(2));
+        hosts[1].send(a, b, None);
+        br.poll(0);
+        while hosts[0].recv().is_some() {}
+        while hosts[2].recv().is_some() {}
+
+        hosts[0].send(b, a, None);
+        br.poll(9);
+        assert_eq!(hosts[2].recv(), None);
+        assert!(br.lookup(DEFAULT_VLAN, b).is_some());
+
+        br.age(10);
+        assert_eq!(br.lookup(DEFAULT_VLAN, b), None);
+        assert!(br.lookup(DEFAULT_VLAN, a).is_some());
+        hosts[0].send(b, a, None);
+        br.poll(10);
+        assert_eq!(hosts[2].recv(), Some((a, None)));
+    }
+
+    #[test]
+    fn floods_group_and_unknown_destinations() {
+        let (mut br, mut hosts) = bridge(&vec![PortConfig::default(); 3]);
+        br.set_max_entries(1);
+        let (a, b) = (mac(1), mac(2));
+        hosts[1].send(MacAddress::BROADCAST, b, None);
+        br.poll(0);
+        assert_eq!(hosts[0].recv(), Some((b, None)));
+        assert_eq!(hosts[2].recv(), Some((b, None)));
+
+        let multicast = MacAddress::new([0x01, 0, 0x5e, 0, 0, 1]);
+        for dst in [MacAddress::BROADCAST, multicast, mac(9)] {
+            hosts[0].send(dst, a, None);
+            br.poll(1);
+            assert_eq!(hosts[0].recv(), None);
+            assert_eq!(hosts[1].recv(), Some((a, None)));
+            assert_eq!(hosts[2].recv(), Some((a, None)));
+        }
+
+        // The table is full, so `a` was never learned and frames for it are flooded too.
+        assert_eq!(br.lookup(DEFAULT_VLAN, a), None);
+        hosts[1].send(a, b, None);
+        br.poll(2);
+        assert_eq!(hosts[0].recv(), Some((b, None)));
+        assert_eq!(hosts[2].recv(), Some((b, None)));
+    }
+
+    #[test]
+    fn forwards_within_vlans() {
+        let configs = [
+            PortConfig { pvid: 10, tagged: Vec::new() },
+            PortConfig { pvid: 20, tagged: Vec::new() },
+            trunk(&[10, 20]),
+            trunk(&[20]),
+        ];
+        let (mut br, mut hosts) = bridge(&configs);
+        let src = mac(50);
+
+        // Access VLAN 10 is carried tagged on the first trunk only.
+        hosts[0].send(MacAddress::BROADCAST, src, None);
+        br.poll(0);
+        assert_eq!(hosts[1].recv(), None);
+        assert_eq!(hosts[2].recv(), Some((src, Some(VlanTag::new(10)))));
+        assert_eq!(hosts[3].recv(), None);
+        assert_eq!(br.lookup(10, src).unwrap().port, 0);
+        assert_eq!(br.lookup(20, src), None);
+
+        // Tagged VLAN 20 leaves access ports untagged and keeps its priority on trunks.
+        let tag = VlanTag { pcp: 5, dei: true, vid: 20 };
+        hosts[2].send(MacAddress::BROADCAST, src, Some(tag));
+        br.poll(1);
+        assert_eq!(hosts[0].recv(), None);
+        assert_eq!(hosts[1].recv(), Some((src, None)));
+        assert_eq!(hosts[3].recv(), Some((src, Some(tag))));
+
+        // Frames outside a port's membership, and untagged frames on a trunk, are dropped.
+        hosts[3].send(MacAddress::BROADCAST, src, Some(VlanTag::new(10)));
+        hosts[2].send(MacAddress::BROADCAST, src, Some(VlanTag::new(30)));
+        hosts[2].send(MacAddress::BROADCAST, src, None);
+        assert_eq!(br.poll(2), 3);
+        assert!(hosts.iter_mut().all(|h| h.recv().is_none()));
+    }
+
+    #[test]
+    fn priority_tags_join_the_port_vlan() {
+        let (mut br, mut hosts) = bridge(&[PortConfig::default(), trunk(&[DEFAULT_VLAN])]);
+        let tag = VlanTag { pcp: 3, dei: false, vid: 0 };
+        hosts[0].send(MacAddress::BROADCAST, mac(1), Some(tag));
+        br.poll(0);
+        let expected = VlanTag { vid: DEFAULT_VLAN, ..tag };
+        assert_eq!(hosts[1].recv(), Some((mac(1), Some(expected))));
+    }
+}
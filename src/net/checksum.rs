@@ -0,0 +1,198 @@
+//! Internet checksum helpers (RFC 1071) and software fallbacks for checksum offload.
+//!
+//! The frame-level helpers operate on raw Ethernet II frames, optionally 802.1Q/802.1ad tagged,
+//! carrying IPv4 or IPv6 with TCP, UDP or ICMP payloads.
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+pub const ETHERTYPE_VLAN: u16 = 0x8100;
+pub const ETHERTYPE_IPV6: u16 = 0x86DD;
+pub const ETHERTYPE_QINQ: u16 = 0x88A8;
+
+pub const IPPROTO_ICMP: u8 = 1;
+pub const IPPROTO_TCP: u8 = 6;
+pub const IPPROTO_UDP: u8 = 17;
+pub const IPPROTO_ICMPV6: u8 = 58;
+
+/// Add `data` to a running one's complement sum. Odd lengths are padded with a zero byte.
+pub fn sum(data: &[u8], mut acc: u32) -> u32 {
+    let mut chunks = data.chunks_exact(2);
+    for c in &mut chunks {
+        acc = acc.wrapping_add(u16::from_be_bytes([c[0], c[1]]) as u32);
+    }
+    if let [last] = chunks.remainder() {
+        acc = acc.wrapping_add((*last as u32) << 8);
+    }
+    acc
+}
+
+/// Fold a running sum into 16 bits and return its complement.
+pub fn finish(mut acc: u32) -> u16 {
+    while acc >> 16 != 0 {
+        acc = (acc & 0xffff) + (acc >> 16);
+    }
+    !(acc as u16)
+}
+
+/// Compute the Internet checksum of `data`.
+pub fn checksum(data: &[u8]) -> u16 {
+    finish(sum(data, 0))
+}
+
+/// Running sum of the IPv4 pseudo header for an upper-layer segment of `len` bytes.
+pub fn pseudo_header_v4(src: &[u8; 4], dst: &[u8; 4], proto: u8, len: u16) -> u32 {
+    let acc = sum(dst, sum(src, 0));
+    acc + proto as u32 + len as u32
+}
+
+/// Running sum of the IPv6 pseudo header for an upper-layer segment of `len` bytes.
+pub fn pseudo_header_v6(src: &[u8; 16], dst: &[u8; 16], next_header: u8, len: u32) -> u32 {
+    let acc = sum(dst, sum(src, 0));
+    acc.wrapping_add(len >> 16)
+        .wrapping_add(len & 0xffff)
+        .wrapping_add(next_header as u32)
+}
+
+/// Location of the L3/L4 headers inside a frame.
+struct Layout {
+    ip_off: usize,
+    ipv4: bool,
+    l4_off: usize,
+    l4_len: usize,
+    proto: u8,
+    pseudo: u32,
+}
+
+fn be16(buf: &[u8], off: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*buf.get(off)?, *buf.get(off + 1)?]))
+}
+
+/// Offset and EtherType of the L3 header, skipping any VLAN tags.
+pub fn l3_offset(frame: &[u8]) -> Option<(usize, u16)> {
+    let mut off = 12;
+    loop {
+        let ethertype = be16(frame, off)?;
+        if ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ {
+            off += 4;
+            continue;
+        }
+        return Some((off + 2, ethertype));
+    }
+}
+
+fn layout(frame: &[u8]) -> Option<Layout> {
+    let (ip_off, ethertype) = l3_offset(frame)?;
+    match ethertype {
+        ETHERTYPE_IPV4 => {
+            let ihl = ((*frame.get(ip_off)? & 0xf) as usize) * 4;
+            let total = be16(frame, ip_off + 2)? as usize;
+            if ihl < 20 || total < ihl || ip_off + total > frame.len() {
+                return None;
+            }
+            let src: [u8; 4] = frame[ip_off + 12..ip_off + 16].try_into().ok()?;
+            let dst: [u8; 4] = frame[ip_off + 16..ip_off + 20].try_into().ok()?;
+            let proto = frame[ip_off + 9];
+            // Fragments after the first carry no L4 header; the first one lacks the full payload.
+            let frag = be16(frame, ip_off + 6)?;
+            let l4_len = if frag & 0x3fff != 0 { 0 } else { total - ihl };
+            Some(Layout {
+                ip_off,
+                ipv4: true,
+                l4_off: ip_off + ihl,
+                l4_len,
+                proto,
+                pseudo: pseudo_header_v4(&src, &dst, proto, l4_len as u16),
+            })
+        }
+        ETHERTYPE_IPV6 => {
+            let payload = be16(frame, ip_off + 4)? as usize;
+            if ip_off + 40 + payload > frame.len() {
+                return None;
+            }
+            let src: [u8; 16] = frame[ip_off + 8..ip_off + 24].try_into().ok()?;
+            let dst: [u8; 16] = frame[ip_off + 24..ip_off + 40].try_into().ok()?;
+            let proto = frame[ip_off + 6];
+            Some(Layout {
+                ip_off,
+                ipv4: false,
+                l4_off: ip_off + 40,
+                l4_len: payload,
+                proto,
+                pseudo: pseudo_header_v6(&src, &dst, proto, payload as u32),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Offset of the checksum field within an L4 header, and whether it covers the pseudo header.
+fn l4_csum_field(proto: u8) -> Option<(usize, bool)> {
+    match proto {
+        IPPROTO_TCP => Some((16, true)),
+        IPPROTO_UDP => Some((6, true)),
+        IPPROTO_ICMP => Some((2, false)),
+        IPPROTO_ICMPV6 => Some((2, true)),
+        _ => None,
+    }
+}
+
+/// Compute the IPv4 header checksum and/or the TCP/UDP/ICMP checksum of `frame` in place.
+/// Returns false if the frame could not be parsed; unknown protocols are left untouched.
+pub fn fill_checksums(frame: &mut [u8], l3: bool, l4: bool) -> bool {
+    let Some(layout) = layout(frame) else {
+        return false;
+    };
+
+    if l3 && layout.ipv4 {
+        let ihl = layout.l4_off - layout.ip_off;
+        let hdr = &mut frame[layout.ip_off..layout.l4_off];
+        hdr[10..12].fill(0);
+        let csum = checksum(&hdr[..ihl]);
+        hdr[10..12].copy_from_slice(&csum.to_be_bytes());
+    }
+
+    if l4 && layout.l4_len > 0 {
+        let Some((field, pseudo)) = l4_csum_field(layout.proto) else {
+            return true;
+        };
+        let seg = &mut frame[layout.l4_off..layout.l4_off + layout.l4_len];
+        if seg.len() < field + 2 {
+            return false;
+        }
+        seg[field..field + 2].fill(0);
+        let mut csum = finish(sum(seg, if pseudo { layout.pseudo } else { 0 }));
+        if csum == 0 && layout.proto == IPPROTO_UDP {
+            // An all-zero UDP checksum means "not computed".
+            csum = 0xffff;
+        }
+        seg[field..field + 2].copy_from_slice(&csum.to_be_bytes());
+    }
+    true
+}
+
+/// Verify the IPv4 header and TCP/UDP/ICMP checksums of a received frame.
+/// Frames that are not IP, or carry protocols without a known checksum, are accepted.
+pub fn verify_checksums(frame: &[u8]) -> bool {
+    let Some(layout) = layout(frame) else {
+        return true;
+    };
+
+    if layout.ipv4 && checksum(&frame[layout.ip_off..layout.l4_off]) != 0 {
+        return false;
+    }
+
+    if layout.l4_len == 0 {
+        return true;
+    }
+    let Some((field, pseudo)) = l4_csum_field(layout.proto) else {
+        return true;
+    };
+    let seg = &frame[layout.l4_off..layout.l4_off + layout.l4_len];
+    if seg.len() < field + 2 {
+        return false;
+    }
+    if layout.ipv4 && layout.proto == IPPROTO_UDP && seg[6] == 0 && seg[7] == 0 {
+        return true;
+    }
+    finish(sum(seg, if pseudo { layout.pseudo } else { 0 })) == 0
+}
@@ -0,0 +1,728 @@
+//! Minimal DHCPv4 client (RFC 2131) over a [`NetIo`] device.
+//!
+//! [`DhcpClient`] runs the DISCOVER/OFFER/REQUEST/ACK exchange, renews the lease with its
+//! server at T1, rebinds with any server at T2 and drops it when it expires. Changes are
+//! reported through a [`LeaseCallback`]. Drive it by calling `poll` with the current time in
+//! seconds; it does not need an IP stack, building and parsing the UDP frames itself.
+//!
+//! Replies are received into a heap buffer, so the device must be a software one (for example
+//! a [`VlanInterface`](super::vlan::VlanInterface)) rather than a `NetClient`, whose driver
+//! only writes into the client's shared memory.
+
+use crate::interface::NetIo;
+use crate::net::MAX_TAGGED_FRAME;
+use crate::net::checksum::{ETHERTYPE_IPV4, IPPROTO_UDP};
+use crate::net::packet::{EthernetFrame, Ipv4Packet, UdpDatagram, ethernet, udp};
+use crate::net::recv::RxSlot;
+use crate::protocol::net::MacAddress;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::net::Ipv4Addr;
+use glenda::error::Error;
+
+pub const SERVER_PORT: u16 = 67;
+pub const CLIENT_PORT: u16 = 68;
+
+pub const MSG_DISCOVER: u8 = 1;
+pub const MSG_OFFER: u8 = 2;
+pub const MSG_REQUEST: u8 = 3;
+pub const MSG_DECLINE: u8 = 4;
+pub const MSG_ACK: u8 = 5;
+pub const MSG_NAK: u8 = 6;
+pub const MSG_RELEASE: u8 = 7;
+
+const OPT_PAD: u8 = 0;
+const OPT_SUBNET_MASK: u8 = 1;
+const OPT_ROUTER: u8 = 3;
+const OPT_DNS: u8 = 6;
+const OPT_REQUESTED_IP: u8 = 50;
+const OPT_LEASE_TIME: u8 = 51;
+const OPT_MSG_TYPE: u8 = 53;
+const OPT_SERVER_ID: u8 = 54;
+const OPT_PARAM_LIST: u8 = 55;
+const OPT_RENEWAL_TIME: u8 = 58;
+const OPT_REBINDING_TIME: u8 = 59;
+const OPT_CLIENT_ID: u8 = 61;
+const OPT_END: u8 = 255;
+
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+const BOOTP_LEN: usize = 236;
+const FLAG_BROADCAST: u16 = 0x8000;
+/// Ethernet, IPv4 and UDP headers plus the fixed BOOTP fields, cookie and options.
+const TX_FRAME_SIZE: usize = 14 + 20 + 8 + BOOTP_LEN + 4 + 64;
+
+const INITIAL_TIMEOUT: u64 = 4;
+const MAX_TIMEOUT: u64 = 64;
+/// Minimum retransmission interval while renewing or rebinding (RFC 2131 4.4.5).
+const MIN_RENEW_RETRY: u64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DhcpState {
+    Init,
+    Selecting,
+    Requesting,
+    Bound,
+    Renewing,
+    Rebinding,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    pub address: Ipv4Addr,
+    pub netmask: Option<Ipv4Addr>,
+    pub gateway: Option<Ipv4Addr>,
+    pub dns: Vec<Ipv4Addr>,
+    pub server: Ipv4Addr,
+    /// Lease duration in seconds.
+    pub lease_time: u32,
+    /// Seconds after `acquired_at` at which to renew (T1) and rebind (T2).
+    pub renew_time: u32,
+    pub rebind_time: u32,
+    /// Time at which the lease was granted or last extended.
+    pub acquired_at: u64,
+}
+
+impl Lease {
+    fn renew_at(&self) -> u64 {
+        self.acquired_at + self.renew_time as u64
+    }
+
+    fn rebind_at(&self) -> u64 {
+        self.acquired_at + self.rebind_time as u64
+    }
+
+    fn expires_at(&self) -> u64 {
+        self.acquired_at + self.lease_time as u64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DhcpEvent {
+    /// A lease was acquired or extended.
+    Bound(Lease),
+    /// The lease expired, was refused on renewal or released; the address must be removed.
+    Deconfigured,
+}
+
+/// Callback invoked on every lease change.
+pub type LeaseCallback = Arc<dyn Fn(&DhcpEvent) + Send + Sync>;
+
+/// Fields of a server reply the client acts on.
+struct Reply {
+    msg_type: u8,
+    yiaddr: Ipv4Addr,
+    server: Option<Ipv4Addr>,
+    netmask: Option<Ipv4Addr>,
+    gateway: Option<Ipv4Addr>,
+    dns: Vec<Ipv4Addr>,
+    lease_time: Option<u32>,
+    renew_time: Option<u32>,
+    rebind_time: Option<u32>,
+    src_mac: MacAddress,
+}
+
+pub struct DhcpClient<D: NetIo> {
+    dev: D,
+    mac: MacAddress,
+    state: DhcpState,
+    xid: u32,
+    /// Generator state for transaction IDs.
+    xid_state: u64,
+    lease: Option<Lease>,
+    /// Address and server offered during selection.
+    offer: Option<(Ipv4Addr, Ipv4Addr)>,
+    server_mac: MacAddress,
+    /// When the current exchange started, for the BOOTP `secs` field.
+    started: u64,
+    retry_at: u64,
+    timeout: u64,
+    callback: Option<LeaseCallback>,
+    rx: RxSlot,
+}
+
+impl<D: NetIo> DhcpClient<D> {
+    pub fn new(dev: D, mac: MacAddress) -> Self {
+        let m = mac.octets;
+        Self {
+            dev,
+            mac,
+            state: DhcpState::Init,
+            xid: 0,
+            xid_state: u64::from_be_bytes([0, 0, m[0], m[1], m[2], m[3], m[4], m[5]]),
+            lease: None,
+            offer: None,
+            server_mac: MacAddress::BROADCAST,
+            started: 0,
+            retry_at: 0,
+            timeout: INITIAL_TIMEOUT,
+            callback: None,
+            rx: RxSlot::new(MAX_TAGGED_FRAME),
+        }
+    }
+
+    /// Register a callback run on every lease change.
+    pub fn on_lease_change(&mut self, cb: LeaseCallback) {
+        self.callback = Some(cb);
+    }
+
+    /// Mix in entropy, e.g. from an `RngDriver`, so transaction IDs differ across restarts
+    /// even when the clock does not.
+    pub fn add_entropy(&mut self, seed: u64) {
+        self.xid_state ^= seed;
+    }
+
+    pub fn state(&self) -> DhcpState {
+        self.state
+    }
+
+    pub fn lease(&self) -> Option<&Lease> {
+        self.lease.as_ref()
+    }
+
+    pub fn device(&self) -> &D {
+        &self.dev
+    }
+
+    /// Process received replies and run timers. `now` is the current time in seconds.
+    pub fn poll(&mut self, now: u64) -> Result<(), Error> {
+        while let Some(cqe) = self.rx.poll(&self.dev, 0)? {
+            if let Some(reply) = self.parse(self.rx.frame(&cqe)) {
+                self.handle(reply, now)?;
+            }
+        }
+        self.run_timers(now)
+    }
+
+    /// Give up the current lease, telling the server. The next `poll` starts over.
+    pub fn release(&mut self) -> Result<(), Error> {
+        if let Some(lease) = &self.lease {
+            self.send(MSG_RELEASE, lease.address, Some(lease.server), None, 0, false)?;
+            self.lease = None;
+            self.report(DhcpEvent::Deconfigured);
+        }
+        self.state = DhcpState::Init;
+        self.retry_at = 0;
+        Ok(())
+    }
+
+    fn run_timers(&mut self, now: u64) -> Result<(), Error> {
+        match self.state {
+            DhcpState::Init => self.discover(now),
+            DhcpState::Selecting if now >= self.retry_at => self.discover(now),
+            DhcpState::Requesting if now >= self.retry_at => {
+                // Give up on the offer after the backoff runs out and start over.
+                if self.timeout >= MAX_TIMEOUT {
+                    return self.discover(now);
+                }
+                self.request(now)
+            }
+            DhcpState::Bound | DhcpState::Renewing | DhcpState::Rebinding => {
+                let Some(lease) = &self.lease else {
+                    return self.discover(now);
+                };
+                let (renew_at, rebind_at, expires_at) =
+                    (lease.renew_at(), lease.rebind_at(), lease.expires_at());
+                if now >= expires_at {
+                    self.lease = None;
+                    self.report(DhcpEvent::Deconfigured);
+                    return self.discover(now);
+                }
+                let next = if now >= rebind_at {
+                    DhcpState::Rebinding
+                } else if now >= renew_at {
+                    DhcpState::Renewing
+                } else {
+                    DhcpState::Bound
+                };
+                if next != self.state {
+                    self.state = next;
+                    self.started = now;
+                    self.retry_at = now;
+                }
+                if self.state != DhcpState::Bound && now >= self.retry_at {
+                    let deadline =
+                        if self.state == DhcpState::Renewing { rebind_at } else { expires_at };
+                    self.retry_at = now + core::cmp::max((deadline - now) / 2, MIN_RENEW_RETRY);
+                    self.request_extend(now)?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn discover(&mut self, now: u64) -> Result<(), Error> {
+        if self.state != DhcpState::Selecting {
+            self.xid = self.next_xid(now);
+            self.started = now;
+            self.timeout = INITIAL_TIMEOUT;
+        } else {
+            self.timeout = core::cmp::min(self.timeout * 2, MAX_TIMEOUT);
+        }
+        self.state = DhcpState::Selecting;
+        self.offer = None;
+        self.retry_at = now + self.timeout;
+        self.send(MSG_DISCOVER, Ipv4Addr::UNSPECIFIED, None, None, now - self.started, true)
+    }
+
+    /// Draw a transaction ID for a new exchange, mixing the time into the MAC-seeded state.
+    fn next_xid(&mut self, now: u64) -> u32 {
+        // splitmix64
+        self.xid_state = self.xid_state.wrapping_add(0x9e37_79b9_7f4a_7c15) ^ now;
+        let mut z = self.xid_state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        (z ^ (z >> 31)) as u32
+    }
+
+    fn request(&mut self, now: u64) -> Result<(), Error> {
+        let Some((address, server)) = self.offer else {
+            return self.discover(now);
+        };
+        if self.state == DhcpState::Requesting {
+            self.timeout = core::cmp::min(self.timeout * 2, MAX_TIMEOUT);
+        } else {
+            self.state = DhcpState::Requesting;
+            self.timeout = INITIAL_TIMEOUT;
+        }
+        self.retry_at = now + self.timeout;
+        let secs = now - self.started;
+        self.send(MSG_REQUEST, Ipv4Addr::UNSPECIFIED, Some(server), Some(address), secs, true)
+    }
+
+    /// Renew (unicast to the leasing server) or rebind (broadcast) the current lease.
+    fn request_extend(&mut self, now: u64) -> Result<(), Error> {
+        let Some(lease) = &self.lease else {
+            return Ok(());
+        };
+        let ciaddr = lease.address;
+        let secs = now - self.started;
+        self.send(MSG_REQUEST, ciaddr, None, None, secs, self.state == DhcpState::Rebinding)
+    }
+
+    fn handle(&mut self, reply: Reply, now: u64) -> Result<(), Error> {
+        match (self.state, reply.msg_type) {
+            // Only the server whose offer was taken may answer the REQUEST (RFC 2131 4.4.1).
+            (DhcpState::Requesting, MSG_ACK | MSG_NAK)
+                if reply.server != self.offer.map(|(_, server)| server) =>
+            {
+                Ok(())
+            }
+            (DhcpState::Selecting, MSG_OFFER) => {
+                let Some(server) = reply.server else {
+                    return Ok(());
+                };
+                self.offer = Some((reply.yiaddr, server));
+                self.request(now)
+            }
+            (DhcpState::Requesting | DhcpState::Renewing | DhcpState::Rebinding, MSG_ACK) => {
+                let Some(lease_time) = reply.lease_time else {
+                    return Ok(());
+                };
+                let server = reply.server.or(self.lease.as_ref().map(|l| l.server));
+                let lease = Lease {
+                    address: reply.yiaddr,
+                    netmask: reply.netmask,
+                    gateway: reply.gateway,
+                    dns: reply.dns,
+                    server: server.unwrap_or(Ipv4Addr::UNSPECIFIED),
+                    lease_time,
+                    renew_time: reply.renew_time.unwrap_or(lease_time / 2),
+                    rebind_time: reply.rebind_time.unwrap_or(lease_time / 8 * 7),
+                    acquired_at: now,
+                };
+                self.server_mac = reply.src_mac;
+                self.state = DhcpState::Bound;
+                self.offer = None;
+                self.lease = Some(lease.clone());
+                self.report(DhcpEvent::Bound(lease));
+                Ok(())
+            }
+            (DhcpState::Requesting | DhcpState::Renewing | DhcpState::Rebinding, MSG_NAK) => {
+                if self.lease.take().is_some() {
+                    self.report(DhcpEvent::Deconfigured);
+                }
+                self.state = DhcpState::Init;
+                self.discover(now)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn report(&self, event: DhcpEvent) {
+        if let Some(cb) = &self.callback {
+            cb(&event);
+        }
+    }
+
+    fn send(
+        &self,
+        msg_type: u8,
+        ciaddr: Ipv4Addr,
+        server: Option<Ipv4Addr>,
+        requested: Option<Ipv4Addr>,
+        secs: u64,
+        broadcast: bool,
+    ) -> Result<(), Error> {
+        let mut opts: Vec<u8> = Vec::with_capacity(64);
+        opts.extend_from_slice(&[OPT_MSG_TYPE, 1, msg_type]);
+        opts.extend_from_slice(&[OPT_CLIENT_ID, 7, 1]);
+        opts.extend_from_slice(&self.mac.octets);
+        if let Some(ip) = requested {
+            opts.extend_from_slice(&[OPT_REQUESTED_IP, 4]);
+            opts.extend_from_slice(&ip.octets());
+        }
+        if let Some(ip) = server {
+            opts.extend_from_slice(&[OPT_SERVER_ID, 4]);
+            opts.extend_from_slice(&ip.octets());
+        }
+        if msg_type != MSG_RELEASE {
+            opts.extend_from_slice(&[OPT_PARAM_LIST, 6, OPT_SUBNET_MASK, OPT_ROUTER, OPT_DNS]);
+            opts.extend_from_slice(&[OPT_LEASE_TIME, OPT_RENEWAL_TIME, OPT_REBINDING_TIME]);
+        }
+        opts.push(OPT_END);
+
+        let (dst_mac, dst_ip) = if broadcast {
+            (MacAddress::BROADCAST, Ipv4Addr::BROADCAST)
+        } else {
+            (self.server_mac, self.lease.as_ref().map_or(Ipv4Addr::BROADCAST, |l| l.server))
+        };
+
+        let mut buf = [0u8; TX_FRAME_SIZE];
+        let off = ethernet::emit_header(&mut buf, dst_mac, self.mac, None, ETHERTYPE_IPV4)?;
+        let dhcp_len = BOOTP_LEN + MAGIC_COOKIE.len() + opts.len();
+        let udp_len = udp::HEADER_LEN + dhcp_len;
+
+        let mut ip = Ipv4Packet::new_unchecked(&mut buf[off..]);
+        ip.init(IPPROTO_UDP, ciaddr, dst_ip, udp_len);
+        ip.fill_checksum();
+        let pseudo = ip.pseudo_header();
+        let ip_len = ip.header_len() + udp_len;
+
+        let mut datagram = UdpDatagram::new_unchecked(ip.payload_mut());
+        datagram.init(CLIENT_PORT, SERVER_PORT, dhcp_len);
+        let msg = datagram.payload_mut();
+        msg[0] = 1; // BOOTREQUEST
+        msg[1] = 1; // Ethernet
+        msg[2] = 6;
+        msg[4..8].copy_from_slice(&self.xid.to_be_bytes());
+        msg[8..10].copy_from_slice(&(core::cmp::min(secs, u16::MAX as u64) as u16).to_be_bytes());
+        if broadcast && ciaddr.is_unspecified() {
+            msg[10..12].copy_from_slice(&FLAG_BROADCAST.to_be_bytes());
+        }
+        msg[12..16].copy_from_slice(&ciaddr.octets());
+        msg[28..34].copy_from_slice(&self.mac.octets);
+        msg[BOOTP_LEN..BOOTP_LEN + 4].copy_from_slice(&MAGIC_COOKIE);
+        msg[BOOTP_LEN + 4..dhcp_len].copy_from_slice(&opts);
+        datagram.fill_checksum(pseudo);
+
+        self.dev.send_packet(&buf[..off + ip_len])
+    }
+
+    fn parse(&self, frame: &[u8]) -> Option<Reply> {
+        let eth = EthernetFrame::new_checked(frame).ok()?;
+        if eth.ethertype() != ETHERTYPE_IPV4 {
+            return None;
+        }
+        let ip = Ipv4Packet::new_checked(eth.payload()).ok()?;
+        if ip.protocol() != IPPROTO_UDP || !ip.verify_checksum() {
+            return None;
+        }
+        let datagram = UdpDatagram::new_checked(ip.payload()).ok()?;
+        if datagram.dst_port() != CLIENT_PORT || !datagram.verify_checksum(ip.pseudo_header()) {
+            return None;
+        }
+        let msg = datagram.payload();
+        if msg.len() < BOOTP_LEN + 4
+            || msg[0] != 2
+            || msg[4..8] != self.xid.to_be_bytes()
+            || msg[28..34] != self.mac.octets
+            || msg[BOOTP_LEN..BOOTP_LEN + 4] != MAGIC_COOKIE
+        {
+            return None;
+        }
+
+        let mut reply = Reply {
+            msg_type: 0,
+            yiaddr: Ipv4Addr::new(msg[16], msg[17], msg[18], msg[19]),
+            server: None,
+            netmask: None,
+            gateway: None,
+            dns: Vec::new(),
+            lease_time: None,
+            renew_time: None,
+            rebind_time: None,
+            src_mac: eth.src(),
+        };
+        let mut opts = &msg[BOOTP_LEN + 4..];
+        while let [code, rest @ ..] = opts {
+            match *code {
+                OPT_PAD => {
+                    opts = rest;
+                    continue;
+                }
+                OPT_END => break,
+                _ => {}
+            }
+            let [len, rest @ ..] = rest else {
+                break;
+            };
+            let data = rest.get(..*len as usize)?;
+            match *code {
+                OPT_MSG_TYPE => reply.msg_type = *data.first()?,
+                OPT_SERVER_ID => reply.server = addr(data),
+                OPT_SUBNET_MASK => reply.netmask = addr(data),
+                OPT_ROUTER => reply.gateway = addr(data),
+                OPT_DNS => reply.dns = data.chunks_exact(4).filter_map(addr).collect(),
+                OPT_LEASE_TIME => reply.lease_time = secs(data),
+                OPT_RENEWAL_TIME => reply.renew_time = secs(data),
+                OPT_REBINDING_TIME => reply.rebind_time = secs(data),
+                _ => {}
+            }
+            opts = &rest[*len as usize..];
+        }
+        if reply.msg_type == 0 { None } else { Some(reply) }
+    }
+}
+
+fn addr(data: &[u8]) -> Option<Ipv4Addr> {
+    let b: [u8; 4] = data.get(..4)?.try_into().ok()?;
+    Some(Ipv4Addr::from(b))
+}
+
+fn secs(data: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(..4)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::virt::{LinkConfig, VirtNet};
+    use alloc::vec;
+    use spin::Mutex;
+
+    const CLIENT_MAC: MacAddress = MacAddress::new([0x02, 0, 0, 0, 0, 0x10]);
+    const SERVER_MAC: MacAddress = MacAddress::new([0x02, 0, 0, 0, 0, 0x01]);
+    const OTHER_MAC: MacAddress = MacAddress::new([0x02, 0, 0, 0, 0, 0x02]);
+    const SERVER_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+    const OTHER_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+    const OFFERED: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 50);
+    const LEASE_TIME: u32 = 100;
+    const T1: u32 = 50;
+    const T2: u32 = 80;
+
+    /// The fields of a client message the server checks.
+    struct Request {
+        dst_mac: MacAddress,
+        dst_ip: Ipv4Addr,
+        msg_type: u8,
+        xid: [u8; 4],
+        ciaddr: Ipv4Addr,
+        broadcast: bool,
+        requested: Option<Ipv4Addr>,
+        server: Option<Ipv4Addr>,
+    }
+
+    fn option(opts: &[u8], code: u8) -> Option<&[u8]> {
+        let mut i = 0;
+        while i < opts.len() && opts[i] != OPT_END {
+            if opts[i] == OPT_PAD {
+                i += 1;
+                continue;
+            }
+            let len = opts[i + 1] as usize;
+            if opts[i] == code {
+                return Some(&opts[i + 2..i + 2 + len]);
+            }
+            i += 2 + len;
+        }
+        None
+    }
+
+    /// A scripted DHCP server on the other end of a `VirtNet` pair.
+    struct Server {
+        dev: VirtNet,
+        rx: RxSlot,
+    }
+
+    impl Server {
+        fn recv(&mut self) -> Option<Request> {
+            let cqe = self.rx.poll(&self.dev, 0).unwrap()?;
+            let eth = EthernetFrame::new_checked(self.rx.frame(&cqe)).unwrap();
+            let ip = Ipv4Packet::new_checked(eth.payload()).unwrap();
+            assert!(ip.verify_checksum());
+            let datagram = UdpDatagram::new_checked(ip.payload()).unwrap();
+            assert!(datagram.verify_checksum(ip.pseudo_header()));
+            assert_eq!((datagram.src_port(), datagram.dst_port()), (CLIENT_PORT, SERVER_PORT));
+            let msg = datagram.payload();
+            assert_eq!(&msg[28..34], &CLIENT_MAC.octets);
+            assert_eq!(msg[BOOTP_LEN..BOOTP_LEN + 4], MAGIC_COOKIE);
+            let opts = &msg[BOOTP_LEN + 4..];
+            Some(Request {
+                dst_mac: eth.dst(),
+                dst_ip: ip.dst(),
+                msg_type: option(opts, OPT_MSG_TYPE).unwrap()[0],
+                xid: msg[4..8].try_into().unwrap(),
+                ciaddr: addr(&msg[12..16]).unwrap(),
+                broadcast: msg[10..12] == FLAG_BROADCAST.to_be_bytes(),
+                requested: option(opts, OPT_REQUESTED_IP).and_then(addr),
+                server: option(opts, OPT_SERVER_ID).and_then(addr),
+            })
+        }
+
+        fn reply(&self, req: &Request, msg_type: u8, server: Ipv4Addr, server_mac: MacAddress) {
+            let mut opts = vec![OPT_MSG_TYPE, 1, msg_type, OPT_SERVER_ID, 4];
+            opts.extend_from_slice(&server.octets());
+            if msg_type != MSG_NAK {
+                for (code, value) in
+                    [(OPT_LEASE_TIME, LEASE_TIME), (OPT_RENEWAL_TIME, T1), (OPT_REBINDING_TIME, T2)]
+                {
+                    opts.extend_from_slice(&[code, 4]);
+                    opts.extend_from_slice(&value.to_be_bytes());
+                }
+                opts.extend_from_slice(&[OPT_SUBNET_MASK, 4, 255, 255, 255, 0, OPT_ROUTER, 4]);
+                opts.extend_from_slice(&SERVER_IP.octets());
+                opts.extend_from_slice(&[OPT_DNS, 8, 8, 8, 8, 8, 1, 1, 1, 1]);
+            }
+            opts.push(OPT_END);
+
+            let dhcp_len = BOOTP_LEN + MAGIC_COOKIE.len() + opts.len();
+            let mut buf = vec![0u8; ethernet::HEADER_LEN + 20 + udp::HEADER_LEN + dhcp_len];
+            let off = ethernet::emit_header(&mut buf, CLIENT_MAC, server_mac, None, ETHERTYPE_IPV4)
+                .unwrap();
+            let mut ip = Ipv4Packet::new_unchecked(&mut buf[off..]);
+            ip.init(IPPROTO_UDP, server, Ipv4Addr::BROADCAST, udp::HEADER_LEN + dhcp_len);
+            ip.fill_checksum();
+            let pseudo = ip.pseudo_header();
+            let mut datagram = UdpDatagram::new_unchecked(ip.payload_mut());
+            datagram.init(SERVER_PORT, CLIENT_PORT, dhcp_len);
+            let msg = datagram.payload_mut();
+            msg[0] = 2; // BOOTREPLY
+            msg[1] = 1;
+            msg[2] = 6;
+            msg[4..8].copy_from_slice(&req.xid);
+            msg[16..20].copy_from_slice(&OFFERED.octets());
+            msg[28..34].copy_from_slice(&CLIENT_MAC.octets);
+            msg[BOOTP_LEN..BOOTP_LEN + 4].copy_from_slice(&MAGIC_COOKIE);
+            msg[BOOTP_LEN + 4..].copy_from_slice(&opts);
+            datagram.fill_checksum(pseudo);
+            self.dev.send_packet(&buf).unwrap();
+        }
+    }
+
+    fn setup() -> (DhcpClient<VirtNet>, Server, Arc<Mutex<Vec<DhcpEvent>>>) {
+        let (client_dev, server_dev) = VirtNet::pair(CLIENT_MAC, SERVER_MAC, LinkConfig::default());
+        let mut client = DhcpClient::new(client_dev, CLIENT_MAC);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        client.on_lease_change(Arc::new(move |e: &DhcpEvent| sink.lock().push(e.clone())));
+        let server = Server { dev: server_dev, rx: RxSlot::new(MAX_TAGGED_FRAME) };
+        (client, server, events)
+    }
+
+    /// Run DISCOVER/OFFER/REQUEST/ACK at time 0.
+    fn acquire(client: &mut DhcpClient<VirtNet>, server: &mut Server) {
+        client.poll(0).unwrap();
+        let discover = server.recv().unwrap();
+        assert_eq!(discover.msg_type, MSG_DISCOVER);
+        assert_eq!(discover.dst_mac, MacAddress::BROADCAST);
+        assert!(discover.broadcast);
+        server.reply(&discover, MSG_OFFER, SERVER_IP, SERVER_MAC);
+
+        client.poll(0).unwrap();
+        assert_eq!(client.state(), DhcpState::Requesting);
+        let request = server.recv().unwrap();
+        assert_eq!(request.msg_type, MSG_REQUEST);
+        assert_eq!(request.xid, discover.xid);
+        assert_eq!(request.requested, Some(OFFERED));
+        assert_eq!(request.server, Some(SERVER_IP));
+        assert_eq!(request.ciaddr, Ipv4Addr::UNSPECIFIED);
+
+        // An ACK from a server that was not selected is ignored.
+        server.reply(&request, MSG_ACK, OTHER_IP, OTHER_MAC);
+        client.poll(0).unwrap();
+        assert_eq!(client.state(), DhcpState::Requesting);
+
+        server.reply(&request, MSG_ACK, SERVER_IP, SERVER_MAC);
+        client.poll(0).unwrap();
+        assert_eq!(client.state(), DhcpState::Bound);
+    }
+
+    #[test]
+    fn acquire_renew_rebind() {
+        let (mut client, mut server, events) = setup();
+        acquire(&mut client, &mut server);
+        let lease = client.lease().unwrap().clone();
+        assert_eq!(lease.address, OFFERED);
+        assert_eq!(lease.server, SERVER_IP);
+        assert_eq!(lease.netmask, Some(Ipv4Addr::new(255, 255, 255, 0)));
+        assert_eq!(lease.gateway, Some(SERVER_IP));
+        assert_eq!(lease.dns, [Ipv4Addr::new(8, 8, 8, 8), Ipv4Addr::new(1, 1, 1, 1)]);
+        assert_eq!((lease.renew_time, lease.rebind_time), (T1, T2));
+
+        // T1: renew by unicast with the leasing server.
+        client.poll(T1 as u64 - 1).unwrap();
+        assert!(server.recv().is_none());
+        client.poll(T1 as u64).unwrap();
+        assert_eq!(client.state(), DhcpState::Renewing);
+        let renew = server.recv().unwrap();
+        assert_eq!(renew.msg_type, MSG_REQUEST);
+        assert_eq!((renew.dst_mac, renew.dst_ip), (SERVER_MAC, SERVER_IP));
+        assert_eq!(renew.ciaddr, OFFERED);
+        assert_eq!((renew.requested, renew.server), (None, None));
+        server.reply(&renew, MSG_ACK, SERVER_IP, SERVER_MAC);
+        client.poll(60).unwrap();
+        assert_eq!(client.state(), DhcpState::Bound);
+        assert_eq!(client.lease().unwrap().acquired_at, 60);
+
+        // The server goes quiet: renew at 60 + T1, then rebind by broadcast at 60 + T2.
+        client.poll(60 + T1 as u64).unwrap();
+        assert_eq!(client.state(), DhcpState::Renewing);
+        assert!(server.recv().is_some());
+        client.poll(60 + T2 as u64).unwrap();
+        assert_eq!(client.state(), DhcpState::Rebinding);
+        let rebind = server.recv().unwrap();
+        assert_eq!(rebind.msg_type, MSG_REQUEST);
+        assert_eq!((rebind.dst_mac, rebind.dst_ip), (MacAddress::BROADCAST, Ipv4Addr::BROADCAST));
+        assert_eq!(rebind.ciaddr, OFFERED);
+
+        // Any server may extend the lease while rebinding.
+        server.reply(&rebind, MSG_ACK, OTHER_IP, OTHER_MAC);
+        client.poll(60 + T2 as u64).unwrap();
+        assert_eq!(client.state(), DhcpState::Bound);
+        assert_eq!(client.lease().unwrap().server, OTHER_IP);
+
+        let events = events.lock();
+        assert_eq!(events.len(), 3);
+        assert!(events.iter().all(|e| matches!(e, DhcpEvent::Bound(_))));
+    }
+
+    #[test]
+    fn lease_expires() {
+        let (mut client, mut server, events) = setup();
+        acquire(&mut client, &mut server);
+        client.poll(T2 as u64).unwrap();
+        assert_eq!(client.state(), DhcpState::Rebinding);
+        client.poll(LEASE_TIME as u64).unwrap();
+        assert_eq!(client.state(), DhcpState::Selecting);
+        assert!(client.lease().is_none());
+        assert_eq!(events.lock().last(), Some(&DhcpEvent::Deconfigured));
+    }
+
+    #[test]
+    fn xid_changes_across_restarts() {
+        let (mut client, mut server, _) = setup();
+        client.poll(0).unwrap();
+        let first = server.recv().unwrap().xid;
+        client.release().unwrap();
+        client.poll(0).unwrap();
+        assert_ne!(server.recv().unwrap().xid, first);
+
+        let (mut a, mut server_a, _) = setup();
+        let (mut b, mut server_b, _) = setup();
+        b.add_entropy(0x1234_5678);
+        a.poll(7).unwrap();
+        b.poll(7).unwrap();
+        assert_ne!(server_a.recv().unwrap().xid, server_b.recv().unwrap().xid);
+    }
+}
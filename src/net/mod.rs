@@ -0,0 +1,19 @@
+//! Software networking components layered on top of the net driver protocol.
+
+pub mod bridge;
+pub mod checksum;
+pub mod dhcp;
+pub mod packet;
+pub mod pcap;
+pub mod ptp;
+pub(crate) mod recv;
+pub mod registry;
+pub mod virt;
+pub mod vlan;
+pub mod wol;
+
+/// Largest 802.1Q-tagged Ethernet frame at the standard 1500-byte MTU, FCS included.
+pub const MAX_TAGGED_FRAME: usize = 1522;
+
+#[cfg(feature = "smoltcp")]
+pub mod phy;
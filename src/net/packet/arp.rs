@@ -0,0 +1,131 @@
+//! ARP for IPv4 over Ethernet (RFC 826).
+
+use super::{get_u16, put_u16};
+use crate::net::checksum::ETHERTYPE_IPV4;
+use crate::protocol::net::MacAddress;
+use core::net::Ipv4Addr;
+use glenda::error::Error;
+
+pub const PACKET_LEN: usize = 28;
+
+pub const HTYPE_ETHERNET: u16 = 1;
+pub const OP_REQUEST: u16 = 1;
+pub const OP_REPLY: u16 = 2;
+
+#[derive(Debug, Clone)]
+pub struct ArpPacket<T> {
+    buf: T,
+}
+
+impl<T: AsRef<[u8]>> ArpPacket<T> {
+    pub const fn new_unchecked(buf: T) -> Self {
+        Self { buf }
+    }
+
+    /// Wrap `buf`, accepting only Ethernet/IPv4 ARP.
+    pub fn new_checked(buf: T) -> Result<Self, Error> {
+        let pkt = Self::new_unchecked(buf);
+        let data = pkt.buf.as_ref();
+        if data.len() < PACKET_LEN
+            || get_u16(data, 0) != HTYPE_ETHERNET
+            || get_u16(data, 2) != ETHERTYPE_IPV4
+            || data[4] != 6
+            || data[5] != 4
+        {
+            return Err(Error::InvalidArgs);
+        }
+        Ok(pkt)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.buf
+    }
+
+    pub fn operation(&self) -> u16 {
+        get_u16(self.buf.as_ref(), 6)
+    }
+
+    pub fn sender_mac(&self) -> MacAddress {
+        let mut octets = [0u8; 6];
+        octets.copy_from_slice(&self.buf.as_ref()[8..14]);
+        MacAddress { octets }
+    }
+
+    pub fn sender_ip(&self) -> Ipv4Addr {
+        let d = self.buf.as_ref();
+        Ipv4Addr::new(d[14], d[15], d[16], d[17])
+    }
+
+    pub fn target_mac(&self) -> MacAddress {
+        let mut octets = [0u8; 6];
+        octets.copy_from_slice(&self.buf.as_ref()[18..24]);
+        MacAddress { octets }
+    }
+
+    pub fn target_ip(&self) -> Ipv4Addr {
+        let d = self.buf.as_ref();
+        Ipv4Addr::new(d[24], d[25], d[26], d[27])
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> ArpPacket<T> {
+    /// Fill in a complete Ethernet/IPv4 ARP packet.
+    pub fn fill(
+        &mut self,
+        op: u16,
+        sender_mac: MacAddress,
+        sender_ip: Ipv4Addr,
+        target_mac: MacAddress,
+        target_ip: Ipv4Addr,
+    ) {
+        let d = self.buf.as_mut();
+        put_u16(d, 0, HTYPE_ETHERNET);
+        put_u16(d, 2, ETHERTYPE_IPV4);
+        d[4] = 6;
+        d[5] = 4;
+        put_u16(d, 6, op);
+        d[8..14].copy_from_slice(&sender_mac.octets);
+        d[14..18].copy_from_slice(&sender_ip.octets());
+        d[18..24].copy_from_slice(&target_mac.octets);
+        d[24..28].copy_from_slice(&target_ip.octets());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::packet::fuzz;
+
+    fn sample() -> [u8; PACKET_LEN] {
+        let mut buf = [0u8; PACKET_LEN];
+        ArpPacket::new_unchecked(&mut buf[..]).fill(
+            OP_REQUEST,
+            MacAddress { octets: [0x02, 0, 0, 0, 0, 1] },
+            Ipv4Addr::new(10, 0, 0, 1),
+            MacAddress { octets: [0; 6] },
+            Ipv4Addr::new(10, 0, 0, 2),
+        );
+        buf
+    }
+
+    #[test]
+    fn round_trip() {
+        let buf = sample();
+        let pkt = ArpPacket::new_checked(&buf[..]).unwrap();
+        assert_eq!(pkt.operation(), OP_REQUEST);
+        assert_eq!(pkt.sender_mac().octets, [0x02, 0, 0, 0, 0, 1]);
+        assert_eq!(pkt.sender_ip(), Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(pkt.target_mac().octets, [0; 6]);
+        assert_eq!(pkt.target_ip(), Ipv4Addr::new(10, 0, 0, 2));
+    }
+
+    #[test]
+    fn malformed_input_never_panics() {
+        fuzz::mangle(&sample(), |data| {
+            if let Ok(pkt) = ArpPacket::new_checked(data) {
+                let _ = (pkt.operation(), pkt.sender_mac(), pkt.sender_ip());
+                let _ = (pkt.target_mac(), pkt.target_ip());
+            }
+        });
+    }
+}
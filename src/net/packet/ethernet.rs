@@ -0,0 +1,232 @@
+//! Ethernet II frames with optional 802.1Q / 802.1ad tags.
+
+use super::{get_u16, put_u16};
+use crate::net::checksum::{ETHERTYPE_QINQ, ETHERTYPE_VLAN};
+use crate::protocol::net::MacAddress;
+use glenda::error::Error;
+
+pub const HEADER_LEN: usize = 14;
+pub const VLAN_TAG_LEN: usize = 4;
+
+/// 802.1Q tag control information.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VlanTag {
+    /// Priority code point (0-7).
+    pub pcp: u8,
+    /// Drop eligible indicator.
+    pub dei: bool,
+    /// VLAN identifier (0-4095).
+    pub vid: u16,
+}
+
+impl VlanTag {
+    pub const fn new(vid: u16) -> Self {
+        Self { pcp: 0, dei: false, vid: vid & 0x0fff }
+    }
+
+    pub const fn from_tci(tci: u16) -> Self {
+        Self { pcp: (tci >> 13) as u8, dei: tci & 0x1000 != 0, vid: tci & 0x0fff }
+    }
+
+    pub const fn tci(&self) -> u16 {
+        ((self.pcp as u16 & 0x7) << 13) | ((self.dei as u16) << 12) | (self.vid & 0x0fff)
+    }
+}
+
+const fn is_tpid(ethertype: u16) -> bool {
+    ethertype == ETHERTYPE_VLAN || ethertype == ETHERTYPE_QINQ
+}
+
+#[derive(Debug, Clone)]
+pub struct EthernetFrame<T> {
+    buf: T,
+}
+
+impl<T: AsRef<[u8]>> EthernetFrame<T> {
+    pub const fn new_unchecked(buf: T) -> Self {
+        Self { buf }
+    }
+
+    /// Wrap `buf`, making sure the header and any VLAN tags fit.
+    pub fn new_checked(buf: T) -> Result<Self, Error> {
+        let frame = Self::new_unchecked(buf);
+        let data = frame.buf.as_ref();
+        let mut off = HEADER_LEN - 2;
+        loop {
+            if data.len() < off + 2 {
+                return Err(Error::InvalidArgs);
+            }
+            if !is_tpid(get_u16(data, off)) {
+                break;
+            }
+            off += VLAN_TAG_LEN;
+        }
+        Ok(frame)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.buf
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.buf.as_ref()
+    }
+
+    pub fn dst(&self) -> MacAddress {
+        let mut octets = [0u8; 6];
+        octets.copy_from_slice(&self.buf.as_ref()[0..6]);
+        MacAddress { octets }
+    }
+
+    pub fn src(&self) -> MacAddress {
+        let mut octets = [0u8; 6];
+        octets.copy_from_slice(&self.buf.as_ref()[6..12]);
+        MacAddress { octets }
+    }
+
+    /// Outermost VLAN tag, if any.
+    pub fn vlan(&self) -> Option<VlanTag> {
+        let data = self.buf.as_ref();
+        if is_tpid(get_u16(data, 12)) {
+            Some(VlanTag::from_tci(get_u16(data, 14)))
+        } else {
+            None
+        }
+    }
+
+    /// Header length including all VLAN tags.
+    pub fn header_len(&self) -> usize {
+        let data = self.buf.as_ref();
+        let mut off = HEADER_LEN - 2;
+        while is_tpid(get_u16(data, off)) {
+            off += VLAN_TAG_LEN;
+        }
+        off + 2
+    }
+
+    /// EtherType of the payload, after any VLAN tags.
+    pub fn ethertype(&self) -> u16 {
+        get_u16(self.buf.as_ref(), self.header_len() - 2)
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.buf.as_ref()[self.header_len()..]
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> EthernetFrame<T> {
+    pub fn set_dst(&mut self, mac: MacAddress) {
+        self.buf.as_mut()[0..6].copy_from_slice(&mac.octets);
+    }
+
+    pub fn set_src(&mut self, mac: MacAddress) {
+        self.buf.as_mut()[6..12].copy_from_slice(&mac.octets);
+    }
+
+    pub fn set_ethertype(&mut self, ethertype: u16) {
+        let off = self.header_len() - 2;
+        put_u16(self.buf.as_mut(), off, ethertype);
+    }
+
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let off = self.header_len();
+        &mut self.buf.as_mut()[off..]
+    }
+}
+
+/// Write an Ethernet header, tagged if `vlan` is set, at the start of `buf`.
+/// Returns the header length, i.e. the payload offset.
+pub fn emit_header(
+    buf: &mut [u8],
+    dst: MacAddress,
+    src: MacAddress,
+    vlan: Option<VlanTag>,
+    ethertype: u16,
+) -> Result<usize, Error> {
+    let len = HEADER_LEN + if vlan.is_some() { VLAN_TAG_LEN } else { 0 };
+    if buf.len() < len {
+        return Err(Error::InvalidArgs);
+    }
+    buf[0..6].copy_from_slice(&dst.octets);
+    buf[6..12].copy_from_slice(&src.octets);
+    if let Some(tag) = vlan {
+        put_u16(buf, 12, ETHERTYPE_VLAN);
+        put_u16(buf, 14, tag.tci());
+    }
+    put_u16(buf, len - 2, ethertype);
+    Ok(len)
+}
+
+/// Insert an 802.1Q tag after the MAC addresses of the `len`-byte frame at the start of `buf`.
+/// `buf` needs `VLAN_TAG_LEN` spare bytes; returns the new frame length.
+pub fn insert_vlan_tag(buf: &mut [u8], len: usize, tag: VlanTag) -> Result<usize, Error> {
+    if len < HEADER_LEN || buf.len() < len + VLAN_TAG_LEN {
+        return Err(Error::InvalidArgs);
+    }
+    buf.copy_within(12..len, 12 + VLAN_TAG_LEN);
+    put_u16(buf, 12, ETHERTYPE_VLAN);
+    put_u16(buf, 14, tag.tci());
+    Ok(len + VLAN_TAG_LEN)
+}
+
+/// Remove the outermost VLAN tag from the `len`-byte frame at the start of `buf`.
+/// Returns the new frame length and the removed tag, or None if the frame is untagged.
+pub fn strip_vlan_tag(buf: &mut [u8], len: usize) -> Option<(usize, VlanTag)> {
+    if len < HEADER_LEN + VLAN_TAG_LEN || buf.len() < len || !is_tpid(get_u16(buf, 12)) {
+        return None;
+    }
+    let tag = VlanTag::from_tci(get_u16(buf, 14));
+    buf.copy_within(12 + VLAN_TAG_LEN..len, 12);
+    Some((len - VLAN_TAG_LEN, tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::checksum::ETHERTYPE_IPV4;
+    use crate::net::packet::fuzz;
+
+    const DST: MacAddress = MacAddress { octets: [0xff; 6] };
+    const SRC: MacAddress = MacAddress { octets: [0x02, 0, 0, 0, 0, 1] };
+
+    #[test]
+    fn round_trip() {
+        let mut buf = [0u8; 64];
+        let tag = VlanTag { pcp: 5, dei: true, vid: 42 };
+        let off = emit_header(&mut buf, DST, SRC, Some(tag), ETHERTYPE_IPV4).unwrap();
+        assert_eq!(off, HEADER_LEN + VLAN_TAG_LEN);
+        buf[off..off + 4].copy_from_slice(b"data");
+
+        let frame = EthernetFrame::new_checked(&buf[..off + 4]).unwrap();
+        assert_eq!(frame.dst(), DST);
+        assert_eq!(frame.src(), SRC);
+        assert_eq!(frame.vlan(), Some(tag));
+        assert_eq!(frame.header_len(), off);
+        assert_eq!(frame.ethertype(), ETHERTYPE_IPV4);
+        assert_eq!(frame.payload(), b"data");
+
+        let (len, stripped) = strip_vlan_tag(&mut buf, off + 4).unwrap();
+        assert_eq!(stripped, tag);
+        let frame = EthernetFrame::new_checked(&buf[..len]).unwrap();
+        assert_eq!(frame.vlan(), None);
+        assert_eq!(frame.ethertype(), ETHERTYPE_IPV4);
+        assert_eq!(frame.payload(), b"data");
+
+        assert_eq!(insert_vlan_tag(&mut buf, len, tag), Ok(off + 4));
+        assert_eq!(EthernetFrame::new_checked(&buf[..off + 4]).unwrap().vlan(), Some(tag));
+    }
+
+    #[test]
+    fn malformed_input_never_panics() {
+        let mut sample = [0u8; 24];
+        let off = emit_header(&mut sample, DST, SRC, None, ETHERTYPE_VLAN).unwrap();
+        put_u16(&mut sample, off, VlanTag::new(7).tci());
+        put_u16(&mut sample, off + 2, ETHERTYPE_IPV4);
+        fuzz::mangle(&sample, |data| {
+            if let Ok(frame) = EthernetFrame::new_checked(data) {
+                let _ = (frame.dst(), frame.src(), frame.vlan(), frame.ethertype());
+                let _ = frame.payload();
+            }
+        });
+    }
+}
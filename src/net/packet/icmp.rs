@@ -0,0 +1,150 @@
+//! ICMPv4 (RFC 792) and ICMPv6 (RFC 4443) messages.
+//!
+//! Both share the type/code/checksum layout; they differ only in whether the checksum covers
+//! an IP pseudo header, so the checksum helpers take the pseudo header sum (0 for ICMPv4).
+
+use super::{get_u16, get_u32, put_u16, put_u32};
+use crate::net::checksum;
+use glenda::error::Error;
+
+pub const HEADER_LEN: usize = 8;
+
+// ICMPv4 Types
+pub const V4_ECHO_REPLY: u8 = 0;
+pub const V4_DEST_UNREACHABLE: u8 = 3;
+pub const V4_ECHO_REQUEST: u8 = 8;
+pub const V4_TIME_EXCEEDED: u8 = 11;
+
+// ICMPv6 Types
+pub const V6_DEST_UNREACHABLE: u8 = 1;
+pub const V6_PACKET_TOO_BIG: u8 = 2;
+pub const V6_TIME_EXCEEDED: u8 = 3;
+pub const V6_ECHO_REQUEST: u8 = 128;
+pub const V6_ECHO_REPLY: u8 = 129;
+pub const V6_ROUTER_SOLICIT: u8 = 133;
+pub const V6_ROUTER_ADVERT: u8 = 134;
+pub const V6_NEIGHBOR_SOLICIT: u8 = 135;
+pub const V6_NEIGHBOR_ADVERT: u8 = 136;
+
+/// A message spanning the whole buffer.
+#[derive(Debug, Clone)]
+pub struct IcmpPacket<T> {
+    buf: T,
+}
+
+impl<T: AsRef<[u8]>> IcmpPacket<T> {
+    pub const fn new_unchecked(buf: T) -> Self {
+        Self { buf }
+    }
+
+    pub fn new_checked(buf: T) -> Result<Self, Error> {
+        if buf.as_ref().len() < HEADER_LEN {
+            return Err(Error::InvalidArgs);
+        }
+        Ok(Self::new_unchecked(buf))
+    }
+
+    pub fn into_inner(self) -> T {
+        self.buf
+    }
+
+    pub fn msg_type(&self) -> u8 {
+        self.buf.as_ref()[0]
+    }
+
+    pub fn code(&self) -> u8 {
+        self.buf.as_ref()[1]
+    }
+
+    pub fn checksum(&self) -> u16 {
+        get_u16(self.buf.as_ref(), 2)
+    }
+
+    /// The type-specific second header word.
+    pub fn rest_of_header(&self) -> u32 {
+        get_u32(self.buf.as_ref(), 4)
+    }
+
+    /// Echo identifier (echo request/reply only).
+    pub fn echo_ident(&self) -> u16 {
+        get_u16(self.buf.as_ref(), 4)
+    }
+
+    /// Echo sequence number (echo request/reply only).
+    pub fn echo_seq(&self) -> u16 {
+        get_u16(self.buf.as_ref(), 6)
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.buf.as_ref()[HEADER_LEN..]
+    }
+
+    pub fn verify_checksum(&self, pseudo: u32) -> bool {
+        checksum::finish(checksum::sum(self.buf.as_ref(), pseudo)) == 0
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> IcmpPacket<T> {
+    pub fn init(&mut self, msg_type: u8, code: u8, rest_of_header: u32) {
+        let d = self.buf.as_mut();
+        d[0] = msg_type;
+        d[1] = code;
+        put_u16(d, 2, 0);
+        put_u32(d, 4, rest_of_header);
+    }
+
+    pub fn init_echo(&mut self, msg_type: u8, ident: u16, seq: u16) {
+        self.init(msg_type, 0, ((ident as u32) << 16) | seq as u32);
+    }
+
+    /// Compute the checksum; pass 0 for ICMPv4 or the IPv6 pseudo header sum for ICMPv6.
+    pub fn fill_checksum(&mut self, pseudo: u32) {
+        let d = self.buf.as_mut();
+        put_u16(d, 2, 0);
+        let csum = checksum::finish(checksum::sum(d, pseudo));
+        put_u16(d, 2, csum);
+    }
+
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        &mut self.buf.as_mut()[HEADER_LEN..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::packet::fuzz;
+
+    fn sample() -> [u8; 12] {
+        let mut buf = [0u8; 12];
+        let mut pkt = IcmpPacket::new_unchecked(&mut buf[..]);
+        pkt.init_echo(V4_ECHO_REQUEST, 0x1234, 7);
+        pkt.payload_mut().copy_from_slice(b"ping");
+        pkt.fill_checksum(0);
+        buf
+    }
+
+    #[test]
+    fn round_trip() {
+        let buf = sample();
+        let pkt = IcmpPacket::new_checked(&buf[..]).unwrap();
+        assert_eq!(pkt.msg_type(), V4_ECHO_REQUEST);
+        assert_eq!(pkt.code(), 0);
+        assert_eq!(pkt.echo_ident(), 0x1234);
+        assert_eq!(pkt.echo_seq(), 7);
+        assert_eq!(pkt.rest_of_header(), 0x1234_0007);
+        assert_eq!(pkt.payload(), b"ping");
+        assert!(pkt.verify_checksum(0));
+    }
+
+    #[test]
+    fn malformed_input_never_panics() {
+        fuzz::mangle(&sample(), |data| {
+            if let Ok(pkt) = IcmpPacket::new_checked(data) {
+                let _ = (pkt.msg_type(), pkt.code(), pkt.checksum(), pkt.rest_of_header());
+                let _ = (pkt.echo_ident(), pkt.echo_seq(), pkt.verify_checksum(0));
+                let _ = pkt.payload();
+            }
+        });
+    }
+}
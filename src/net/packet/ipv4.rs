@@ -0,0 +1,249 @@
+//! IPv4 headers (RFC 791).
+
+use super::{get_u16, put_u16};
+use crate::net::checksum;
+use core::net::Ipv4Addr;
+use glenda::error::Error;
+
+pub const MIN_HEADER_LEN: usize = 20;
+pub const DEFAULT_TTL: u8 = 64;
+
+const FLAG_DF: u16 = 0x4000;
+const FLAG_MF: u16 = 0x2000;
+
+#[derive(Debug, Clone)]
+pub struct Ipv4Packet<T> {
+    buf: T,
+}
+
+impl<T: AsRef<[u8]>> Ipv4Packet<T> {
+    pub const fn new_unchecked(buf: T) -> Self {
+        Self { buf }
+    }
+
+    /// Wrap `buf`, checking the version, header length and total length.
+    pub fn new_checked(buf: T) -> Result<Self, Error> {
+        let pkt = Self::new_unchecked(buf);
+        let data = pkt.buf.as_ref();
+        if data.len() < MIN_HEADER_LEN || data[0] >> 4 != 4 {
+            return Err(Error::InvalidArgs);
+        }
+        let ihl = pkt.header_len();
+        let total = pkt.total_len() as usize;
+        if ihl < MIN_HEADER_LEN || total < ihl || total > data.len() {
+            return Err(Error::InvalidArgs);
+        }
+        Ok(pkt)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.buf
+    }
+
+    pub fn header_len(&self) -> usize {
+        ((self.buf.as_ref()[0] & 0x0f) as usize) * 4
+    }
+
+    pub fn dscp_ecn(&self) -> u8 {
+        self.buf.as_ref()[1]
+    }
+
+    pub fn total_len(&self) -> u16 {
+        get_u16(self.buf.as_ref(), 2)
+    }
+
+    pub fn ident(&self) -> u16 {
+        get_u16(self.buf.as_ref(), 4)
+    }
+
+    pub fn dont_frag(&self) -> bool {
+        get_u16(self.buf.as_ref(), 6) & FLAG_DF != 0
+    }
+
+    pub fn more_frags(&self) -> bool {
+        get_u16(self.buf.as_ref(), 6) & FLAG_MF != 0
+    }
+
+    /// Fragment offset in bytes.
+    pub fn frag_offset(&self) -> u16 {
+        (get_u16(self.buf.as_ref(), 6) & 0x1fff) * 8
+    }
+
+    pub fn ttl(&self) -> u8 {
+        self.buf.as_ref()[8]
+    }
+
+    pub fn protocol(&self) -> u8 {
+        self.buf.as_ref()[9]
+    }
+
+    pub fn checksum(&self) -> u16 {
+        get_u16(self.buf.as_ref(), 10)
+    }
+
+    pub fn src(&self) -> Ipv4Addr {
+        let d = self.buf.as_ref();
+        Ipv4Addr::new(d[12], d[13], d[14], d[15])
+    }
+
+    pub fn dst(&self) -> Ipv4Addr {
+        let d = self.buf.as_ref();
+        Ipv4Addr::new(d[16], d[17], d[18], d[19])
+    }
+
+    pub fn verify_checksum(&self) -> bool {
+        checksum::checksum(&self.buf.as_ref()[..self.header_len()]) == 0
+    }
+
+    /// Payload bounded by the total length field.
+    pub fn payload(&self) -> &[u8] {
+        &self.buf.as_ref()[self.header_len()..self.total_len() as usize]
+    }
+
+    /// Running sum of the pseudo header for this packet's upper-layer payload.
+    pub fn pseudo_header(&self) -> u32 {
+        let len = self.total_len() as usize - self.header_len();
+        checksum::pseudo_header_v4(
+            &self.src().octets(),
+            &self.dst().octets(),
+            self.protocol(),
+            len as u16,
+        )
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Ipv4Packet<T> {
+    /// Write an option-less header: version 4, IHL 5, DF set, default TTL.
+    /// The checksum is left zero; call `fill_checksum` once all fields are final.
+    pub fn init(&mut self, protocol: u8, src: Ipv4Addr, dst: Ipv4Addr, payload_len: usize) {
+        let d = self.buf.as_mut();
+        d[0] = 0x45;
+        d[1] = 0;
+        put_u16(d, 2, (MIN_HEADER_LEN + payload_len) as u16);
+        put_u16(d, 4, 0);
+        put_u16(d, 6, FLAG_DF);
+        d[8] = DEFAULT_TTL;
+        d[9] = protocol;
+        put_u16(d, 10, 0);
+        d[12..16].copy_from_slice(&src.octets());
+        d[16..20].copy_from_slice(&dst.octets());
+    }
+
+    pub fn set_dscp_ecn(&mut self, v: u8) {
+        self.buf.as_mut()[1] = v;
+    }
+
+    /// Set the total length, which must cover the header and fit in the buffer.
+    pub fn set_total_len(&mut self, len: u16) -> Result<(), Error> {
+        if (len as usize) < self.header_len() || len as usize > self.buf.as_ref().len() {
+            return Err(Error::InvalidArgs);
+        }
+        put_u16(self.buf.as_mut(), 2, len);
+        Ok(())
+    }
+
+    pub fn set_ident(&mut self, ident: u16) {
+        put_u16(self.buf.as_mut(), 4, ident);
+    }
+
+    pub fn set_dont_frag(&mut self, df: bool) {
+        let d = self.buf.as_mut();
+        let v = get_u16(d, 6);
+        put_u16(d, 6, if df { v | FLAG_DF } else { v & !FLAG_DF });
+    }
+
+    pub fn set_ttl(&mut self, ttl: u8) {
+        self.buf.as_mut()[8] = ttl;
+    }
+
+    pub fn set_protocol(&mut self, proto: u8) {
+        self.buf.as_mut()[9] = proto;
+    }
+
+    pub fn set_src(&mut self, addr: Ipv4Addr) {
+        self.buf.as_mut()[12..16].copy_from_slice(&addr.octets());
+    }
+
+    pub fn set_dst(&mut self, addr: Ipv4Addr) {
+        self.buf.as_mut()[16..20].copy_from_slice(&addr.octets());
+    }
+
+    pub fn fill_checksum(&mut self) {
+        let ihl = self.header_len();
+        let d = self.buf.as_mut();
+        put_u16(d, 10, 0);
+        let csum = checksum::checksum(&d[..ihl]);
+        put_u16(d, 10, csum);
+    }
+
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let (start, end) = (self.header_len(), self.total_len() as usize);
+        &mut self.buf.as_mut()[start..end]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::packet::fuzz;
+
+    const SRC: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 1);
+    const DST: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 2);
+
+    fn sample() -> [u8; 28] {
+        let mut buf = [0u8; 28];
+        let mut pkt = Ipv4Packet::new_unchecked(&mut buf[..]);
+        pkt.init(17, SRC, DST, 8);
+        pkt.set_dscp_ecn(0xb8);
+        pkt.set_ident(0x1234);
+        pkt.set_ttl(9);
+        pkt.payload_mut().copy_from_slice(b"12345678");
+        pkt.fill_checksum();
+        buf
+    }
+
+    #[test]
+    fn round_trip() {
+        let buf = sample();
+        let pkt = Ipv4Packet::new_checked(&buf[..]).unwrap();
+        assert_eq!(pkt.header_len(), MIN_HEADER_LEN);
+        assert_eq!(pkt.dscp_ecn(), 0xb8);
+        assert_eq!(pkt.total_len(), 28);
+        assert_eq!(pkt.ident(), 0x1234);
+        assert!(pkt.dont_frag());
+        assert!(!pkt.more_frags());
+        assert_eq!(pkt.frag_offset(), 0);
+        assert_eq!(pkt.ttl(), 9);
+        assert_eq!(pkt.protocol(), 17);
+        assert_eq!(pkt.src(), SRC);
+        assert_eq!(pkt.dst(), DST);
+        assert!(pkt.verify_checksum());
+        assert_eq!(pkt.payload(), b"12345678");
+        assert_eq!(
+            pkt.pseudo_header(),
+            checksum::pseudo_header_v4(&SRC.octets(), &DST.octets(), 17, 8)
+        );
+    }
+
+    #[test]
+    fn set_total_len_keeps_view_valid() {
+        let mut buf = sample();
+        let mut pkt = Ipv4Packet::new_checked(&mut buf[..]).unwrap();
+        assert_eq!(pkt.set_total_len(29), Err(Error::InvalidArgs));
+        assert_eq!(pkt.set_total_len(19), Err(Error::InvalidArgs));
+        assert_eq!(pkt.total_len(), 28);
+        pkt.set_total_len(24).unwrap();
+        assert_eq!(pkt.payload(), b"1234");
+    }
+
+    #[test]
+    fn malformed_input_never_panics() {
+        fuzz::mangle(&sample(), |data| {
+            if let Ok(pkt) = Ipv4Packet::new_checked(data) {
+                let _ = (pkt.dscp_ecn(), pkt.ident(), pkt.frag_offset(), pkt.ttl());
+                let _ = (pkt.src(), pkt.dst(), pkt.verify_checksum(), pkt.pseudo_header());
+                let _ = pkt.payload();
+            }
+        });
+    }
+}
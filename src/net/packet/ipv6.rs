@@ -0,0 +1,197 @@
+//! IPv6 fixed headers (RFC 8200). Extension headers are exposed as part of the payload.
+
+use super::{get_u16, get_u32, put_u16, put_u32};
+use crate::net::checksum;
+use core::net::Ipv6Addr;
+use glenda::error::Error;
+
+pub const HEADER_LEN: usize = 40;
+pub const DEFAULT_HOP_LIMIT: u8 = 64;
+
+#[derive(Debug, Clone)]
+pub struct Ipv6Packet<T> {
+    buf: T,
+}
+
+impl<T: AsRef<[u8]>> Ipv6Packet<T> {
+    pub const fn new_unchecked(buf: T) -> Self {
+        Self { buf }
+    }
+
+    /// Wrap `buf`, checking the version and payload length.
+    pub fn new_checked(buf: T) -> Result<Self, Error> {
+        let pkt = Self::new_unchecked(buf);
+        let data = pkt.buf.as_ref();
+        if data.len() < HEADER_LEN
+            || data[0] >> 4 != 6
+            || HEADER_LEN + pkt.payload_len() as usize > data.len()
+        {
+            return Err(Error::InvalidArgs);
+        }
+        Ok(pkt)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.buf
+    }
+
+    pub fn traffic_class(&self) -> u8 {
+        (get_u16(self.buf.as_ref(), 0) >> 4) as u8
+    }
+
+    pub fn flow_label(&self) -> u32 {
+        get_u32(self.buf.as_ref(), 0) & 0x000f_ffff
+    }
+
+    pub fn payload_len(&self) -> u16 {
+        get_u16(self.buf.as_ref(), 4)
+    }
+
+    pub fn next_header(&self) -> u8 {
+        self.buf.as_ref()[6]
+    }
+
+    pub fn hop_limit(&self) -> u8 {
+        self.buf.as_ref()[7]
+    }
+
+    pub fn src(&self) -> Ipv6Addr {
+        let mut octets = [0u8; 16];
+        octets.copy_from_slice(&self.buf.as_ref()[8..24]);
+        Ipv6Addr::from(octets)
+    }
+
+    pub fn dst(&self) -> Ipv6Addr {
+        let mut octets = [0u8; 16];
+        octets.copy_from_slice(&self.buf.as_ref()[24..40]);
+        Ipv6Addr::from(octets)
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.buf.as_ref()[HEADER_LEN..HEADER_LEN + self.payload_len() as usize]
+    }
+
+    /// Running sum of the pseudo header, assuming the payload is the upper-layer packet.
+    pub fn pseudo_header(&self) -> u32 {
+        checksum::pseudo_header_v6(
+            &self.src().octets(),
+            &self.dst().octets(),
+            self.next_header(),
+            self.payload_len() as u32,
+        )
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> Ipv6Packet<T> {
+    /// Write a header with zero traffic class and flow label and the default hop limit.
+    pub fn init(&mut self, next_header: u8, src: Ipv6Addr, dst: Ipv6Addr, payload_len: usize) {
+        let d = self.buf.as_mut();
+        put_u32(d, 0, 6 << 28);
+        put_u16(d, 4, payload_len as u16);
+        d[6] = next_header;
+        d[7] = DEFAULT_HOP_LIMIT;
+        d[8..24].copy_from_slice(&src.octets());
+        d[24..40].copy_from_slice(&dst.octets());
+    }
+
+    pub fn set_traffic_class(&mut self, tc: u8) {
+        let d = self.buf.as_mut();
+        let v = (get_u32(d, 0) & !0x0ff0_0000) | ((tc as u32) << 20);
+        put_u32(d, 0, v);
+    }
+
+    pub fn set_flow_label(&mut self, label: u32) {
+        let d = self.buf.as_mut();
+        let v = (get_u32(d, 0) & !0x000f_ffff) | (label & 0x000f_ffff);
+        put_u32(d, 0, v);
+    }
+
+    /// Set the payload length, which must fit in the buffer after the header.
+    pub fn set_payload_len(&mut self, len: u16) -> Result<(), Error> {
+        if HEADER_LEN + len as usize > self.buf.as_ref().len() {
+            return Err(Error::InvalidArgs);
+        }
+        put_u16(self.buf.as_mut(), 4, len);
+        Ok(())
+    }
+
+    pub fn set_next_header(&mut self, nh: u8) {
+        self.buf.as_mut()[6] = nh;
+    }
+
+    pub fn set_hop_limit(&mut self, hl: u8) {
+        self.buf.as_mut()[7] = hl;
+    }
+
+    pub fn set_src(&mut self, addr: Ipv6Addr) {
+        self.buf.as_mut()[8..24].copy_from_slice(&addr.octets());
+    }
+
+    pub fn set_dst(&mut self, addr: Ipv6Addr) {
+        self.buf.as_mut()[24..40].copy_from_slice(&addr.octets());
+    }
+
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let end = HEADER_LEN + self.payload_len() as usize;
+        &mut self.buf.as_mut()[HEADER_LEN..end]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::packet::fuzz;
+
+    const SRC: Ipv6Addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+    const DST: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+
+    fn sample() -> [u8; 48] {
+        let mut buf = [0u8; 48];
+        let mut pkt = Ipv6Packet::new_unchecked(&mut buf[..]);
+        pkt.init(58, SRC, DST, 8);
+        pkt.set_traffic_class(0xb8);
+        pkt.set_flow_label(0x12345);
+        pkt.set_hop_limit(255);
+        pkt.payload_mut().copy_from_slice(b"12345678");
+        buf
+    }
+
+    #[test]
+    fn round_trip() {
+        let buf = sample();
+        let pkt = Ipv6Packet::new_checked(&buf[..]).unwrap();
+        assert_eq!(pkt.traffic_class(), 0xb8);
+        assert_eq!(pkt.flow_label(), 0x12345);
+        assert_eq!(pkt.payload_len(), 8);
+        assert_eq!(pkt.next_header(), 58);
+        assert_eq!(pkt.hop_limit(), 255);
+        assert_eq!(pkt.src(), SRC);
+        assert_eq!(pkt.dst(), DST);
+        assert_eq!(pkt.payload(), b"12345678");
+        assert_eq!(
+            pkt.pseudo_header(),
+            checksum::pseudo_header_v6(&SRC.octets(), &DST.octets(), 58, 8)
+        );
+    }
+
+    #[test]
+    fn set_payload_len_keeps_view_valid() {
+        let mut buf = sample();
+        let mut pkt = Ipv6Packet::new_checked(&mut buf[..]).unwrap();
+        assert_eq!(pkt.set_payload_len(9), Err(Error::InvalidArgs));
+        assert_eq!(pkt.payload_len(), 8);
+        pkt.set_payload_len(4).unwrap();
+        assert_eq!(pkt.payload(), b"1234");
+    }
+
+    #[test]
+    fn malformed_input_never_panics() {
+        fuzz::mangle(&sample(), |data| {
+            if let Ok(pkt) = Ipv6Packet::new_checked(data) {
+                let _ = (pkt.traffic_class(), pkt.flow_label(), pkt.next_header());
+                let _ = (pkt.hop_limit(), pkt.src(), pkt.dst(), pkt.pseudo_header());
+                let _ = pkt.payload();
+            }
+        });
+    }
+}
@@ -0,0 +1,72 @@
+//! Zero-copy views and builders for the frames carried by `NetClient`.
+//!
+//! Every view wraps an `AsRef<[u8]>` buffer, such as the slices handed to `send_packet` and
+//! `submit_recv`. Setters are available when the buffer is also `AsMut<[u8]>`, so the same types
+//! parse received frames and build outgoing ones in place. `new_checked` validates lengths and
+//! fixed fields; accessors on a checked view never panic. Setters that change a length field
+//! reject values the buffer cannot hold, so a checked view stays valid. `init` builders assume
+//! the buffer is large enough for the header and payload they describe.
+
+pub mod arp;
+pub mod ethernet;
+pub mod icmp;
+pub mod ipv4;
+pub mod ipv6;
+pub mod tcp;
+pub mod udp;
+
+pub use arp::ArpPacket;
+pub use ethernet::{EthernetFrame, VlanTag};
+pub use icmp::IcmpPacket;
+pub use ipv4::Ipv4Packet;
+pub use ipv6::Ipv6Packet;
+pub use tcp::TcpSegment;
+pub use udp::UdpDatagram;
+
+pub(crate) fn get_u16(buf: &[u8], off: usize) -> u16 {
+    u16::from_be_bytes([buf[off], buf[off + 1]])
+}
+
+pub(crate) fn put_u16(buf: &mut [u8], off: usize, v: u16) {
+    buf[off..off + 2].copy_from_slice(&v.to_be_bytes());
+}
+
+pub(crate) fn get_u32(buf: &[u8], off: usize) -> u32 {
+    u32::from_be_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
+
+pub(crate) fn put_u32(buf: &mut [u8], off: usize, v: u32) {
+    buf[off..off + 4].copy_from_slice(&v.to_be_bytes());
+}
+
+#[cfg(test)]
+pub(crate) mod fuzz {
+    /// Feed `check` every prefix of `sample`, copies of it with random bytes flipped, and random
+    /// buffers of similar length.
+    pub(crate) fn mangle(sample: &[u8], mut check: impl FnMut(&[u8])) {
+        for len in 0..=sample.len() {
+            check(&sample[..len]);
+        }
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let mut buf = alloc::vec::Vec::new();
+        for _ in 0..512 {
+            buf.clear();
+            buf.extend_from_slice(sample);
+            for _ in 0..1 + next() % 4 {
+                let i = (next() as usize) % buf.len();
+                buf[i] = next() as u8;
+            }
+            buf.truncate((next() as usize) % (sample.len() + 1));
+            check(&buf);
+            buf.clear();
+            buf.extend((0..(next() as usize) % (sample.len() + 8)).map(|_| next() as u8));
+            check(&buf);
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! TCP segments (RFC 9293).
+
+use super::{get_u16, get_u32, put_u16, put_u32};
+use crate::net::checksum;
+use glenda::error::Error;
+
+pub const MIN_HEADER_LEN: usize = 20;
+pub const MAX_HEADER_LEN: usize = 60;
+
+// Control Flags
+pub const FIN: u16 = 0x001;
+pub const SYN: u16 = 0x002;
+pub const RST: u16 = 0x004;
+pub const PSH: u16 = 0x008;
+pub const ACK: u16 = 0x010;
+pub const URG: u16 = 0x020;
+pub const ECE: u16 = 0x040;
+pub const CWR: u16 = 0x080;
+
+/// A segment spanning the whole buffer; the TCP length is the buffer length.
+#[derive(Debug, Clone)]
+pub struct TcpSegment<T> {
+    buf: T,
+}
+
+impl<T: AsRef<[u8]>> TcpSegment<T> {
+    pub const fn new_unchecked(buf: T) -> Self {
+        Self { buf }
+    }
+
+    /// Wrap `buf`, checking the data offset.
+    pub fn new_checked(buf: T) -> Result<Self, Error> {
+        let seg = Self::new_unchecked(buf);
+        let data = seg.buf.as_ref();
+        if data.len() < MIN_HEADER_LEN {
+            return Err(Error::InvalidArgs);
+        }
+        let hlen = seg.header_len();
+        if hlen < MIN_HEADER_LEN || hlen > data.len() {
+            return Err(Error::InvalidArgs);
+        }
+        Ok(seg)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.buf
+    }
+
+    pub fn src_port(&self) -> u16 {
+        get_u16(self.buf.as_ref(), 0)
+    }
+
+    pub fn dst_port(&self) -> u16 {
+        get_u16(self.buf.as_ref(), 2)
+    }
+
+    pub fn seq(&self) -> u32 {
+        get_u32(self.buf.as_ref(), 4)
+    }
+
+    pub fn ack(&self) -> u32 {
+        get_u32(self.buf.as_ref(), 8)
+    }
+
+    pub fn header_len(&self) -> usize {
+        ((self.buf.as_ref()[12] >> 4) as usize) * 4
+    }
+
+    pub fn flags(&self) -> u16 {
+        get_u16(self.buf.as_ref(), 12) & 0x01ff
+    }
+
+    pub fn window(&self) -> u16 {
+        get_u16(self.buf.as_ref(), 14)
+    }
+
+    pub fn checksum(&self) -> u16 {
+        get_u16(self.buf.as_ref(), 16)
+    }
+
+    pub fn urgent(&self) -> u16 {
+        get_u16(self.buf.as_ref(), 18)
+    }
+
+    pub fn options(&self) -> &[u8] {
+        &self.buf.as_ref()[MIN_HEADER_LEN..self.header_len()]
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.buf.as_ref()[self.header_len()..]
+    }
+
+    pub fn verify_checksum(&self, pseudo: u32) -> bool {
+        checksum::finish(checksum::sum(self.buf.as_ref(), pseudo)) == 0
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> TcpSegment<T> {
+    /// Write an option-less header; checksum and urgent pointer are zeroed.
+    pub fn init(&mut self, src_port: u16, dst_port: u16, seq: u32, ack: u32, flags: u16) {
+        let d = self.buf.as_mut();
+        put_u16(d, 0, src_port);
+        put_u16(d, 2, dst_port);
+        put_u32(d, 4, seq);
+        put_u32(d, 8, ack);
+        put_u16(d, 12, (((MIN_HEADER_LEN / 4) as u16) << 12) | (flags & 0x01ff));
+        put_u16(d, 14, 0);
+        put_u16(d, 16, 0);
+        put_u16(d, 18, 0);
+    }
+
+    pub fn set_src_port(&mut self, port: u16) {
+        put_u16(self.buf.as_mut(), 0, port);
+    }
+
+    pub fn set_dst_port(&mut self, port: u16) {
+        put_u16(self.buf.as_mut(), 2, port);
+    }
+
+    pub fn set_seq(&mut self, seq: u32) {
+        put_u32(self.buf.as_mut(), 4, seq);
+    }
+
+    pub fn set_ack(&mut self, ack: u32) {
+        put_u32(self.buf.as_mut(), 8, ack);
+    }
+
+    /// Set the header length in bytes: a multiple of 4 from 20 to 60 that fits in the buffer.
+    pub fn set_header_len(&mut self, len: usize) -> Result<(), Error> {
+        let d = self.buf.as_mut();
+        if !(MIN_HEADER_LEN..=MAX_HEADER_LEN).contains(&len)
+            || !len.is_multiple_of(4)
+            || len > d.len()
+        {
+            return Err(Error::InvalidArgs);
+        }
+        d[12] = (d[12] & 0x0f) | (((len / 4) as u8) << 4);
+        Ok(())
+    }
+
+    pub fn set_flags(&mut self, flags: u16) {
+        let d = self.buf.as_mut();
+        let v = (get_u16(d, 12) & 0xf000) | (flags & 0x01ff);
+        put_u16(d, 12, v);
+    }
+
+    pub fn set_window(&mut self, window: u16) {
+        put_u16(self.buf.as_mut(), 14, window);
+    }
+
+    pub fn set_urgent(&mut self, urgent: u16) {
+        put_u16(self.buf.as_mut(), 18, urgent);
+    }
+
+    /// Compute the checksum over the segment and the IP pseudo header sum.
+    pub fn fill_checksum(&mut self, pseudo: u32) {
+        let d = self.buf.as_mut();
+        put_u16(d, 16, 0);
+        let csum = checksum::finish(checksum::sum(d, pseudo));
+        put_u16(d, 16, csum);
+    }
+
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let off = self.header_len();
+        &mut self.buf.as_mut()[off..]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::packet::fuzz;
+
+    const PSEUDO: u32 = 0x1234;
+
+    fn sample() -> [u8; 28] {
+        let mut buf = [0u8; 28];
+        let mut seg = TcpSegment::new_unchecked(&mut buf[..]);
+        seg.init(49152, 80, 1000, 2000, SYN | ACK);
+        seg.set_window(8192);
+        seg.set_urgent(3);
+        seg.payload_mut().copy_from_slice(b"12345678");
+        seg.fill_checksum(PSEUDO);
+        buf
+    }
+
+    #[test]
+    fn round_trip() {
+        let buf = sample();
+        let seg = TcpSegment::new_checked(&buf[..]).unwrap();
+        assert_eq!(seg.src_port(), 49152);
+        assert_eq!(seg.dst_port(), 80);
+        assert_eq!(seg.seq(), 1000);
+        assert_eq!(seg.ack(), 2000);
+        assert_eq!(seg.header_len(), MIN_HEADER_LEN);
+        assert_eq!(seg.flags(), SYN | ACK);
+        assert_eq!(seg.window(), 8192);
+        assert_eq!(seg.urgent(), 3);
+        assert!(seg.options().is_empty());
+        assert_eq!(seg.payload(), b"12345678");
+        assert!(seg.verify_checksum(PSEUDO));
+    }
+
+    #[test]
+    fn set_header_len_keeps_view_valid() {
+        let mut buf = sample();
+        let mut seg = TcpSegment::new_checked(&mut buf[..]).unwrap();
+        assert_eq!(seg.set_header_len(32), Err(Error::InvalidArgs));
+        assert_eq!(seg.set_header_len(22), Err(Error::InvalidArgs));
+        assert_eq!(seg.set_header_len(16), Err(Error::InvalidArgs));
+        assert_eq!(seg.header_len(), MIN_HEADER_LEN);
+        seg.set_header_len(24).unwrap();
+        assert_eq!(seg.options(), b"1234");
+        assert_eq!(seg.payload(), b"5678");
+        assert_eq!(seg.flags(), SYN | ACK);
+    }
+
+    #[test]
+    fn malformed_input_never_panics() {
+        fuzz::mangle(&sample(), |data| {
+            if let Ok(seg) = TcpSegment::new_checked(data) {
+                let _ = (seg.src_port(), seg.dst_port(), seg.seq(), seg.ack(), seg.flags());
+                let _ = (seg.window(), seg.urgent(), seg.verify_checksum(PSEUDO));
+                let _ = (seg.options(), seg.payload());
+            }
+        });
+    }
+}
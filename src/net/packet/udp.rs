@@ -0,0 +1,161 @@
+//! UDP datagrams (RFC 768).
+
+use super::{get_u16, put_u16};
+use crate::net::checksum;
+use glenda::error::Error;
+
+pub const HEADER_LEN: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct UdpDatagram<T> {
+    buf: T,
+}
+
+impl<T: AsRef<[u8]>> UdpDatagram<T> {
+    pub const fn new_unchecked(buf: T) -> Self {
+        Self { buf }
+    }
+
+    /// Wrap `buf`, checking the length field against the buffer.
+    pub fn new_checked(buf: T) -> Result<Self, Error> {
+        let dgram = Self::new_unchecked(buf);
+        let data = dgram.buf.as_ref();
+        if data.len() < HEADER_LEN {
+            return Err(Error::InvalidArgs);
+        }
+        let len = dgram.len() as usize;
+        if len < HEADER_LEN || len > data.len() {
+            return Err(Error::InvalidArgs);
+        }
+        Ok(dgram)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.buf
+    }
+
+    pub fn src_port(&self) -> u16 {
+        get_u16(self.buf.as_ref(), 0)
+    }
+
+    pub fn dst_port(&self) -> u16 {
+        get_u16(self.buf.as_ref(), 2)
+    }
+
+    /// Header plus payload length, as carried in the header.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u16 {
+        get_u16(self.buf.as_ref(), 4)
+    }
+
+    pub fn checksum(&self) -> u16 {
+        get_u16(self.buf.as_ref(), 6)
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.buf.as_ref()[HEADER_LEN..self.len() as usize]
+    }
+
+    /// Verify against the IP pseudo header sum. A zero checksum (IPv4 only) is accepted.
+    pub fn verify_checksum(&self, pseudo: u32) -> bool {
+        self.checksum() == 0
+            || checksum::finish(checksum::sum(&self.buf.as_ref()[..self.len() as usize], pseudo))
+                == 0
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> UdpDatagram<T> {
+    /// Write the header for a datagram carrying `payload_len` bytes; the checksum is zeroed.
+    pub fn init(&mut self, src_port: u16, dst_port: u16, payload_len: usize) {
+        let d = self.buf.as_mut();
+        put_u16(d, 0, src_port);
+        put_u16(d, 2, dst_port);
+        put_u16(d, 4, (HEADER_LEN + payload_len) as u16);
+        put_u16(d, 6, 0);
+    }
+
+    pub fn set_src_port(&mut self, port: u16) {
+        put_u16(self.buf.as_mut(), 0, port);
+    }
+
+    pub fn set_dst_port(&mut self, port: u16) {
+        put_u16(self.buf.as_mut(), 2, port);
+    }
+
+    /// Set the datagram length, which must cover the header and fit in the buffer.
+    pub fn set_len(&mut self, len: u16) -> Result<(), Error> {
+        if (len as usize) < HEADER_LEN || len as usize > self.buf.as_ref().len() {
+            return Err(Error::InvalidArgs);
+        }
+        put_u16(self.buf.as_mut(), 4, len);
+        Ok(())
+    }
+
+    /// Compute the checksum over the datagram and the IP pseudo header sum.
+    pub fn fill_checksum(&mut self, pseudo: u32) {
+        let len = self.len() as usize;
+        let d = self.buf.as_mut();
+        put_u16(d, 6, 0);
+        let csum = match checksum::finish(checksum::sum(&d[..len], pseudo)) {
+            0 => 0xffff,
+            c => c,
+        };
+        put_u16(d, 6, csum);
+    }
+
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        let len = self.len() as usize;
+        &mut self.buf.as_mut()[HEADER_LEN..len]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::packet::fuzz;
+
+    const PSEUDO: u32 = 0x1234;
+
+    fn sample() -> [u8; 16] {
+        let mut buf = [0u8; 16];
+        let mut dgram = UdpDatagram::new_unchecked(&mut buf[..]);
+        dgram.init(68, 67, 8);
+        dgram.payload_mut().copy_from_slice(b"12345678");
+        dgram.fill_checksum(PSEUDO);
+        buf
+    }
+
+    #[test]
+    fn round_trip() {
+        let buf = sample();
+        let dgram = UdpDatagram::new_checked(&buf[..]).unwrap();
+        assert_eq!(dgram.src_port(), 68);
+        assert_eq!(dgram.dst_port(), 67);
+        assert_eq!(dgram.len(), 16);
+        assert_ne!(dgram.checksum(), 0);
+        assert!(dgram.verify_checksum(PSEUDO));
+        assert!(!dgram.verify_checksum(PSEUDO + 1));
+        assert_eq!(dgram.payload(), b"12345678");
+    }
+
+    #[test]
+    fn set_len_keeps_view_valid() {
+        let mut buf = sample();
+        let mut dgram = UdpDatagram::new_checked(&mut buf[..]).unwrap();
+        assert_eq!(dgram.set_len(17), Err(Error::InvalidArgs));
+        assert_eq!(dgram.set_len(7), Err(Error::InvalidArgs));
+        assert_eq!(dgram.len(), 16);
+        dgram.set_len(12).unwrap();
+        assert_eq!(dgram.payload(), b"1234");
+    }
+
+    #[test]
+    fn malformed_input_never_panics() {
+        fuzz::mangle(&sample(), |data| {
+            if let Ok(dgram) = UdpDatagram::new_checked(data) {
+                let _ = (dgram.src_port(), dgram.dst_port(), dgram.verify_checksum(PSEUDO));
+                let _ = dgram.payload();
+            }
+        });
+    }
+}
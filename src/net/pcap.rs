@@ -0,0 +1,221 @@
+//! Packet capture in pcapng format.
+//!
+//! [`PcapngWriter`] emits a single-section, single-interface Ethernet capture with microsecond
+//! timestamps into any [`ByteSink`]. [`NetTap`] wraps a [`NetIo`] device such as `NetClient` and
+//! mirrors every frame it sends or receives into a writer.
+
+use crate::interface::NetIo;
+use crate::net::packet::EthernetFrame;
+use crate::protocol::net::TxOffload;
+use crate::sink::ByteSink;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use glenda::error::Error;
+use glenda::io::uring::IoUringCqe;
+
+const BLOCK_SHB: u32 = 0x0A0D_0D0A;
+const BLOCK_IDB: u32 = 0x0000_0001;
+const BLOCK_EPB: u32 = 0x0000_0006;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+const LINKTYPE_ETHERNET: u16 = 1;
+const OPT_ENDOFOPT: u16 = 0;
+const OPT_EPB_FLAGS: u16 = 2;
+
+pub const DEFAULT_SNAPLEN: u32 = 65535;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+pub struct PcapngWriter<S: ByteSink> {
+    sink: S,
+    snaplen: u32,
+}
+
+impl<S: ByteSink> PcapngWriter<S> {
+    /// Start a capture, writing the section and interface headers.
+    pub fn new(mut sink: S, snaplen: u32) -> Result<Self, Error> {
+        let mut shb = Vec::with_capacity(28);
+        shb.extend_from_slice(&BLOCK_SHB.to_le_bytes());
+        shb.extend_from_slice(&28u32.to_le_bytes());
+        shb.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        shb.extend_from_slice(&1u16.to_le_bytes());
+        shb.extend_from_slice(&0u16.to_le_bytes());
+        // Section length unknown.
+        shb.extend_from_slice(&u64::MAX.to_le_bytes());
+        shb.extend_from_slice(&28u32.to_le_bytes());
+        sink.write_all(&shb)?;
+
+        let mut idb = Vec::with_capacity(20);
+        idb.extend_from_slice(&BLOCK_IDB.to_le_bytes());
+        idb.extend_from_slice(&20u32.to_le_bytes());
+        idb.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        idb.extend_from_slice(&0u16.to_le_bytes());
+        idb.extend_from_slice(&snaplen.to_le_bytes());
+        idb.extend_from_slice(&20u32.to_le_bytes());
+        sink.write_all(&idb)?;
+
+        Ok(Self { sink, snaplen })
+    }
+
+    /// Append an Enhanced Packet Block. `ts_us` is microseconds since the UNIX epoch.
+    pub fn write_packet(&mut self, ts_us: u64, dir: Direction, data: &[u8]) -> Result<(), Error> {
+        let cap_len = core::cmp::min(data.len(), self.snaplen as usize);
+        let padded = (cap_len + 3) & !3;
+        // 28 bytes of fixed fields, data, epb_flags option (8) and end of options (4), trailer (4).
+        let total = (28 + padded + 12 + 4) as u32;
+        let flags: u32 = match dir {
+            Direction::Inbound => 0b01,
+            Direction::Outbound => 0b10,
+        };
+
+        let mut hdr = Vec::with_capacity(28);
+        hdr.extend_from_slice(&BLOCK_EPB.to_le_bytes());
+        hdr.extend_from_slice(&total.to_le_bytes());
+        hdr.extend_from_slice(&0u32.to_le_bytes());
+        hdr.extend_from_slice(&((ts_us >> 32) as u32).to_le_bytes());
+        hdr.extend_from_slice(&(ts_us as u32).to_le_bytes());
+        hdr.extend_from_slice(&(cap_len as u32).to_le_bytes());
+        hdr.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        self.sink.write_all(&hdr)?;
+        self.sink.write_all(&data[..cap_len])?;
+
+        let mut tail = Vec::with_capacity(padded - cap_len + 16);
+        tail.resize(padded - cap_len, 0);
+        tail.extend_from_slice(&OPT_EPB_FLAGS.to_le_bytes());
+        tail.extend_from_slice(&4u16.to_le_bytes());
+        tail.extend_from_slice(&flags.to_le_bytes());
+        tail.extend_from_slice(&OPT_ENDOFOPT.to_le_bytes());
+        tail.extend_from_slice(&0u16.to_le_bytes());
+        tail.extend_from_slice(&total.to_le_bytes());
+        self.sink.write_all(&tail)
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.sink.flush()
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+/// A [`NetIo`] wrapper that mirrors traffic into a pcapng capture.
+///
+/// `clock` returns the current time in microseconds. Capture errors never fail the data path;
+/// they are counted and can be read back with `capture_errors`.
+pub struct NetTap<D: NetIo, S: ByteSink, C: Fn() -> u64> {
+    dev: D,
+    writer: RefCell<PcapngWriter<S>>,
+    clock: C,
+    filter: Option<Vec<u16>>,
+    posted: RefCell<Vec<(u64, *const u8)>>,
+    errors: Cell<u64>,
+}
+
+impl<D: NetIo, S: ByteSink, C: Fn() -> u64> NetTap<D, S, C> {
+    pub fn new(dev: D, writer: PcapngWriter<S>, clock: C) -> Self {
+        Self {
+            dev,
+            writer: RefCell::new(writer),
+            clock,
+            filter: None,
+            posted: RefCell::new(Vec::new()),
+            errors: Cell::new(0),
+        }
+    }
+
+    /// Only capture frames whose EtherType (after any VLAN tags) is in `ethertypes`.
+    /// `None` captures everything.
+    pub fn set_ethertype_filter(&mut self, ethertypes: Option<Vec<u16>>) {
+        self.filter = ethertypes;
+    }
+
+    pub fn device(&self) -> &D {
+        &self.dev
+    }
+
+    pub fn capture_errors(&self) -> u64 {
+        self.errors.get()
+    }
+
+    pub fn flush(&self) -> Result<(), Error> {
+        self.writer.borrow_mut().flush()
+    }
+
+    /// Stop capturing, returning the device and the writer.
+    pub fn into_parts(self) -> (D, PcapngWriter<S>) {
+        (self.dev, self.writer.into_inner())
+    }
+
+    fn capture(&self, dir: Direction, frame: &[u8]) {
+        if let Some(filter) = &self.filter {
+            match EthernetFrame::new_checked(frame) {
+                Ok(eth) if filter.contains(&eth.ethertype()) => {}
+                _ => return,
+            }
+        }
+        let ts = (self.clock)();
+        if self.writer.borrow_mut().write_packet(ts, dir, frame).is_err() {
+            self.errors.set(self.errors.get() + 1);
+        }
+    }
+
+    /// Capture the frame of a receive completion before handing the completion on.
+    fn reaped(&self, cqe: IoUringCqe) -> IoUringCqe {
+        let posted = {
+            let mut posted = self.posted.borrow_mut();
+            let idx = posted.iter().position(|(id, _)| *id == cqe.user_data);
+            idx.map(|i| posted.swap_remove(i).1)
+        };
+        if let Some(ptr) = posted
+            && cqe.res > 0
+        {
+            // The poster keeps the buffer valid until this completion has been returned.
+            let frame = unsafe { core::slice::from_raw_parts(ptr, cqe.res as usize) };
+            self.capture(Direction::Inbound, frame);
+        }
+        cqe
+    }
+}
+
+impl<D: NetIo, S: ByteSink, C: Fn() -> u64> NetIo for NetTap<D, S, C> {
+    fn send_packet(&self, buf: &[u8]) -> Result<(), Error> {
+        self.dev.send_packet(buf)?;
+        self.capture(Direction::Outbound, buf);
+        Ok(())
+    }
+
+    unsafe fn submit_recv(&self, buf: &mut [u8], id: u64) -> Result<(), Error> {
+        let ptr = buf.as_ptr();
+        unsafe { self.dev.submit_recv(buf, id)? };
+        self.posted.borrow_mut().push((id, ptr));
+        Ok(())
+    }
+
+    fn peek_cqe(&self) -> Option<IoUringCqe> {
+        self.dev.peek_cqe().map(|cqe| self.reaped(cqe))
+    }
+
+    fn take_recv_cqe(&self, id: u64) -> Option<IoUringCqe> {
+        self.dev.take_recv_cqe(id).map(|cqe| self.reaped(cqe))
+    }
+
+    fn offload_caps(&self) -> u32 {
+        self.dev.offload_caps()
+    }
+
+    fn send_packet_offload(&self, buf: &mut [u8], offload: TxOffload) -> Result<(), Error> {
+        self.dev.send_packet_offload(buf, offload)?;
+        self.capture(Direction::Outbound, buf);
+        Ok(())
+    }
+
+    fn send_packet_timestamped(&self, buf: &mut [u8], len: usize) -> Result<u64, Error> {
+        let ts = self.dev.send_packet_timestamped(buf, len)?;
+        self.capture(Direction::Outbound, &buf[..len]);
+        Ok(ts)
+    }
+}
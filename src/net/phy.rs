@@ -0,0 +1,371 @@
+//! smoltcp `phy::Device` implementation over a connected [`NetClient`] or any other [`NetIo`]
+//! device.
+//!
+//! The client's shared memory buffer is carved into fixed-size slots. RX slots stay posted on
+//! the ring and are handed to smoltcp as they complete; TX slots are recycled once the driver
+//! reports the send as done. Other devices get heap-allocated slots and send synchronously.
+
+use crate::client::net::NetClient;
+use crate::interface::{NetDriver, NetIo};
+use crate::protocol::net::{self as proto, TxOffload};
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use glenda::error::Error;
+use smoltcp::phy::{self, Checksum, ChecksumCapabilities, DeviceCapabilities, Medium};
+use smoltcp::time::Instant;
+
+/// Ethernet II header length, added on top of the IP MTU.
+pub const ETH_HDR_LEN: usize = 14;
+/// IP MTU assumed when the driver cannot report one.
+pub const DEFAULT_MTU: usize = 1500;
+
+// Completion routing: the upper bits of `user_data` tell RX and TX slots apart.
+const RX_TAG: u64 = 0x1 << 48;
+const TX_TAG: u64 = 0x2 << 48;
+const TAG_MASK: u64 = 0xffff << 48;
+
+/// Hands a filled TX slot to the device. Returns true if the slot is released by a later
+/// completion, false if it can be reused right away.
+type SendFn<D> = fn(&D, &mut [u8], u64, TxOffload) -> Result<bool, Error>;
+
+pub struct NetPhy<D: NetIo = NetClient> {
+    dev: D,
+    send: SendFn<D>,
+    base: usize,
+    /// Slot storage for devices without shared memory.
+    heap: Vec<u8>,
+    slot_size: usize,
+    rx_slots: usize,
+    tx_slots: usize,
+    rx_ready: VecDeque<(usize, usize)>,
+    tx_free: RefCell<VecDeque<usize>>,
+    mtu: usize,
+    checksum: ChecksumCapabilities,
+    tx_offload: TxOffload,
+}
+
+impl NetPhy<NetClient> {
+    /// Build a device over `client`, which must already be connected.
+    /// `rx_slots + tx_slots` frames must fit in the client's SHM buffer.
+    pub fn new(client: NetClient, rx_slots: usize, tx_slots: usize) -> Result<Self, Error> {
+        let shm = client.shm().ok_or(Error::NotInitialized)?;
+        if rx_slots == 0 || tx_slots == 0 {
+            return Err(Error::InvalidArgs);
+        }
+
+        let base = shm.vaddr();
+        let mtu = client.mtu().map(|m| m as usize).unwrap_or(DEFAULT_MTU);
+        if (rx_slots + tx_slots) * slot_size(mtu) > shm.size() {
+            return Err(Error::InvalidArgs);
+        }
+        let send: SendFn<NetClient> =
+            |client, buf, id, offload| client.submit_send_offload(buf, id, offload).map(|()| true);
+        Self::build(client, send, base, Vec::new(), mtu, rx_slots, tx_slots)
+    }
+
+    pub fn client(&self) -> &NetClient {
+        &self.dev
+    }
+
+    /// Block until the driver posts new completions.
+    pub fn wait(&self) -> Result<(), Error> {
+        self.dev.wait_for_completions()
+    }
+}
+
+impl<D: NetIo> NetPhy<D> {
+    /// Build a device over any [`NetIo`] with an IP MTU of `mtu`, allocating the slots on the
+    /// heap. Frames are sent with `send_packet_offload`, so TX slots are free again at once.
+    pub fn with_device(
+        dev: D,
+        mtu: usize,
+        rx_slots: usize,
+        tx_slots: usize,
+    ) -> Result<Self, Error> {
+        if rx_slots == 0 || tx_slots == 0 || mtu == 0 {
+            return Err(Error::InvalidArgs);
+        }
+        let mut heap = vec![0u8; (rx_slots + tx_slots) * slot_size(mtu)];
+        let base = heap.as_mut_ptr() as usize;
+        let send: SendFn<D> =
+            |dev, buf, _, offload| dev.send_packet_offload(buf, offload).map(|()| false);
+        Self::build(dev, send, base, heap, mtu, rx_slots, tx_slots)
+    }
+
+    pub fn device(&self) -> &D {
+        &self.dev
+    }
+
+    fn build(
+        dev: D,
+        send: SendFn<D>,
+        base: usize,
+        heap: Vec<u8>,
+        mtu: usize,
+        rx_slots: usize,
+        tx_slots: usize,
+    ) -> Result<Self, Error> {
+        // The stack always verifies RX checksums; TX checksums are left to the NIC only when it
+        // offloads them. L4 offload is used only if it covers both TCP and UDP, since the SQE
+        // request flag does not distinguish the two.
+        let caps = dev.offload_caps();
+        let l4_caps = proto::OFFLOAD_TX_CSUM_TCP | proto::OFFLOAD_TX_CSUM_UDP;
+        let tx_l3 = caps & proto::OFFLOAD_TX_CSUM_IPV4 != 0;
+        let tx_l4 = caps & l4_caps == l4_caps;
+        let mut tx_offload = TxOffload::default();
+        if tx_l3 {
+            tx_offload.flags |= proto::TX_CSUM_L3;
+        }
+        if tx_l4 {
+            tx_offload.flags |= proto::TX_CSUM_L4;
+        }
+
+        let mut checksum = ChecksumCapabilities::default();
+        checksum.ipv4 = if tx_l3 { Checksum::Rx } else { Checksum::Both };
+        checksum.udp = if tx_l4 { Checksum::Rx } else { Checksum::Both };
+        checksum.tcp = if tx_l4 { Checksum::Rx } else { Checksum::Both };
+        checksum.icmpv4 = Checksum::Both;
+
+        let phy = Self {
+            dev,
+            send,
+            base,
+            heap,
+            slot_size: slot_size(mtu),
+            rx_slots,
+            tx_slots,
+            rx_ready: VecDeque::with_capacity(rx_slots),
+            tx_free: RefCell::new((rx_slots..rx_slots + tx_slots).collect()),
+            mtu,
+            checksum,
+            tx_offload,
+        };
+
+        for slot in 0..rx_slots {
+            phy.post_rx(slot)?;
+        }
+        Ok(phy)
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn slot(&self, slot: usize) -> &mut [u8] {
+        // Slots are disjoint regions of the SHM or heap buffer, and each one is owned by exactly
+        // one of: the driver, a pending token, or the free list.
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                (self.base + slot * self.slot_size) as *mut u8,
+                self.slot_size,
+            )
+        }
+    }
+
+    fn post_rx(&self, slot: usize) -> Result<(), Error> {
+        // The slot is not touched again until its completion is reaped, and the buffer is
+        // leaked on drop rather than freed under the device.
+        unsafe { self.dev.submit_recv(self.slot(slot), RX_TAG | slot as u64) }
+    }
+
+    fn poll_completions(&mut self) {
+        while let Some(cqe) = self.dev.peek_cqe() {
+            let slot = (cqe.user_data & !TAG_MASK) as usize;
+            match cqe.user_data & TAG_MASK {
+                RX_TAG if slot < self.rx_slots => {
+                    // Frames the NIC flagged as corrupt are recycled right away.
+                    if cqe.res > 0 && cqe.flags & proto::RX_CSUM_BAD == 0 {
+                        self.rx_ready.push_back((slot, cqe.res as usize));
+                    } else {
+                        let _ = self.post_rx(slot);
+                    }
+                }
+                TX_TAG if slot >= self.rx_slots => self.tx_free.get_mut().push_back(slot),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<D: NetIo> Drop for NetPhy<D> {
+    fn drop(&mut self) {
+        // RX slots stay posted for the life of the device, which may outlive us.
+        core::mem::forget(core::mem::take(&mut self.heap));
+    }
+}
+
+/// Slot size for an IP MTU: the Ethernet frame rounded up to a cache line.
+fn slot_size(mtu: usize) -> usize {
+    (mtu + ETH_HDR_LEN + 63) & !63
+}
+
+pub struct NetRxToken<'a, D: NetIo = NetClient> {
+    phy: &'a NetPhy<D>,
+    slot: usize,
+    len: usize,
+}
+
+impl<D: NetIo> phy::RxToken for NetRxToken<'_, D> {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        let buf = self.phy.slot(self.slot);
+        let len = core::cmp::min(self.len, buf.len());
+        let r = f(&buf[..len]);
+        let _ = self.phy.post_rx(self.slot);
+        r
+    }
+}
+
+pub struct NetTxToken<'a, D: NetIo = NetClient> {
+    phy: &'a NetPhy<D>,
+}
+
+impl<D: NetIo> phy::TxToken for NetTxToken<'_, D> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let slot = self.phy.tx_free.borrow_mut().pop_front();
+        match slot {
+            Some(slot) => {
+                let buf = self.phy.slot(slot);
+                let len = core::cmp::min(len, buf.len());
+                let r = f(&mut buf[..len]);
+                let id = TX_TAG | slot as u64;
+                let pending =
+                    (self.phy.send)(&self.phy.dev, &mut buf[..len], id, self.phy.tx_offload);
+                if !matches!(pending, Ok(true)) {
+                    self.phy.tx_free.borrow_mut().push_back(slot);
+                }
+                r
+            }
+            None => {
+                // Ring is full: let the stack build the frame, then drop it.
+                let mut scratch = vec![0u8; len];
+                f(&mut scratch)
+            }
+        }
+    }
+}
+
+impl<D: NetIo> phy::Device for NetPhy<D> {
+    type RxToken<'a>
+        = NetRxToken<'a, D>
+    where
+        Self: 'a;
+    type TxToken<'a>
+        = NetTxToken<'a, D>
+    where
+        Self: 'a;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        self.poll_completions();
+        let (slot, len) = self.rx_ready.pop_front()?;
+        Some((NetRxToken { phy: self, slot, len }, NetTxToken { phy: self }))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        self.poll_completions();
+        if self.tx_free.get_mut().is_empty() {
+            return None;
+        }
+        Some(NetTxToken { phy: self })
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.medium = Medium::Ethernet;
+        caps.max_transmission_unit = self.mtu + ETH_HDR_LEN;
+        caps.max_burst_size = Some(self.tx_slots);
+        caps.checksum = self.checksum.clone();
+        caps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::virt::{LinkConfig, VirtNet};
+    use crate::protocol::net::MacAddress;
+    use smoltcp::iface::{Config, Interface, SocketSet};
+    use smoltcp::socket::tcp;
+    use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr};
+
+    struct Host {
+        phy: NetPhy<VirtNet>,
+        iface: Interface,
+        sockets: SocketSet<'static>,
+        handle: smoltcp::iface::SocketHandle,
+    }
+
+    impl Host {
+        fn new(dev: VirtNet, ip: IpAddress) -> Self {
+            let mac = dev.mac_address().octets;
+            let mut phy = NetPhy::with_device(dev, DEFAULT_MTU, 4, 4).unwrap();
+            let config = Config::new(HardwareAddress::Ethernet(EthernetAddress(mac)));
+            let mut iface = Interface::new(config, &mut phy, Instant::ZERO);
+            iface.update_ip_addrs(|addrs| addrs.push(IpCidr::new(ip, 24)).unwrap());
+            let socket = tcp::Socket::new(
+                tcp::SocketBuffer::new(vec![0; 1024]),
+                tcp::SocketBuffer::new(vec![0; 1024]),
+            );
+            let mut sockets = SocketSet::new(Vec::new());
+            let handle = sockets.add(socket);
+            Self { phy, iface, sockets, handle }
+        }
+
+        fn poll(&mut self, now: Instant) {
+            self.iface.poll(now, &mut self.phy, &mut self.sockets);
+        }
+
+        fn socket(&mut self) -> &mut tcp::Socket<'static> {
+            self.sockets.get_mut::<tcp::Socket>(self.handle)
+        }
+    }
+
+    #[test]
+    fn tcp_handshake_over_virt_pair() {
+        let mac_a = MacAddress::new([0x02, 0, 0, 0, 0, 0x0a]);
+        let mac_b = MacAddress::new([0x02, 0, 0, 0, 0, 0x0b]);
+        let (dev_a, dev_b) = VirtNet::pair(mac_a, mac_b, LinkConfig::default());
+        let server_ip = IpAddress::v4(10, 0, 0, 1);
+        let mut server = Host::new(dev_a, server_ip);
+        let mut client = Host::new(dev_b, IpAddress::v4(10, 0, 0, 2));
+
+        server.socket().listen(80).unwrap();
+        let cx = client.iface.context();
+        client
+            .sockets
+            .get_mut::<tcp::Socket>(client.handle)
+            .connect(cx, (server_ip, 80), 49152)
+            .unwrap();
+
+        for ms in 0..100 {
+            let now = Instant::from_millis(ms);
+            client.poll(now);
+            server.poll(now);
+            if client.socket().state() == tcp::State::Established
+                && server.socket().state() == tcp::State::Established
+            {
+                break;
+            }
+        }
+        assert_eq!(client.socket().state(), tcp::State::Established);
+        assert_eq!(server.socket().state(), tcp::State::Established);
+
+        client.socket().send_slice(b"hello").unwrap();
+        let mut buf = [0u8; 8];
+        let mut n = 0;
+        for ms in 100..200 {
+            let now = Instant::from_millis(ms);
+            client.poll(now);
+            server.poll(now);
+            if server.socket().can_recv() {
+                n = server.socket().recv_slice(&mut buf).unwrap();
+                break;
+            }
+        }
+        assert_eq!(&buf[..n], b"hello");
+    }
+}
@@ -0,0 +1,519 @@
+//! IEEE 1588 PTP clock servo and a minimal slave built on NIC hardware timestamps.
+//!
+//! [`PtpServo`] is a PI controller turning offset samples into clock steps and frequency
+//! corrections. [`PtpSlave`] is a small example of its use: an end-to-end, two-step aware PTPv2
+//! slave over raw Ethernet (EtherType 0x88F7) that disciplines the NIC's PTP hardware clock.
+//! It follows the first master it hears and implements no best master clock algorithm.
+//!
+//! The device must support hardware timestamps (`net::OFFLOAD_TIMESTAMP`) with receive
+//! timestamping enabled for PTP frames, e.g. `set_rx_timestamping(net::RX_TIMESTAMP_PTP)`.
+//! It must also be able to receive into the slave's heap buffer, which rules out a plain
+//! `NetClient`: its driver only writes into the client's shared memory.
+
+use crate::interface::{NetDriver, NetIo};
+use crate::net::MAX_TAGGED_FRAME;
+use crate::net::packet::ethernet::{self, EthernetFrame};
+use crate::net::packet::{get_u16, put_u16};
+use crate::net::recv::RxSlot;
+use crate::protocol::net::{self, MacAddress};
+use alloc::vec;
+use alloc::vec::Vec;
+use glenda::error::Error;
+
+pub const ETHERTYPE_PTP: u16 = 0x88F7;
+/// Multicast address for PTP messages other than peer delay.
+pub const PTP_MULTICAST: MacAddress = MacAddress::new([0x01, 0x1b, 0x19, 0x00, 0x00, 0x00]);
+
+pub const MSG_SYNC: u8 = 0x0;
+pub const MSG_DELAY_REQ: u8 = 0x1;
+pub const MSG_FOLLOW_UP: u8 = 0x8;
+pub const MSG_DELAY_RESP: u8 = 0x9;
+
+const HEADER_LEN: usize = 34;
+const TIMESTAMP_LEN: usize = 10;
+const PORT_ID_LEN: usize = 10;
+const FLAG_TWO_STEP: u16 = 1 << 9;
+const NS_PER_SEC: u64 = 1_000_000_000;
+
+/// Receive buffer size, leaving room for the hardware timestamp stored past the frame.
+pub const DEFAULT_FRAME_SIZE: usize = MAX_TAGGED_FRAME + net::TIMESTAMP_LEN;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServoAction {
+    /// Step the clock by this many nanoseconds.
+    Step(i64),
+    /// Set the clock frequency offset to this many parts per billion.
+    Adjust(i64),
+}
+
+/// Proportional-integral clock servo.
+///
+/// Offsets are `slave - master` in nanoseconds. Offsets larger than the step threshold are
+/// corrected by stepping the clock; smaller ones by steering its frequency.
+#[derive(Debug, Clone)]
+pub struct PtpServo {
+    kp: f64,
+    ki: f64,
+    step_threshold: i64,
+    max_ppb: f64,
+    integral: f64,
+}
+
+impl Default for PtpServo {
+    fn default() -> Self {
+        Self::new(0.7, 0.3)
+    }
+}
+
+impl PtpServo {
+    pub fn new(kp: f64, ki: f64) -> Self {
+        Self { kp, ki, step_threshold: 1_000_000, max_ppb: 500_000.0, integral: 0.0 }
+    }
+
+    /// Offsets above `ns` in magnitude are stepped instead of slewed. Defaults to 1 ms.
+    pub fn set_step_threshold(&mut self, ns: i64) {
+        self.step_threshold = ns;
+    }
+
+    /// Clamp frequency corrections to `ppb`. Defaults to 500 ppm.
+    pub fn set_max_frequency(&mut self, ppb: i64) {
+        self.max_ppb = ppb as f64;
+    }
+
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+    }
+
+    /// Feed one offset sample and return the correction to apply.
+    pub fn sample(&mut self, offset_ns: i64) -> ServoAction {
+        if offset_ns.unsigned_abs() > self.step_threshold as u64 {
+            self.integral = 0.0;
+            return ServoAction::Step(-offset_ns);
+        }
+        let offset = offset_ns as f64;
+        self.integral = (self.integral + self.ki * offset).clamp(-self.max_ppb, self.max_ppb);
+        let ppb = (self.kp * offset + self.integral).clamp(-self.max_ppb, self.max_ppb);
+        ServoAction::Adjust(-(ppb as i64))
+    }
+}
+
+/// A parsed PTP message, reduced to the fields the slave needs.
+struct Message {
+    kind: u8,
+    flags: u16,
+    correction: i64,
+    source: [u8; PORT_ID_LEN],
+    seq: u16,
+    timestamp: u64,
+    requester: Option<[u8; PORT_ID_LEN]>,
+}
+
+impl Message {
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < HEADER_LEN + TIMESTAMP_LEN || data[1] & 0x0f != 2 {
+            return None;
+        }
+        let kind = data[0] & 0x0f;
+        let mut source = [0u8; PORT_ID_LEN];
+        source.copy_from_slice(&data[20..30]);
+        let requester = if kind == MSG_DELAY_RESP {
+            let id = data.get(44..44 + PORT_ID_LEN)?;
+            Some(id.try_into().ok()?)
+        } else {
+            None
+        };
+        let correction = i64::from_be_bytes(data[8..16].try_into().ok()?);
+        Some(Self {
+            kind,
+            flags: get_u16(data, 6),
+            // The correction field is in nanoseconds scaled by 2^16.
+            correction: correction >> 16,
+            source,
+            seq: get_u16(data, 30),
+            timestamp: read_timestamp(&data[HEADER_LEN..]),
+            requester,
+        })
+    }
+}
+
+fn read_timestamp(b: &[u8]) -> u64 {
+    let mut sec = [0u8; 8];
+    sec[2..].copy_from_slice(&b[0..6]);
+    let nsec = u32::from_be_bytes([b[6], b[7], b[8], b[9]]);
+    u64::from_be_bytes(sec) * NS_PER_SEC + nsec as u64
+}
+
+/// EUI-64 clock identity derived from the interface MAC.
+fn clock_identity(mac: MacAddress) -> [u8; 8] {
+    let m = mac.octets;
+    [m[0], m[1], m[2], 0xff, 0xfe, m[3], m[4], m[5]]
+}
+
+pub struct PtpSlave<D: NetIo + NetDriver> {
+    dev: D,
+    mac: MacAddress,
+    port_id: [u8; PORT_ID_LEN],
+    domain: u8,
+    servo: PtpServo,
+    rx: RxSlot,
+    tx_buf: Vec<u8>,
+    master: Option<[u8; PORT_ID_LEN]>,
+    /// Two-step Sync awaiting its Follow_Up: (sequence, t2, Sync correction).
+    pending_sync: Option<(u16, u64, i64)>,
+    /// Last master-to-slave difference `t2 - t1`.
+    ms_diff: Option<i64>,
+    /// Outstanding Delay_Req: (sequence, t3).
+    delay_req: Option<(u16, u64)>,
+    delay_seq: u16,
+    path_delay: Option<i64>,
+}
+
+impl<D: NetIo + NetDriver> PtpSlave<D> {
+    pub fn new(dev: D, domain: u8) -> Self {
+        let mac = dev.mac_address();
+        let mut port_id = [0u8; PORT_ID_LEN];
+        port_id[..8].copy_from_slice(&clock_identity(mac));
+        port_id[9] = 1;
+        Self {
+            dev,
+            mac,
+            port_id,
+            domain,
+            servo: PtpServo::default(),
+            rx: RxSlot::new(DEFAULT_FRAME_SIZE),
+            tx_buf: vec![0u8; 64 + net::TIMESTAMP_LEN],
+            master: None,
+            pending_sync: None,
+            ms_diff: None,
+            delay_req: None,
+            delay_seq: 0,
+            path_delay: None,
+        }
+    }
+
+    pub fn servo_mut(&mut self) -> &mut PtpServo {
+        &mut self.servo
+    }
+
+    pub fn device(&self) -> &D {
+        &self.dev
+    }
+
+    /// Mean path delay to the master in nanoseconds, once measured.
+    pub fn path_delay(&self) -> Option<i64> {
+        self.path_delay
+    }
+
+    /// Process all pending frames. Returns the last offset from the master fed to the servo.
+    pub fn poll(&mut self) -> Result<Option<i64>, Error> {
+        let mut last = None;
+        loop {
+            let Some(cqe) = self.rx.poll(&self.dev, 0)? else {
+                return Ok(last);
+            };
+            let Some(rx_ts) = net::cqe_timestamp(&cqe, self.rx.buf()) else {
+                continue;
+            };
+            let Ok(frame) = EthernetFrame::new_checked(self.rx.frame(&cqe)) else {
+                continue;
+            };
+            if frame.ethertype() != ETHERTYPE_PTP {
+                continue;
+            }
+            let Some(msg) = Message::parse(frame.payload()) else {
+                continue;
+            };
+            if frame.payload()[4] != self.domain {
+                continue;
+            }
+            if let Some(offset) = self.handle(msg, rx_ts)? {
+                last = Some(offset);
+            }
+        }
+    }
+
+    fn handle(&mut self, msg: Message, rx_ts: u64) -> Result<Option<i64>, Error> {
+        let master = *self.master.get_or_insert(msg.source);
+        if msg.source != master {
+            return Ok(None);
+        }
+        match msg.kind {
+            MSG_SYNC if msg.flags & FLAG_TWO_STEP != 0 => {
+                self.pending_sync = Some((msg.seq, rx_ts, msg.correction));
+                Ok(None)
+            }
+            MSG_SYNC => self.on_sync(msg.timestamp as i64 + msg.correction, rx_ts),
+            MSG_FOLLOW_UP => match self.pending_sync.take() {
+                Some((seq, t2, corr)) if seq == msg.seq => {
+                    self.on_sync(msg.timestamp as i64 + corr + msg.correction, t2)
+                }
+                _ => Ok(None),
+            },
+            MSG_DELAY_RESP => {
+                let (Some((seq, t3)), Some(ms)) = (self.delay_req, self.ms_diff) else {
+                    return Ok(None);
+                };
+                if msg.seq != seq || msg.requester != Some(self.port_id) {
+                    return Ok(None);
+                }
+                self.delay_req = None;
+                let t4 = msg.timestamp as i64 - msg.correction;
+                self.path_delay = Some((ms + (t4 - t3 as i64)) / 2);
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Handle a Sync with master origin time `t1` received at local time `t2`.
+    fn on_sync(&mut self, t1: i64, t2: u64) -> Result<Option<i64>, Error> {
+        let ms = t2 as i64 - t1;
+        self.ms_diff = Some(ms);
+        let offset = self.path_delay.map(|delay| ms - delay);
+        if let Some(offset) = offset {
+            match self.servo.sample(offset) {
+                ServoAction::Step(delta) => {
+                    self.dev.adjust_ptp_time(delta)?;
+                    // Timestamps taken before the step are no longer comparable.
+                    self.path_delay = None;
+                    self.delay_req = None;
+                }
+                ServoAction::Adjust(ppb) => self.dev.adjust_ptp_frequency(ppb)?,
+            }
+        }
+        self.send_delay_req()?;
+        Ok(offset)
+    }
+
+    fn send_delay_req(&mut self) -> Result<(), Error> {
+        self.delay_seq = self.delay_seq.wrapping_add(1);
+        let len = HEADER_LEN + TIMESTAMP_LEN;
+        let buf = &mut self.tx_buf;
+        buf.fill(0);
+        let off = ethernet::emit_header(buf, PTP_MULTICAST, self.mac, None, ETHERTYPE_PTP)?;
+        let msg = &mut buf[off..off + len];
+        msg[0] = MSG_DELAY_REQ;
+        msg[1] = 2;
+        put_u16(msg, 2, len as u16);
+        msg[4] = self.domain;
+        msg[20..30].copy_from_slice(&self.port_id);
+        put_u16(msg, 30, self.delay_seq);
+        msg[32] = 1;
+        msg[33] = 0x7f;
+        // Pad to the Ethernet minimum; the timestamp lands right after the frame.
+        let frame_len = core::cmp::max(off + len, 60);
+        let t3 = self.dev.send_packet_timestamped(buf, frame_len)?;
+        self.delay_req = Some((self.delay_seq, t3));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::recv::RecvQueue;
+    use alloc::collections::VecDeque;
+    use core::cell::{Cell, RefCell};
+    use glenda::io::uring::IoUringCqe;
+
+    #[test]
+    fn servo_steps_large_offsets() {
+        let mut servo = PtpServo::default();
+        assert_eq!(servo.sample(2_000_000), ServoAction::Step(-2_000_000));
+        assert_eq!(servo.sample(-1_000_001), ServoAction::Step(1_000_001));
+        assert!(matches!(servo.sample(1_000_000), ServoAction::Adjust(_)));
+        servo.set_step_threshold(100);
+        assert_eq!(servo.sample(101), ServoAction::Step(-101));
+    }
+
+    #[test]
+    fn servo_slows_a_fast_clock() {
+        let mut servo = PtpServo::new(0.5, 0.25);
+        // A slave ahead of the master is slowed down, and the integral builds up.
+        assert_eq!(servo.sample(1000), ServoAction::Adjust(-750));
+        assert_eq!(servo.sample(1000), ServoAction::Adjust(-1000));
+        assert_eq!(servo.sample(-1000), ServoAction::Adjust(250));
+        servo.reset();
+        assert_eq!(servo.sample(-1000), ServoAction::Adjust(750));
+    }
+
+    #[test]
+    fn servo_clamps_the_correction_and_integral() {
+        let mut servo = PtpServo::new(0.5, 0.25);
+        servo.set_max_frequency(100);
+        assert_eq!(servo.sample(1000), ServoAction::Adjust(-100));
+        assert_eq!(servo.sample(1000), ServoAction::Adjust(-100));
+        // The integral stopped at 100 ppb, so a small negative sample brings it back quickly.
+        assert_eq!(servo.sample(-100), ServoAction::Adjust(-25));
+    }
+
+    #[test]
+    fn servo_step_resets_the_integral() {
+        let mut servo = PtpServo::new(0.5, 0.25);
+        servo.set_step_threshold(10_000);
+        servo.sample(1000);
+        servo.sample(1000);
+        assert_eq!(servo.sample(20_000), ServoAction::Step(-20_000));
+        assert_eq!(servo.sample(0), ServoAction::Adjust(0));
+    }
+
+    const MASTER_MAC: MacAddress = MacAddress::new([0x02, 0, 0, 0, 0, 0x01]);
+    const SLAVE_MAC: MacAddress = MacAddress::new([0x02, 0, 0, 0, 0, 0x10]);
+    const MASTER_PORT: [u8; PORT_ID_LEN] = [0x02, 0, 0, 0xff, 0xfe, 0, 0, 0x01, 0, 1];
+    const SLAVE_PORT: [u8; PORT_ID_LEN] = [0x02, 0, 0, 0xff, 0xfe, 0, 0, 0x10, 0, 1];
+
+    /// A NIC whose receive and transmit timestamps are scripted by the test.
+    #[derive(Default)]
+    struct Nic {
+        recv: RefCell<RecvQueue>,
+        /// Frames to deliver with their receive timestamp.
+        inbox: RefCell<VecDeque<(Vec<u8>, u64)>>,
+        sent: RefCell<Vec<Vec<u8>>>,
+        tx_timestamp: Cell<u64>,
+        steps: Vec<i64>,
+        ppb: Option<i64>,
+    }
+
+    impl NetIo for Nic {
+        fn send_packet(&self, _buf: &[u8]) -> Result<(), Error> {
+            Err(Error::InvalidArgs)
+        }
+
+        unsafe fn submit_recv(&self, buf: &mut [u8], id: u64) -> Result<(), Error> {
+            unsafe { self.recv.borrow_mut().post(buf, id) }
+        }
+
+        fn peek_cqe(&self) -> Option<IoUringCqe> {
+            unimplemented!("PtpSlave only takes its own completions")
+        }
+
+        fn take_recv_cqe(&self, id: u64) -> Option<IoUringCqe> {
+            let (mut frame, ts) = self.inbox.borrow_mut().pop_front()?;
+            let len = frame.len();
+            frame.extend_from_slice(&ts.to_le_bytes());
+            let mut recv = self.recv.borrow_mut();
+            assert!(recv.deliver(&frame));
+            let cqe = recv.take_completion(id)?;
+            Some(IoUringCqe { res: len as i32, flags: net::CQE_TIMESTAMP, ..cqe })
+        }
+
+        fn send_packet_timestamped(&self, buf: &mut [u8], len: usize) -> Result<u64, Error> {
+            assert!(buf.len() >= len + net::TIMESTAMP_LEN);
+            self.sent.borrow_mut().push(buf[..len].to_vec());
+            Ok(self.tx_timestamp.get())
+        }
+    }
+
+    impl NetDriver for Nic {
+        fn mac_address(&self) -> MacAddress {
+            SLAVE_MAC
+        }
+
+        fn adjust_ptp_time(&mut self, delta_ns: i64) -> Result<(), Error> {
+            self.steps.push(delta_ns);
+            Ok(())
+        }
+
+        fn adjust_ptp_frequency(&mut self, ppb: i64) -> Result<(), Error> {
+            self.ppb = Some(ppb);
+            Ok(())
+        }
+    }
+
+    struct Msg {
+        kind: u8,
+        seq: u16,
+        two_step: bool,
+        correction: i64,
+        timestamp: u64,
+        requester: [u8; PORT_ID_LEN],
+    }
+
+    impl Msg {
+        fn new(kind: u8, seq: u16, timestamp: u64) -> Self {
+            Self { kind, seq, two_step: false, correction: 0, timestamp, requester: SLAVE_PORT }
+        }
+
+        fn correction(self, ns: i64) -> Self {
+            Self { correction: ns, ..self }
+        }
+
+        fn frame(&self) -> Vec<u8> {
+            let len = HEADER_LEN + TIMESTAMP_LEN + PORT_ID_LEN;
+            let mut buf = vec![0u8; ethernet::HEADER_LEN + len];
+            let off =
+                ethernet::emit_header(&mut buf, PTP_MULTICAST, MASTER_MAC, None, ETHERTYPE_PTP)
+                    .unwrap();
+            let msg = &mut buf[off..];
+            msg[0] = self.kind;
+            msg[1] = 2;
+            put_u16(msg, 2, len as u16);
+            if self.two_step {
+                put_u16(msg, 6, FLAG_TWO_STEP);
+            }
+            msg[8..16].copy_from_slice(&(self.correction << 16).to_be_bytes());
+            msg[20..30].copy_from_slice(&MASTER_PORT);
+            put_u16(msg, 30, self.seq);
+            let secs = self.timestamp / NS_PER_SEC;
+            msg[34..40].copy_from_slice(&secs.to_be_bytes()[2..]);
+            msg[40..44].copy_from_slice(&((self.timestamp % NS_PER_SEC) as u32).to_be_bytes());
+            msg[44..54].copy_from_slice(&self.requester);
+            buf
+        }
+    }
+
+    fn deliver(slave: &PtpSlave<Nic>, msg: Msg, rx_timestamp: u64) {
+        slave.device().inbox.borrow_mut().push_back((msg.frame(), rx_timestamp));
+    }
+
+    /// Check the last Delay_Req sent and return its sequence number.
+    fn delay_req(slave: &PtpSlave<Nic>) -> u16 {
+        let sent = slave.device().sent.borrow();
+        let eth = EthernetFrame::new_checked(&sent.last().unwrap()[..]).unwrap();
+        assert_eq!(
+            (eth.dst(), eth.src(), eth.ethertype()),
+            (PTP_MULTICAST, SLAVE_MAC, ETHERTYPE_PTP)
+        );
+        let msg = Message::parse(eth.payload()).unwrap();
+        assert_eq!((msg.kind, msg.source), (MSG_DELAY_REQ, SLAVE_PORT));
+        msg.seq
+    }
+
+    #[test]
+    fn slave_measures_offset_and_path_delay() {
+        // The slave clock is 500 ns ahead and the path takes 1000 ns each way.
+        let mut slave = PtpSlave::new(Nic::default(), 0);
+
+        // Two-step Sync; a transparent clock adds 40 + 60 ns of residence time.
+        let sync = Msg { two_step: true, ..Msg::new(MSG_SYNC, 1, 0) }.correction(40);
+        deliver(&slave, sync, 10_000 + 100 + 1000 + 500);
+        deliver(&slave, Msg::new(MSG_FOLLOW_UP, 1, 10_000).correction(60), 0);
+        slave.device().tx_timestamp.set(20_000);
+        // No path delay yet, so no offset; a Delay_Req goes out at slave time 20_000.
+        assert_eq!(slave.poll().unwrap(), None);
+        let seq = delay_req(&slave);
+
+        // It reaches the master at 20_000 - 500 + 1000, plus 200 ns in a transparent clock.
+        let resp = Msg::new(MSG_DELAY_RESP, seq, 20_700).correction(200);
+        deliver(&slave, Msg { requester: MASTER_PORT, ..resp }, 0);
+        deliver(&slave, Msg::new(MSG_DELAY_RESP, seq.wrapping_add(1), 20_700).correction(200), 0);
+        assert_eq!(slave.poll().unwrap(), None);
+        assert_eq!(slave.path_delay(), None);
+        deliver(&slave, Msg::new(MSG_DELAY_RESP, seq, 20_700).correction(200), 0);
+        slave.poll().unwrap();
+        assert_eq!(slave.path_delay(), Some(1000));
+
+        // One-step Sync: offset 500 is slewed by the servo.
+        deliver(&slave, Msg::new(MSG_SYNC, 2, 30_000), 30_000 + 1000 + 500);
+        assert_eq!(slave.poll().unwrap(), Some(500));
+        assert_eq!(slave.device().ppb, Some(-500));
+        assert_eq!(delay_req(&slave), seq.wrapping_add(1));
+        assert!(slave.device().steps.is_empty());
+
+        // A 2 ms offset is stepped, after which the path delay must be measured again.
+        deliver(&slave, Msg::new(MSG_SYNC, 3, 40_000), 40_000 + 1000 + 2_000_000);
+        assert_eq!(slave.poll().unwrap(), Some(2_000_000));
+        assert_eq!(slave.device().steps, [-2_000_000]);
+        assert_eq!(slave.path_delay(), None);
+    }
+}
@@ -0,0 +1,157 @@
+//! Receive-side plumbing shared by software [`NetIo`](crate::interface::NetIo) devices and the
+//! components that consume them.
+
+use crate::interface::NetIo;
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+use glenda::error::Error;
+use glenda::io::uring::IoUringCqe;
+
+/// A buffer handed over through `NetIo::submit_recv`.
+struct PostedBuf {
+    id: u64,
+    ptr: *mut u8,
+    len: usize,
+}
+
+/// Receive buffers posted to a software device and the completions produced for them.
+#[derive(Default)]
+pub(crate) struct RecvQueue {
+    posted: VecDeque<PostedBuf>,
+    completions: VecDeque<IoUringCqe>,
+}
+
+impl RecvQueue {
+    /// Queue `buf` for the next frame delivered.
+    ///
+    /// # Safety
+    ///
+    /// `buf` must meet the contract of `NetIo::submit_recv`.
+    pub(crate) unsafe fn post(&mut self, buf: &mut [u8], id: u64) -> Result<(), Error> {
+        if buf.is_empty() {
+            return Err(Error::InvalidArgs);
+        }
+        self.posted.push_back(PostedBuf { id, ptr: buf.as_mut_ptr(), len: buf.len() });
+        Ok(())
+    }
+
+    pub(crate) fn has_buffer(&self) -> bool {
+        !self.posted.is_empty()
+    }
+
+    /// Copy `frame` into the oldest posted buffer, truncating it to fit, and complete that
+    /// buffer. Returns false if no buffer is posted.
+    pub(crate) fn deliver(&mut self, frame: &[u8]) -> bool {
+        let Some(buf) = self.posted.pop_front() else {
+            return false;
+        };
+        let len = core::cmp::min(frame.len(), buf.len);
+        // `post` requires the buffer to stay valid and unaliased until its completion is reaped.
+        unsafe { core::ptr::copy_nonoverlapping(frame.as_ptr(), buf.ptr, len) };
+        self.completions.push_back(IoUringCqe {
+            user_data: buf.id,
+            res: len as i32,
+            ..Default::default()
+        });
+        true
+    }
+
+    pub(crate) fn pop_completion(&mut self) -> Option<IoUringCqe> {
+        self.completions.pop_front()
+    }
+
+    /// Take the completion for the buffer posted as `id`, leaving the others queued.
+    pub(crate) fn take_completion(&mut self, id: u64) -> Option<IoUringCqe> {
+        let pos = self.completions.iter().position(|cqe| cqe.user_data == id)?;
+        self.completions.remove(pos)
+    }
+}
+
+/// A single receive buffer kept posted on a device, for consumers that take frames one at a
+/// time. Only the completion for its own `id` is taken; others are left on the device.
+///
+/// The buffer is on the heap, so this only works over devices that fill it from this address
+/// space: software devices such as [`VirtNet`](super::virt::VirtNet),
+/// [`VlanInterface`](super::vlan::VlanInterface) or a UART `FramedLink`. A `NetClient` driver
+/// can only reach the client's shared memory.
+pub(crate) struct RxSlot {
+    buf: Vec<u8>,
+    posted: bool,
+}
+
+impl RxSlot {
+    pub(crate) fn new(size: usize) -> Self {
+        Self { buf: vec![0u8; size], posted: false }
+    }
+
+    /// Post the buffer on `dev` if it is not already, and return the next successful
+    /// completion. The frame then stays in the buffer until the next call.
+    pub(crate) fn poll<D: NetIo>(&mut self, dev: &D, id: u64) -> Result<Option<IoUringCqe>, Error> {
+        loop {
+            if !self.posted {
+                // The buffer is only touched again once its completion is reaped, and is
+                // leaked on drop if that never happens.
+                unsafe { dev.submit_recv(&mut self.buf, id)? };
+                self.posted = true;
+            }
+            let Some(cqe) = dev.take_recv_cqe(id) else {
+                return Ok(None);
+            };
+            self.posted = false;
+            if cqe.res > 0 {
+                return Ok(Some(cqe));
+            }
+        }
+    }
+
+    /// The received frame, clamped to the buffer.
+    pub(crate) fn frame(&self, cqe: &IoUringCqe) -> &[u8] {
+        &self.buf[..core::cmp::min(cqe.res.max(0) as usize, self.buf.len())]
+    }
+
+    /// The whole buffer, including anything the device stored past the frame.
+    pub(crate) fn buf(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub(crate) fn buf_mut(&mut self) -> &mut [u8] {
+        &mut self.buf
+    }
+}
+
+impl Drop for RxSlot {
+    fn drop(&mut self) {
+        if self.posted {
+            // The device may still write into it.
+            core::mem::forget(core::mem::take(&mut self.buf));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::virt::{LinkConfig, VirtNet};
+    use crate::protocol::net::MacAddress;
+
+    #[test]
+    fn rx_slot_leaves_other_completions() {
+        let mac = MacAddress { octets: [0x02, 0, 0, 0, 0, 1] };
+        let (a, b) = VirtNet::pair(mac, mac, LinkConfig::default());
+        // Another consumer posts first, so it gets the first frame.
+        let mut other = [0u8; 16];
+        unsafe { b.submit_recv(&mut other, 7).unwrap() };
+        let mut slot = RxSlot::new(16);
+        assert_eq!(slot.poll(&b, 1).unwrap().map(|cqe| cqe.user_data), None);
+
+        a.send_packet(b"first").unwrap();
+        a.send_packet(b"second").unwrap();
+        let cqe = slot.poll(&b, 1).unwrap().unwrap();
+        assert_eq!(cqe.user_data, 1);
+        assert_eq!(slot.frame(&cqe), b"second");
+        let cqe = b.peek_cqe().unwrap();
+        assert_eq!((cqe.user_data, cqe.res), (7, 5));
+        assert_eq!(&other[..5], b"first");
+    }
+}
@@ -0,0 +1,211 @@
+//! Registry assigning stable names and indexes to network interfaces.
+//!
+//! Interfaces are identified by their bus location when known, else by MAC address. An
+//! identity keeps its name and index when its NIC disappears and comes back, so `eth0` stays
+//! `eth0` across a driver restart. New identities get the lowest free number for their kind.
+
+use crate::protocol::net::MacAddress;
+use crate::protocol::pci::PciAddress;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use glenda::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterfaceKind {
+    Ethernet,
+    Wireless,
+    Loopback,
+}
+
+impl InterfaceKind {
+    pub const fn prefix(&self) -> &'static str {
+        match self {
+            Self::Ethernet => "eth",
+            Self::Wireless => "wlan",
+            Self::Loopback => "lo",
+        }
+    }
+}
+
+/// Hardware identity an interface name is bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HwKey {
+    Pci(PciAddress),
+    Mac(MacAddress),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceInfo {
+    /// Interface index, starting at 1.
+    pub index: u32,
+    pub name: String,
+    pub kind: InterfaceKind,
+    pub mac: MacAddress,
+    pub location: Option<PciAddress>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryEvent {
+    Added(InterfaceInfo),
+    Removed(InterfaceInfo),
+}
+
+/// Callback invoked on every interface addition and removal.
+pub type RegistryCallback = Arc<dyn Fn(&RegistryEvent) + Send + Sync>;
+
+/// A name reserved for a hardware identity, present or not.
+struct Binding {
+    key: HwKey,
+    kind: InterfaceKind,
+    name: String,
+    index: u32,
+}
+
+struct Entry<D> {
+    info: InterfaceInfo,
+    dev: D,
+}
+
+pub struct NetRegistry<D> {
+    bindings: Vec<Binding>,
+    present: Vec<Entry<D>>,
+    next_index: u32,
+    listeners: Vec<RegistryCallback>,
+}
+
+impl<D> Default for NetRegistry<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D> NetRegistry<D> {
+    pub fn new() -> Self {
+        Self { bindings: Vec::new(), present: Vec::new(), next_index: 1, listeners: Vec::new() }
+    }
+
+    /// Register a callback run on every `RegistryEvent`.
+    pub fn subscribe(&mut self, cb: RegistryCallback) {
+        self.listeners.push(cb);
+    }
+
+    /// Add an interface, naming it after its bus location if given, else its MAC address.
+    /// Fails if the same hardware is already present.
+    pub fn add(
+        &mut self,
+        dev: D,
+        kind: InterfaceKind,
+        mac: MacAddress,
+        location: Option<PciAddress>,
+    ) -> Result<InterfaceInfo, Error> {
+        let key = match location {
+            Some(loc) => HwKey::Pci(loc),
+            None => HwKey::Mac(mac),
+        };
+        if self.present.iter().any(|e| Self::key_of(&e.info) == key) {
+            return Err(Error::InvalidArgs);
+        }
+
+        let (name, index) = match self.bindings.iter().find(|b| b.key == key && b.kind == kind) {
+            Some(b) => (b.name.clone(), b.index),
+            None => {
+                let name = self.free_name(kind);
+                let index = self.next_index;
+                self.next_index += 1;
+                self.bindings.push(Binding { key, kind, name: name.clone(), index });
+                (name, index)
+            }
+        };
+
+        let info = InterfaceInfo { index, name, kind, mac, location };
+        self.present.push(Entry { info: info.clone(), dev });
+        self.notify(&RegistryEvent::Added(info.clone()));
+        Ok(info)
+    }
+
+    /// Remove the interface called `name`, returning its device. Its name stays reserved.
+    pub fn remove(&mut self, name: &str) -> Option<D> {
+        let pos = self.present.iter().position(|e| e.info.name == name)?;
+        let entry = self.present.remove(pos);
+        self.notify(&RegistryEvent::Removed(entry.info));
+        Some(entry.dev)
+    }
+
+    /// Remove the interface bound to `key`, e.g. when its NIC is unplugged.
+    pub fn remove_by_key(&mut self, key: HwKey) -> Option<D> {
+        let name = self.present.iter().find(|e| Self::key_of(&e.info) == key)?.info.name.clone();
+        self.remove(&name)
+    }
+
+    /// Drop the name reservation of an absent identity so its name can be reused.
+    pub fn forget(&mut self, key: HwKey) -> Result<(), Error> {
+        if self.present.iter().any(|e| Self::key_of(&e.info) == key) {
+            return Err(Error::InvalidArgs);
+        }
+        self.bindings.retain(|b| b.key != key);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&D> {
+        self.present.iter().find(|e| e.info.name == name).map(|e| &e.dev)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut D> {
+        self.present.iter_mut().find(|e| e.info.name == name).map(|e| &mut e.dev)
+    }
+
+    pub fn by_index(&self, index: u32) -> Option<(&InterfaceInfo, &D)> {
+        self.present.iter().find(|e| e.info.index == index).map(|e| (&e.info, &e.dev))
+    }
+
+    pub fn info(&self, name: &str) -> Option<&InterfaceInfo> {
+        self.present.iter().find(|e| e.info.name == name).map(|e| &e.info)
+    }
+
+    pub fn find_by_mac(&self, mac: MacAddress) -> Option<&InterfaceInfo> {
+        self.present.iter().find(|e| e.info.mac == mac).map(|e| &e.info)
+    }
+
+    pub fn find_by_location(&self, location: PciAddress) -> Option<&InterfaceInfo> {
+        self.present.iter().find(|e| e.info.location == Some(location)).map(|e| &e.info)
+    }
+
+    /// Present interfaces in registration order.
+    pub fn iter(&self) -> impl Iterator<Item = (&InterfaceInfo, &D)> + '_ {
+        self.present.iter().map(|e| (&e.info, &e.dev))
+    }
+
+    pub fn len(&self) -> usize {
+        self.present.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.present.is_empty()
+    }
+
+    fn key_of(info: &InterfaceInfo) -> HwKey {
+        match info.location {
+            Some(loc) => HwKey::Pci(loc),
+            None => HwKey::Mac(info.mac),
+        }
+    }
+
+    fn free_name(&self, kind: InterfaceKind) -> String {
+        let mut n = 0;
+        loop {
+            let name = format!("{}{}", kind.prefix(), n);
+            if !self.bindings.iter().any(|b| b.name == name) {
+                return name;
+            }
+            n += 1;
+        }
+    }
+
+    fn notify(&self, event: &RegistryEvent) {
+        for cb in &self.listeners {
+            cb(event);
+        }
+    }
+}
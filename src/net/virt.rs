@@ -0,0 +1,261 @@
+//! In-process software net devices: a loopback device and veth-style pairs.
+//!
+//! Both expose the same [`NetIo`] and [`NetDriver`] surface as `NetClient`, so net consumers can
+//! be exercised
+//! without hardware. Each direction of a link can drop, reorder and delay frames; delays are
+//! measured in ticks of a virtual clock advanced explicitly with [`VirtNet::advance`], which keeps
+//! lossy runs deterministic for a given seed.
+
+use crate::interface::{NetDriver, NetIo};
+use crate::net::recv::RecvQueue;
+use crate::protocol::net::{LinkStatus, MacAddress};
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use glenda::error::Error;
+use glenda::io::uring::IoUringCqe;
+
+/// Impairments applied to frames crossing a link, in parts per million.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkConfig {
+    pub drop_ppm: u32,
+    /// Chance that a frame overtakes the frame queued before it.
+    pub reorder_ppm: u32,
+    /// Delivery delay in virtual clock ticks.
+    pub latency: u64,
+    /// PRNG seed for drop/reorder decisions; 0 picks a fixed default.
+    pub seed: u64,
+}
+
+struct Frame {
+    due: u64,
+    data: Vec<u8>,
+}
+
+/// One direction of a link.
+struct Link {
+    config: LinkConfig,
+    rng: u64,
+    in_flight: VecDeque<Frame>,
+    recv: RecvQueue,
+}
+
+impl Link {
+    fn new(config: LinkConfig) -> Self {
+        Self {
+            config,
+            rng: if config.seed == 0 { 0x9e37_79b9_7f4a_7c15 } else { config.seed },
+            in_flight: VecDeque::new(),
+            recv: RecvQueue::default(),
+        }
+    }
+
+    /// xorshift64* draw, true with probability `ppm` / 1_000_000.
+    fn chance(&mut self, ppm: u32) -> bool {
+        if ppm == 0 {
+            return false;
+        }
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let r = self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        (r % 1_000_000) < ppm as u64
+    }
+
+    fn enqueue(&mut self, data: &[u8], now: u64) {
+        if self.chance(self.config.drop_ppm) {
+            return;
+        }
+        let mut frame = Frame { due: now + self.config.latency, data: Vec::from(data) };
+        if !self.in_flight.is_empty() && self.chance(self.config.reorder_ppm) {
+            let pos = self.in_flight.len() - 1;
+            frame.due = core::cmp::min(frame.due, self.in_flight[pos].due);
+            self.in_flight.insert(pos, frame);
+        } else {
+            self.in_flight.push_back(frame);
+        }
+    }
+
+    /// Move due frames into posted buffers.
+    fn pump(&mut self, now: u64) {
+        while self.recv.has_buffer() && self.in_flight.front().is_some_and(|f| f.due <= now) {
+            let Some(frame) = self.in_flight.pop_front() else {
+                break;
+            };
+            self.recv.deliver(&frame.data);
+        }
+    }
+}
+
+/// A software net device endpoint. Clones share the same endpoint.
+#[derive(Clone)]
+pub struct VirtNet {
+    mac: MacAddress,
+    clock: Rc<Cell<u64>>,
+    tx: Rc<RefCell<Link>>,
+    rx: Rc<RefCell<Link>>,
+}
+
+impl VirtNet {
+    /// A device whose transmitted frames are received by itself.
+    pub fn loopback(mac: MacAddress, config: LinkConfig) -> Self {
+        let link = Rc::new(RefCell::new(Link::new(config)));
+        Self { mac, clock: Rc::new(Cell::new(0)), tx: link.clone(), rx: link }
+    }
+
+    /// Two devices connected back to back, like a veth pair.
+    pub fn pair(mac_a: MacAddress, mac_b: MacAddress, config: LinkConfig) -> (Self, Self) {
+        let a_to_b = Rc::new(RefCell::new(Link::new(config)));
+        let mut b_config = config;
+        b_config.seed = config.seed.wrapping_add(1);
+        let b_to_a = Rc::new(RefCell::new(Link::new(b_config)));
+        let clock = Rc::new(Cell::new(0));
+        let a = Self { mac: mac_a, clock: clock.clone(), tx: a_to_b.clone(), rx: b_to_a.clone() };
+        let b = Self { mac: mac_b, clock, tx: b_to_a, rx: a_to_b };
+        (a, b)
+    }
+
+    /// Change the impairments applied to frames sent from this endpoint.
+    pub fn set_tx_config(&self, config: LinkConfig) {
+        self.tx.borrow_mut().config = config;
+    }
+
+    /// Current virtual time in ticks, shared by both ends of a pair.
+    pub fn now(&self) -> u64 {
+        self.clock.get()
+    }
+
+    /// Advance the virtual clock, releasing frames whose latency has elapsed.
+    pub fn advance(&self, ticks: u64) {
+        self.clock.set(self.clock.get() + ticks);
+    }
+
+    /// Frames sent towards this endpoint but not yet delivered.
+    pub fn pending(&self) -> usize {
+        self.rx.borrow().in_flight.len()
+    }
+}
+
+/// Only the MAC address and link state are modelled; the other controls keep their defaults.
+impl NetDriver for VirtNet {
+    fn mac_address(&self) -> MacAddress {
+        self.mac
+    }
+
+    fn link_status(&self) -> Result<LinkStatus, Error> {
+        Ok(LinkStatus { up: true, ..Default::default() })
+    }
+}
+
+impl NetIo for VirtNet {
+    fn send_packet(&self, buf: &[u8]) -> Result<(), Error> {
+        self.tx.borrow_mut().enqueue(buf, self.clock.get());
+        Ok(())
+    }
+
+    unsafe fn submit_recv(&self, buf: &mut [u8], id: u64) -> Result<(), Error> {
+        let mut rx = self.rx.borrow_mut();
+        unsafe { rx.recv.post(buf, id)? };
+        rx.pump(self.clock.get());
+        Ok(())
+    }
+
+    fn peek_cqe(&self) -> Option<IoUringCqe> {
+        let mut rx = self.rx.borrow_mut();
+        rx.pump(self.clock.get());
+        rx.recv.pop_completion()
+    }
+
+    fn take_recv_cqe(&self, id: u64) -> Option<IoUringCqe> {
+        let mut rx = self.rx.borrow_mut();
+        rx.pump(self.clock.get());
+        rx.recv.take_completion(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    const MAC_A: MacAddress = MacAddress { octets: [0x02, 0, 0, 0, 0, 1] };
+    const MAC_B: MacAddress = MacAddress { octets: [0x02, 0, 0, 0, 0, 2] };
+
+    /// Send `count` frames carrying their index from `a`, then collect what `b` receives.
+    fn run(config: LinkConfig, count: u32) -> Vec<u32> {
+        let (a, b) = VirtNet::pair(MAC_A, MAC_B, config);
+        for i in 0..count {
+            a.send_packet(&i.to_be_bytes()).unwrap();
+        }
+        a.advance(config.latency);
+        let mut received = Vec::new();
+        let mut buf = [0u8; 4];
+        loop {
+            unsafe { b.submit_recv(&mut buf, 0).unwrap() };
+            if b.peek_cqe().is_none() {
+                return received;
+            }
+            received.push(u32::from_be_bytes(buf));
+        }
+    }
+
+    #[test]
+    fn drops_at_the_configured_rate() {
+        let config = LinkConfig { drop_ppm: 250_000, seed: 42, ..Default::default() };
+        let received = run(config, 4000);
+        assert!((2800..3200).contains(&received.len()), "{} delivered", received.len());
+        assert!(received.windows(2).all(|w| w[0] < w[1]));
+        // The same seed makes the same decisions.
+        assert_eq!(run(config, 4000), received);
+        assert_ne!(run(LinkConfig { seed: 43, ..config }, 4000), received);
+    }
+
+    #[test]
+    fn reordered_frames_overtake_their_predecessor() {
+        let always = LinkConfig { reorder_ppm: 1_000_000, ..Default::default() };
+        // Each frame overtakes the one queued last, which is always frame 0.
+        assert_eq!(run(always, 4), [1, 2, 3, 0]);
+
+        let config = LinkConfig { reorder_ppm: 100_000, seed: 7, ..Default::default() };
+        let received = run(config, 1000);
+        let swapped = received.windows(2).filter(|w| w[0] > w[1]).count();
+        assert!((50..150).contains(&swapped), "{swapped} out of order");
+        let mut sorted = received.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn frames_arrive_after_the_latency() {
+        let config = LinkConfig { latency: 10, ..Default::default() };
+        let (a, b) = VirtNet::pair(MAC_A, MAC_B, config);
+        let mut buf = vec![0u8; 16];
+        unsafe { b.submit_recv(&mut buf, 1).unwrap() };
+        a.send_packet(b"first").unwrap();
+        a.advance(5);
+        a.send_packet(b"second").unwrap();
+
+        a.advance(4);
+        assert!(b.peek_cqe().is_none());
+        assert_eq!(b.pending(), 2);
+        a.advance(1);
+        assert_eq!(b.peek_cqe().map(|cqe| cqe.res), Some(5));
+        assert_eq!(&buf[..5], b"first");
+
+        unsafe { b.submit_recv(&mut buf, 2).unwrap() };
+        a.advance(4);
+        assert!(b.peek_cqe().is_none());
+        a.advance(1);
+        assert_eq!(b.peek_cqe().map(|cqe| cqe.res), Some(6));
+        assert_eq!(b.now(), 15);
+    }
+
+    #[test]
+    fn driver_controls_default() {
+        let (a, _b) = VirtNet::pair(MAC_A, MAC_B, LinkConfig::default());
+        assert_eq!(a.mac_address(), MAC_A);
+        assert!(a.link_status().unwrap().up);
+        assert_eq!(a.mtu(), Err(Error::InvalidArgs));
+    }
+}
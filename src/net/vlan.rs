@@ -0,0 +1,186 @@
+//! 802.1Q VLAN sub-interfaces on top of a [`NetIo`] device.
+//!
+//! A [`VlanDemux`] owns the parent device and hands out one [`VlanInterface`] per VLAN ID.
+//! Sub-interfaces tag frames on send and see untagged frames on receive. Tag insertion and
+//! stripping use the NIC offloads when the parent advertises them, and are done in software
+//! otherwise. Untagged frames and frames for VLANs without a sub-interface are dropped.
+//!
+//! The demux receives from the parent into a heap buffer, which a `NetClient` driver cannot
+//! reach; the parent has to be a software device such as [`VirtNet`](super::virt::VirtNet).
+
+use crate::interface::NetIo;
+use crate::net::MAX_TAGGED_FRAME;
+use crate::net::packet::VlanTag;
+use crate::net::packet::ethernet::{self, VLAN_TAG_LEN};
+use crate::net::recv::{RecvQueue, RxSlot};
+use crate::protocol::net::{self, TxOffload};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::rc::Rc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use glenda::error::Error;
+use glenda::io::uring::IoUringCqe;
+
+/// Frames queued per sub-interface while it has no receive buffer posted.
+pub const BACKLOG_LIMIT: usize = 64;
+
+#[derive(Default)]
+struct SubIf {
+    recv: RecvQueue,
+    backlog: VecDeque<Vec<u8>>,
+}
+
+impl SubIf {
+    fn deliver(&mut self, frame: &[u8]) {
+        if !self.recv.deliver(frame) && self.backlog.len() < BACKLOG_LIMIT {
+            self.backlog.push_back(Vec::from(frame));
+        }
+    }
+}
+
+struct Shared<D: NetIo> {
+    parent: D,
+    hw_tx: bool,
+    hw_rx: bool,
+    rx: RefCell<RxSlot>,
+    subifs: RefCell<BTreeMap<u16, SubIf>>,
+    dropped: Cell<u64>,
+}
+
+impl<D: NetIo> Shared<D> {
+    /// Drain the parent and dispatch frames to their sub-interfaces.
+    fn poll(&self) -> Result<(), Error> {
+        loop {
+            let mut rx = self.rx.borrow_mut();
+            let Some(cqe) = rx.poll(&self.parent, 0)? else {
+                return Ok(());
+            };
+            let len = rx.frame(&cqe).len();
+            let buf = rx.buf_mut();
+            let (vid, len) = if self.hw_rx && cqe.flags & net::RX_VLAN_STRIPPED != 0 {
+                (VlanTag::from_tci((cqe.flags >> 16) as u16).vid, len)
+            } else {
+                match ethernet::strip_vlan_tag(buf, len) {
+                    Some((n, tag)) => (tag.vid, n),
+                    None => {
+                        self.dropped.set(self.dropped.get() + 1);
+                        continue;
+                    }
+                }
+            };
+
+            match self.subifs.borrow_mut().get_mut(&vid) {
+                Some(subif) => subif.deliver(&buf[..len]),
+                None => self.dropped.set(self.dropped.get() + 1),
+            }
+        }
+    }
+}
+
+pub struct VlanDemux<D: NetIo> {
+    shared: Rc<Shared<D>>,
+}
+
+impl<D: NetIo> VlanDemux<D> {
+    pub fn new(parent: D) -> Self {
+        Self::with_frame_size(parent, MAX_TAGGED_FRAME)
+    }
+
+    pub fn with_frame_size(parent: D, frame_size: usize) -> Self {
+        let caps = parent.offload_caps();
+        Self {
+            shared: Rc::new(Shared {
+                hw_tx: caps & net::OFFLOAD_VLAN_TX != 0,
+                hw_rx: caps & net::OFFLOAD_VLAN_RX != 0,
+                parent,
+                rx: RefCell::new(RxSlot::new(frame_size)),
+                subifs: RefCell::new(BTreeMap::new()),
+                dropped: Cell::new(0),
+            }),
+        }
+    }
+
+    /// Create the sub-interface for `vid` (1-4094). Fails if it already exists.
+    pub fn create(&self, vid: u16) -> Result<VlanInterface<D>, Error> {
+        if vid == 0 || vid >= 4095 {
+            return Err(Error::InvalidArgs);
+        }
+        let mut subifs = self.shared.subifs.borrow_mut();
+        if subifs.contains_key(&vid) {
+            return Err(Error::InvalidArgs);
+        }
+        subifs.insert(vid, SubIf::default());
+        Ok(VlanInterface { shared: self.shared.clone(), vid })
+    }
+
+    pub fn parent(&self) -> &D {
+        &self.shared.parent
+    }
+
+    /// Frames dropped because they were untagged or had no matching sub-interface.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.get()
+    }
+
+    /// Receive and dispatch all frames pending on the parent.
+    pub fn poll(&self) -> Result<(), Error> {
+        self.shared.poll()
+    }
+}
+
+/// A VLAN sub-interface. Dropping it removes the VLAN from the demultiplexer.
+pub struct VlanInterface<D: NetIo> {
+    shared: Rc<Shared<D>>,
+    vid: u16,
+}
+
+impl<D: NetIo> VlanInterface<D> {
+    pub fn vid(&self) -> u16 {
+        self.vid
+    }
+}
+
+impl<D: NetIo> Drop for VlanInterface<D> {
+    fn drop(&mut self) {
+        self.shared.subifs.borrow_mut().remove(&self.vid);
+    }
+}
+
+impl<D: NetIo> NetIo for VlanInterface<D> {
+    fn send_packet(&self, buf: &[u8]) -> Result<(), Error> {
+        let tag = VlanTag::new(self.vid);
+        if self.shared.hw_tx {
+            let mut frame = Vec::from(buf);
+            let offload =
+                TxOffload { flags: net::TX_VLAN, vlan_tci: tag.tci(), ..Default::default() };
+            return self.shared.parent.send_packet_offload(&mut frame, offload);
+        }
+        let mut frame = vec![0u8; buf.len() + VLAN_TAG_LEN];
+        frame[..buf.len()].copy_from_slice(buf);
+        let n = ethernet::insert_vlan_tag(&mut frame, buf.len(), tag)?;
+        self.shared.parent.send_packet(&frame[..n])
+    }
+
+    unsafe fn submit_recv(&self, buf: &mut [u8], id: u64) -> Result<(), Error> {
+        {
+            let mut subifs = self.shared.subifs.borrow_mut();
+            let subif = subifs.get_mut(&self.vid).ok_or(Error::NotInitialized)?;
+            unsafe { subif.recv.post(buf, id)? };
+            if let Some(frame) = subif.backlog.pop_front() {
+                subif.deliver(&frame);
+            }
+        }
+        self.shared.poll()
+    }
+
+    fn peek_cqe(&self) -> Option<IoUringCqe> {
+        let _ = self.shared.poll();
+        self.shared.subifs.borrow_mut().get_mut(&self.vid)?.recv.pop_completion()
+    }
+
+    fn take_recv_cqe(&self, id: u64) -> Option<IoUringCqe> {
+        let _ = self.shared.poll();
+        self.shared.subifs.borrow_mut().get_mut(&self.vid)?.recv.take_completion(id)
+    }
+}
@@ -0,0 +1,58 @@
+//! Wake-on-LAN magic packets.
+//!
+//! A magic packet payload is six 0xFF bytes followed by the target MAC repeated 16 times,
+//! optionally followed by a 6-byte SecureOn password. It is sent as a broadcast Ethernet frame
+//! with EtherType 0x0842; the same payload can also be carried in a UDP datagram to port 9.
+
+use crate::interface::NetIo;
+use crate::net::packet::ethernet::{self, HEADER_LEN};
+use crate::protocol::net::MacAddress;
+use glenda::error::Error;
+
+pub const ETHERTYPE_WOL: u16 = 0x0842;
+pub const WOL_UDP_PORT: u16 = 9;
+pub const MAGIC_PAYLOAD_LEN: usize = 102;
+pub const SECURE_ON_LEN: usize = 6;
+
+/// Write the magic packet payload for `target` into `buf`, returning its length.
+pub fn fill_magic_payload(
+    buf: &mut [u8],
+    target: MacAddress,
+    secure_on: Option<[u8; 6]>,
+) -> Result<usize, Error> {
+    let len = MAGIC_PAYLOAD_LEN + if secure_on.is_some() { SECURE_ON_LEN } else { 0 };
+    if buf.len() < len {
+        return Err(Error::InvalidArgs);
+    }
+    buf[..6].fill(0xff);
+    for chunk in buf[6..MAGIC_PAYLOAD_LEN].chunks_exact_mut(6) {
+        chunk.copy_from_slice(&target.octets);
+    }
+    if let Some(password) = secure_on {
+        buf[MAGIC_PAYLOAD_LEN..len].copy_from_slice(&password);
+    }
+    Ok(len)
+}
+
+/// Build a broadcast magic packet frame from `src` waking `target`. Returns the frame length.
+pub fn build_magic_packet(
+    buf: &mut [u8],
+    src: MacAddress,
+    target: MacAddress,
+    secure_on: Option<[u8; 6]>,
+) -> Result<usize, Error> {
+    let off = ethernet::emit_header(buf, MacAddress::BROADCAST, src, None, ETHERTYPE_WOL)?;
+    Ok(off + fill_magic_payload(&mut buf[off..], target, secure_on)?)
+}
+
+/// Send a magic packet waking `target` on `dev`, whose own address is `src`.
+pub fn send_magic_packet<D: NetIo>(
+    dev: &D,
+    src: MacAddress,
+    target: MacAddress,
+    secure_on: Option<[u8; 6]>,
+) -> Result<(), Error> {
+    let mut buf = [0u8; HEADER_LEN + MAGIC_PAYLOAD_LEN + SECURE_ON_LEN];
+    let len = build_magic_packet(&mut buf, src, target, secure_on)?;
+    dev.send_packet(&buf[..len])
+}
@@ -2,9 +2,38 @@
 
 /// Get MAC Address
 pub const GET_MAC: usize = 0x1;
+/// Get link status. Returns: arg0: up (0/1), arg1: speed in Mbps, arg2: duplex
+pub const GET_LINK_STATUS: usize = 0x2;
+/// Get MTU in bytes (excluding the Ethernet header). Returns: arg0: mtu
+pub const GET_MTU: usize = 0x3;
+/// Set MTU in bytes (excluding the Ethernet header). Args: arg0: mtu
+pub const SET_MTU: usize = 0x4;
+/// Get hardware offload capabilities. Returns: arg0: OFFLOAD_* bitmask
+pub const GET_OFFLOAD_CAPS: usize = 0x5;
+/// Set the unicast MAC address. Args: arg0-arg5: octets
+pub const SET_MAC: usize = 0x6;
+/// Join a multicast group. Args: arg0-arg5: octets
+pub const ADD_MULTICAST: usize = 0x7;
+/// Leave a multicast group. Args: arg0-arg5: octets
+pub const DEL_MULTICAST: usize = 0x8;
+/// Toggle promiscuous mode. Args: arg0: enable (0/1)
+pub const SET_PROMISC: usize = 0x9;
+/// Toggle reception of all multicast frames. Args: arg0: enable (0/1)
+pub const SET_ALLMULTI: usize = 0xA;
+/// Get interface statistics. Returns: NetStats in the IPC buffer
+pub const GET_STATS: usize = 0xB;
+/// Get multi-queue and RSS capabilities. Returns: arg0: max queue pairs, arg1: RSS key size in
+/// bytes, arg2: RSS indirection table entries
+pub const GET_QUEUE_CAPS: usize = 0xC;
+/// Set the RSS Toeplitz hash key. Args: key bytes in the IPC buffer
+pub const SET_RSS_KEY: usize = 0xD;
+/// Set the RSS indirection table. Args: little-endian u16 queue indices in the IPC buffer
+pub const SET_RSS_INDIR: usize = 0xE;
+/// Select the header fields hashed by RSS. Args: arg0: RSS_HASH_* bitmask
+pub const SET_RSS_HASH: usize = 0xF;
 
-/// Setup io_uring (Primary IO Channel).
-/// Args: sq_entries, cq_entries
+/// Setup io_uring for a queue pair. Queue 0 is the primary IO channel.
+/// Args: sq_entries, cq_entries, queue index
 /// Resp: Cap Transfer (Frame)
 pub const SETUP_RING: usize = 0x10;
 
@@ -17,24 +46,220 @@ pub const NOTIFY_SQ: usize = 0x12;
 /// Async notification for packet RX/TX completion
 pub const NOTIFY_IO: usize = 0x20;
 
+/// Async notification for link changes, pushed over the ring notify endpoint.
+/// Args: arg0: up (0/1), arg1: speed in Mbps, arg2: duplex
+pub const NOTIFY_LINK: usize = 0x21;
+
+/// Read the NIC's PTP hardware clock. Returns: arg0: time in nanoseconds
+pub const PTP_GET_TIME: usize = 0x30;
+/// Set the PTP hardware clock. Args: arg0: time in nanoseconds
+pub const PTP_SET_TIME: usize = 0x31;
+/// Step the PTP hardware clock. Args: arg0: signed offset in nanoseconds
+pub const PTP_ADJ_TIME: usize = 0x32;
+/// Set the PTP hardware clock frequency offset. Args: arg0: signed offset in parts per billion
+pub const PTP_ADJ_FREQ: usize = 0x33;
+/// Select which received frames get a hardware timestamp. Args: arg0: RX_TIMESTAMP_* mode
+pub const SET_RX_TIMESTAMP: usize = 0x34;
+
+/// Get Wake-on-LAN settings. Returns: arg0: supported WOL_* modes, arg1: enabled WOL_* modes
+pub const GET_WOL: usize = 0x40;
+/// Set Wake-on-LAN modes. Args: arg0: WOL_* modes, arg1-arg6: SecureOn password octets
+pub const SET_WOL: usize = 0x41;
+
+// Link Duplex
+pub const DUPLEX_UNKNOWN: u8 = 0;
+pub const DUPLEX_HALF: u8 = 1;
+pub const DUPLEX_FULL: u8 = 2;
+
+/// `user_data` bits reserved by NetClient to classify completions for its counters.
+pub const USER_DATA_RX: u64 = 1 << 63;
+pub const USER_DATA_TX: u64 = 1 << 62;
+
 /// Network-specific ring opcodes for io_uring
 pub mod opcodes {
     pub const SEND: u8 = 10;
     pub const RECV: u8 = 11;
 }
 
-use glenda::io::uring::IoUringSqe;
+use glenda::io::uring::{IoUringCqe, IoUringSqe};
+use serde::{Deserialize, Serialize};
 
 pub fn sqe_send(addr: u64, len: u32, user_data: u64) -> IoUringSqe {
     IoUringSqe { opcode: opcodes::SEND, addr, len, user_data, ..Default::default() }
 }
 
+/// Send with offload metadata. The request is packed into the otherwise unused `off` field.
+pub fn sqe_send_offload(addr: u64, len: u32, user_data: u64, offload: TxOffload) -> IoUringSqe {
+    IoUringSqe {
+        opcode: opcodes::SEND,
+        off: offload.encode(),
+        addr,
+        len,
+        user_data,
+        ..Default::default()
+    }
+}
+
 pub fn sqe_recv(addr: u64, len: u32, user_data: u64) -> IoUringSqe {
     IoUringSqe { opcode: opcodes::RECV, addr, len, user_data, ..Default::default() }
 }
 
+// Offload Capability Flags (GET_OFFLOAD_CAPS)
+pub const OFFLOAD_TX_CSUM_IPV4: u32 = 1 << 0;
+pub const OFFLOAD_TX_CSUM_TCP: u32 = 1 << 1;
+pub const OFFLOAD_TX_CSUM_UDP: u32 = 1 << 2;
+pub const OFFLOAD_RX_CSUM: u32 = 1 << 3;
+pub const OFFLOAD_TSO: u32 = 1 << 4;
+// Bit 5 is reserved for scatter-gather, which the ring does not support yet.
+pub const OFFLOAD_VLAN_TX: u32 = 1 << 6;
+pub const OFFLOAD_VLAN_RX: u32 = 1 << 7;
+pub const OFFLOAD_TIMESTAMP: u32 = 1 << 8;
+
+// TX Offload Request Flags (SQE `off` bits 0..16, MSS in bits 16..32, VLAN TCI in bits 32..48)
+pub const TX_CSUM_L3: u16 = 1 << 0;
+pub const TX_CSUM_L4: u16 = 1 << 1;
+pub const TX_TSO: u16 = 1 << 2;
+pub const TX_VLAN: u16 = 1 << 3;
+/// Report a hardware transmit timestamp for this frame (see `CQE_TIMESTAMP`).
+pub const TX_TIMESTAMP: u16 = 1 << 4;
+
+// RX Completion Flags (CQE `flags`)
+pub const RX_CSUM_L3_OK: u32 = 1 << 0;
+pub const RX_CSUM_L4_OK: u32 = 1 << 1;
+pub const RX_CSUM_BAD: u32 = 1 << 2;
+/// The NIC stripped an 802.1Q tag; its TCI is in CQE `flags` bits 16..32.
+pub const RX_VLAN_STRIPPED: u32 = 1 << 3;
+/// A hardware timestamp follows the frame in the buffer (see `cqe_timestamp`). Set on RX
+/// completions and on TX completions of frames sent with `TX_TIMESTAMP`.
+pub const CQE_TIMESTAMP: u32 = 1 << 4;
+
+/// Size of the little-endian nanosecond timestamp the driver writes right after the frame.
+/// Buffers need this much spare room past the frame to receive one.
+pub const TIMESTAMP_LEN: usize = 8;
+
+// RX Timestamping Modes (SET_RX_TIMESTAMP)
+pub const RX_TIMESTAMP_NONE: u8 = 0;
+pub const RX_TIMESTAMP_PTP: u8 = 1;
+pub const RX_TIMESTAMP_ALL: u8 = 2;
+
+/// Hardware timestamp of a completed frame in PTP clock nanoseconds, read from `buf`,
+/// the buffer the completion refers to.
+pub fn cqe_timestamp(cqe: &IoUringCqe, buf: &[u8]) -> Option<u64> {
+    if cqe.flags & CQE_TIMESTAMP == 0 || cqe.res < 0 {
+        return None;
+    }
+    let off = cqe.res as usize;
+    let bytes = buf.get(off..off + TIMESTAMP_LEN)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+// Wake-on-LAN Modes (GET_WOL / SET_WOL)
+pub const WOL_PHY: u32 = 1 << 0;
+pub const WOL_UNICAST: u32 = 1 << 1;
+pub const WOL_MULTICAST: u32 = 1 << 2;
+pub const WOL_BROADCAST: u32 = 1 << 3;
+pub const WOL_ARP: u32 = 1 << 4;
+pub const WOL_MAGIC: u32 = 1 << 5;
+/// Magic packets must carry the SecureOn password. Implies `WOL_MAGIC`.
+pub const WOL_MAGIC_SECURE: u32 = 1 << 6;
+
+// RSS Hash Types (SET_RSS_HASH)
+pub const RSS_HASH_IPV4: u32 = 1 << 0;
+pub const RSS_HASH_TCP_IPV4: u32 = 1 << 1;
+pub const RSS_HASH_UDP_IPV4: u32 = 1 << 2;
+pub const RSS_HASH_IPV6: u32 = 1 << 3;
+pub const RSS_HASH_TCP_IPV6: u32 = 1 << 4;
+pub const RSS_HASH_UDP_IPV6: u32 = 1 << 5;
+
+/// Per-packet offload request attached to a SEND SQE.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TxOffload {
+    pub flags: u16,
+    /// TCP segment size when `TX_TSO` is set.
+    pub mss: u16,
+    /// 802.1Q TCI to insert when `TX_VLAN` is set.
+    pub vlan_tci: u16,
+}
+
+impl TxOffload {
+    pub const fn encode(&self) -> u64 {
+        ((self.vlan_tci as u64) << 32) | ((self.mss as u64) << 16) | self.flags as u64
+    }
+
+    pub const fn decode(off: u64) -> Self {
+        Self { flags: off as u16, mss: (off >> 16) as u16, vlan_tci: (off >> 32) as u16 }
+    }
+}
+
+/// Multi-queue and RSS limits reported by `GET_QUEUE_CAPS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueCaps {
+    pub max_queues: u16,
+    pub rss_key_size: u16,
+    pub rss_indir_size: u16,
+}
+
+impl Default for QueueCaps {
+    fn default() -> Self {
+        Self { max_queues: 1, rss_key_size: 0, rss_indir_size: 0 }
+    }
+}
+
 #[repr(C)]
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct MacAddress {
     pub octets: [u8; 6],
 }
+
+impl MacAddress {
+    pub const BROADCAST: Self = Self { octets: [0xff; 6] };
+
+    pub const fn new(octets: [u8; 6]) -> Self {
+        Self { octets }
+    }
+
+    /// Group bit set: multicast or broadcast.
+    pub const fn is_multicast(&self) -> bool {
+        self.octets[0] & 0x01 != 0
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+        self.octets == [0xff; 6]
+    }
+
+    pub fn is_unicast(&self) -> bool {
+        !self.is_multicast() && self.octets != [0; 6]
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WolConfig {
+    /// WOL_* modes the NIC can wake on.
+    pub supported: u32,
+    /// WOL_* modes currently armed.
+    pub enabled: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkStatus {
+    pub up: bool,
+    pub speed_mbps: u32,
+    pub duplex: u8,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetStats {
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_dropped: u64,
+    pub tx_dropped: u64,
+    pub rx_errors: u64,
+    pub tx_errors: u64,
+    pub rx_crc_errors: u64,
+    /// RX FIFO overruns.
+    pub rx_fifo_errors: u64,
+}
@@ -22,7 +22,7 @@ pub const PCI_CMD_BUS_MASTER: u16 = 0x4;
 pub const PCI_CMD_INTX_DISABLE: u16 = 0x400;
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct PciAddress {
     pub segment: u16,
     pub bus: u8,
@@ -8,7 +8,32 @@ pub const GET_CHAR: usize = 0x02;
 pub const PUT_STR: usize = 0x03;
 /// Configuration
 pub const SET_BAUD_RATE: usize = 0x04;
+/// Get the line configuration.
+/// Returns: arg0: baud rate, arg1: data bits, arg2: StopBits, arg3: Parity, arg4: FlowControl
 pub const GET_CONFIG: usize = 0x05;
+/// Set the line configuration. Args: same layout as GET_CONFIG returns.
+/// Fails if the hardware does not support any of the settings.
+pub const SET_CONFIG: usize = 0x06;
+/// Get the modem control lines. Returns: arg0: MODEM_* bitmask
+pub const GET_MODEM: usize = 0x07;
+/// Set output modem lines. Args: arg0: MODEM_DTR/MODEM_RTS lines to change, arg1: new levels
+pub const SET_MODEM: usize = 0x08;
+/// Transmit a break condition. Args: arg0: duration in milliseconds
+pub const SEND_BREAK: usize = 0x09;
+/// Assert or release a break condition until told otherwise. Args: arg0: enable (0/1)
+pub const SET_BREAK: usize = 0x0A;
+/// Select which events are pushed with NOTIFY_EVENT. Args: arg0: EVENT_* mask
+pub const SET_EVENT_MASK: usize = 0x0B;
+/// Set the fill level raising EVENT_RX_THRESHOLD. Args: arg0: bytes, 0 disables the event
+pub const SET_RX_THRESHOLD: usize = 0x0C;
+/// Get line statistics. Returns: UartStats in the IPC buffer
+pub const GET_STATS: usize = 0x0D;
+/// Get the RS-485 configuration.
+/// Returns: arg0: RS485_* flags, arg1: delay before send (us), arg2: delay after send (us),
+/// arg3: receive address filter
+pub const GET_RS485: usize = 0x0E;
+/// Set the RS-485 configuration. Args: same layout as GET_RS485 returns.
+pub const SET_RS485: usize = 0x0F;
 
 /// Setup io_uring (Primary IO Channel).
 /// Args: sq_entries, cq_entries
@@ -21,10 +46,57 @@ pub const SETUP_BUFFER: usize = 0x11;
 /// Notify the driver that new requests are in the SQ.
 pub const NOTIFY_SQ: usize = 0x12;
 
+/// Send a character with the address (9th) bit set. Args: arg0: address
+/// Fails unless the port is in RS-485 address mode (RS485_ADDRB).
+pub const SEND_ADDRESS: usize = 0x13;
+
 /// Async notification for IO completion
 pub const NOTIFY_IO: usize = 0x20;
+/// Async line event, pushed over the ring notify endpoint.
+/// Args: arg0: MODEM_* line levels, arg1: EVENT_* bits, arg2: bytes lost since the last event
+pub const NOTIFY_EVENT: usize = 0x21;
+
+// Modem Lines (GET_MODEM / SET_MODEM / NOTIFY_EVENT)
+pub const MODEM_DTR: u32 = 1 << 0;
+pub const MODEM_RTS: u32 = 1 << 1;
+pub const MODEM_CTS: u32 = 1 << 2;
+pub const MODEM_DSR: u32 = 1 << 3;
+pub const MODEM_DCD: u32 = 1 << 4;
+pub const MODEM_RI: u32 = 1 << 5;
+
+// Line Events (NOTIFY_EVENT)
+/// An input modem line changed.
+pub const EVENT_MODEM: u32 = 1 << 0;
+/// A break condition was received.
+pub const EVENT_BREAK: u32 = 1 << 1;
+/// The RX FIFO overflowed and bytes were lost.
+pub const EVENT_OVERRUN: u32 = 1 << 2;
+/// A character arrived without a valid stop bit.
+pub const EVENT_FRAMING: u32 = 1 << 3;
+/// A character failed its parity check.
+pub const EVENT_PARITY: u32 = 1 << 4;
+/// The driver's RX buffer reached the SET_RX_THRESHOLD level.
+pub const EVENT_RX_THRESHOLD: u32 = 1 << 5;
+/// Received bytes were dropped because no read was posted and the driver buffer was full.
+pub const EVENT_RX_DROPPED: u32 = 1 << 6;
+/// Events reporting lost or corrupted input.
+pub const EVENT_ERRORS: u32 = EVENT_OVERRUN | EVENT_FRAMING | EVENT_PARITY | EVENT_RX_DROPPED;
 
+// RS-485 Flags (GET_RS485 / SET_RS485)
+/// Half-duplex RS-485 mode with automatic driver-enable on RTS.
+pub const RS485_ENABLED: u32 = 1 << 0;
+/// RTS is driven high while sending (low when clear).
+pub const RS485_RTS_ON_SEND: u32 = 1 << 1;
+/// Keep the receiver enabled while sending, to read back our own transmission.
+pub const RS485_RX_DURING_TX: u32 = 1 << 2;
+/// 9-bit mode: the extra bit marks address characters.
+pub const RS485_ADDRB: u32 = 1 << 3;
+/// Only receive frames whose address character matches arg3. Requires RS485_ADDRB.
+pub const RS485_ADDR_FILTER: u32 = 1 << 4;
+
+use glenda::error::Error;
 use glenda::io::uring::{IOURING_OP_READ, IOURING_OP_WRITE, IoUringSqe};
+use serde::{Deserialize, Serialize};
 
 pub fn sqe_read(addr: u64, len: u32, user_data: u64) -> IoUringSqe {
     IoUringSqe { opcode: IOURING_OP_READ, addr, len, user_data, ..Default::default() }
@@ -34,11 +106,192 @@ pub fn sqe_write(addr: u64, len: u32, user_data: u64) -> IoUringSqe {
     IoUringSqe { opcode: IOURING_OP_WRITE, addr, len, user_data, ..Default::default() }
 }
 
+/// A `NOTIFY_EVENT` message.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UartEvent {
+    /// MODEM_* line levels when the event was raised.
+    pub lines: u32,
+    /// EVENT_* bits.
+    pub events: u32,
+    /// Bytes lost to overruns or drops since the previous event.
+    pub lost: u32,
+}
+
 #[repr(C)]
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UartStats {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    /// Bytes lost to RX FIFO overruns.
+    pub overrun_errors: u64,
+    pub framing_errors: u64,
+    pub parity_errors: u64,
+    /// Break conditions received.
+    pub breaks: u64,
+    /// Bytes dropped because the driver buffer was full.
+    pub rx_dropped: u64,
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Parity {
+    #[default]
+    None = 0,
+    Odd = 1,
+    Even = 2,
+    Mark = 3,
+    Space = 4,
+}
+
+impl TryFrom<u8> for Parity {
+    type Error = Error;
+
+    fn try_from(v: u8) -> Result<Self, Error> {
+        match v {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Odd),
+            2 => Ok(Self::Even),
+            3 => Ok(Self::Mark),
+            4 => Ok(Self::Space),
+            _ => Err(Error::InvalidArgs),
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StopBits {
+    #[default]
+    One = 1,
+    Two = 2,
+    /// 1.5 stop bits, only valid with 5 data bits on most hardware.
+    OneAndHalf = 3,
+}
+
+impl TryFrom<u8> for StopBits {
+    type Error = Error;
+
+    fn try_from(v: u8) -> Result<Self, Error> {
+        match v {
+            1 => Ok(Self::One),
+            2 => Ok(Self::Two),
+            3 => Ok(Self::OneAndHalf),
+            _ => Err(Error::InvalidArgs),
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FlowControl {
+    #[default]
+    None = 0,
+    /// Hardware flow control on the RTS/CTS lines.
+    RtsCts = 1,
+    /// Software flow control with XON (0x11) / XOFF (0x13) characters.
+    XonXoff = 2,
+}
+
+impl TryFrom<u8> for FlowControl {
+    type Error = Error;
+
+    fn try_from(v: u8) -> Result<Self, Error> {
+        match v {
+            0 => Ok(Self::None),
+            1 => Ok(Self::RtsCts),
+            2 => Ok(Self::XonXoff),
+            _ => Err(Error::InvalidArgs),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct UartConfig {
     pub baud_rate: u32,
+    /// Data bits per character (5-8).
     pub data_bits: u8,
-    pub stop_bits: u8,
-    pub parity: u8,
+    pub stop_bits: StopBits,
+    pub parity: Parity,
+    pub flow_control: FlowControl,
+}
+
+impl Default for UartConfig {
+    /// 115200 baud, 8N1, no flow control.
+    fn default() -> Self {
+        Self {
+            baud_rate: 115200,
+            data_bits: 8,
+            stop_bits: StopBits::One,
+            parity: Parity::None,
+            flow_control: FlowControl::None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rs485Config {
+    pub enabled: bool,
+    /// Drive RTS high while sending. Clear for transceivers with an active-low enable.
+    pub rts_on_send: bool,
+    /// Delay between asserting driver-enable and the first bit, in microseconds.
+    pub delay_before_send_us: u32,
+    /// Delay between the last stop bit and releasing driver-enable, in microseconds.
+    pub delay_after_send_us: u32,
+    pub rx_during_tx: bool,
+    /// Use the 9th data bit to mark address characters.
+    pub address_mode: bool,
+    /// In address mode, only receive frames sent to this address.
+    pub address_filter: Option<u8>,
+}
+
+impl Default for Rs485Config {
+    /// Disabled, with an active-high driver-enable and no delays.
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rts_on_send: true,
+            delay_before_send_us: 0,
+            delay_after_send_us: 0,
+            rx_during_tx: false,
+            address_mode: false,
+            address_filter: None,
+        }
+    }
+}
+
+impl Rs485Config {
+    /// The RS485_* flags describing this configuration.
+    pub fn flags(&self) -> u32 {
+        let mut flags = 0;
+        if self.enabled {
+            flags |= RS485_ENABLED;
+        }
+        if self.rts_on_send {
+            flags |= RS485_RTS_ON_SEND;
+        }
+        if self.rx_during_tx {
+            flags |= RS485_RX_DURING_TX;
+        }
+        if self.address_mode {
+            flags |= RS485_ADDRB;
+        }
+        if self.address_filter.is_some() {
+            flags |= RS485_ADDR_FILTER;
+        }
+        flags
+    }
+
+    /// Rebuild a configuration from its wire form.
+    pub fn from_flags(flags: u32, before_us: u32, after_us: u32, address: u8) -> Self {
+        Self {
+            enabled: flags & RS485_ENABLED != 0,
+            rts_on_send: flags & RS485_RTS_ON_SEND != 0,
+            delay_before_send_us: before_us,
+            delay_after_send_us: after_us,
+            rx_during_tx: flags & RS485_RX_DURING_TX != 0,
+            address_mode: flags & RS485_ADDRB != 0,
+            address_filter: (flags & RS485_ADDR_FILTER != 0).then_some(address),
+        }
+    }
 }
@@ -0,0 +1,161 @@
+//! Byte sinks for streaming output (captures, file transfers) into a driver or memory.
+
+use crate::interface::{BlockDriver, UartDriver};
+use alloc::vec;
+use alloc::vec::Vec;
+use glenda::error::Error;
+
+pub trait ByteSink {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error>;
+
+    /// Push out any buffered data.
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl ByteSink for Vec<u8> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+        self.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+/// Writes bytes to a UART one character at a time.
+pub struct UartSink<U: UartDriver> {
+    uart: U,
+}
+
+impl<U: UartDriver> UartSink<U> {
+    pub const fn new(uart: U) -> Self {
+        Self { uart }
+    }
+
+    pub fn into_inner(self) -> U {
+        self.uart
+    }
+}
+
+impl<U: UartDriver> ByteSink for UartSink<U> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+        for &b in buf {
+            self.uart.put_char(b);
+        }
+        Ok(())
+    }
+}
+
+/// Writes bytes sequentially to a block device starting at a given sector.
+/// Data is staged one block at a time; `flush` zero-pads and writes a trailing partial block.
+pub struct BlockSink<B: BlockDriver> {
+    dev: B,
+    sector: u64,
+    block: Vec<u8>,
+    fill: usize,
+}
+
+impl<B: BlockDriver> BlockSink<B> {
+    pub fn new(dev: B, start_sector: u64) -> Result<Self, Error> {
+        let bs = dev.block_size() as usize;
+        if bs == 0 {
+            return Err(Error::NotInitialized);
+        }
+        Ok(Self { dev, sector: start_sector, block: vec![0u8; bs], fill: 0 })
+    }
+
+    /// Next sector that will be written.
+    pub fn position(&self) -> u64 {
+        self.sector
+    }
+
+    pub fn into_inner(self) -> B {
+        self.dev
+    }
+
+    /// Write the buffered block to the current sector, moving on to the next one only once the
+    /// block is full.
+    fn write_block(&mut self) -> Result<(), Error> {
+        if self.sector >= self.dev.capacity() {
+            return Err(Error::InvalidArgs);
+        }
+        self.dev.write_blocks(self.sector, 1, &self.block)?;
+        if self.fill == self.block.len() {
+            self.sector += 1;
+            self.fill = 0;
+        }
+        Ok(())
+    }
+}
+
+impl<B: BlockDriver> ByteSink for BlockSink<B> {
+    fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Error> {
+        while !buf.is_empty() {
+            let n = core::cmp::min(buf.len(), self.block.len() - self.fill);
+            self.block[self.fill..self.fill + n].copy_from_slice(&buf[..n]);
+            self.fill += n;
+            buf = &buf[n..];
+            if self.fill == self.block.len() {
+                self.write_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write out a partial block zero-padded. The padding is not part of the stream: later
+    /// writes continue after the data in the same sector and rewrite it.
+    fn flush(&mut self) -> Result<(), Error> {
+        if self.fill == 0 {
+            return Ok(());
+        }
+        self.block[self.fill..].fill(0);
+        self.write_block()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::RefCell;
+
+    struct Disk(RefCell<Vec<u8>>);
+
+    impl BlockDriver for Disk {
+        fn read_blocks(&self, sector: u64, count: u32, buf: &mut [u8]) -> Result<(), Error> {
+            let start = sector as usize * 4;
+            buf.copy_from_slice(&self.0.borrow()[start..start + count as usize * 4]);
+            Ok(())
+        }
+
+        fn write_blocks(&self, sector: u64, count: u32, buf: &[u8]) -> Result<(), Error> {
+            let start = sector as usize * 4;
+            self.0.borrow_mut()[start..start + count as usize * 4].copy_from_slice(buf);
+            Ok(())
+        }
+
+        fn block_size(&self) -> u32 {
+            4
+        }
+
+        fn capacity(&self) -> u64 {
+            self.0.borrow().len() as u64 / 4
+        }
+    }
+
+    #[test]
+    fn flush_keeps_the_stream_contiguous() {
+        let mut sink = BlockSink::new(Disk(RefCell::new(vec![0xff; 16])), 1).unwrap();
+        sink.write_all(b"abcdef").unwrap();
+        sink.flush().unwrap();
+        assert_eq!(sink.position(), 2);
+        assert_eq!(&sink.dev.0.borrow()[4..12], b"abcdef\0\0");
+
+        sink.write_all(b"gh").unwrap();
+        assert_eq!(sink.position(), 3);
+        sink.write_all(b"i").unwrap();
+        sink.flush().unwrap();
+        sink.flush().unwrap();
+        let disk = sink.into_inner().0.into_inner();
+        assert_eq!(&disk[..4], [0xff; 4]);
+        assert_eq!(&disk[4..], b"abcdefghi\0\0\0");
+    }
+}
@@ -0,0 +1,483 @@
+//! Serial console and `log` backend.
+//!
+//! [`UartConsole`] buffers output in a fixed-size ring behind a spinlock and drains it to the
+//! port without blocking. A message that does not fit is dropped whole and counted, so a slow
+//! or stalled UART never blocks the logging caller. [`UartConsole::flush`] drains everything
+//! and is meant for shutdown; [`UartConsole::flush_on_panic`] does so from a panic handler.
+//!
+//! With the `log` feature, [`UartLogger`] formats records as
+//! `[seconds.micros] LEVEL target: message` into a console.
+//!
+//! [`UartConsole::set_global`] makes a console the target of this crate's
+//! [`print!`](crate::print) and [`println!`](crate::println). `glenda::println!` belongs to
+//! libglenda; its output reaches the UART once libglenda's print hook forwards to [`print`].
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use embedded_io::{Write, WriteReady};
+use spin::Mutex;
+
+/// Default ring size in bytes.
+pub const DEFAULT_CONSOLE_CAPACITY: usize = 4096;
+
+/// How often `flush_on_panic` retries a held lock before giving up.
+const PANIC_LOCK_ATTEMPTS: usize = 1 << 20;
+
+/// Object-safe view of a console, so the global target does not depend on the port type.
+trait GlobalConsole: Sync {
+    fn print(&self, args: fmt::Arguments) -> bool;
+    fn flush_on_panic(&self) -> bool;
+}
+
+/// Console behind `print!` and `println!`.
+static GLOBAL: Mutex<Option<&'static dyn GlobalConsole>> = Mutex::new(None);
+
+struct Ring {
+    buf: Vec<u8>,
+    head: usize,
+    len: usize,
+}
+
+impl Ring {
+    fn new(capacity: usize) -> Self {
+        Self { buf: vec![0; capacity], head: 0, len: 0 }
+    }
+
+    /// Append all of `data` or nothing.
+    fn push(&mut self, data: &[u8]) -> bool {
+        let cap = self.buf.len();
+        if data.len() > cap - self.len {
+            return false;
+        }
+        let tail = (self.head + self.len) % cap;
+        let first = core::cmp::min(data.len(), cap - tail);
+        self.buf[tail..tail + first].copy_from_slice(&data[..first]);
+        self.buf[..data.len() - first].copy_from_slice(&data[first..]);
+        self.len += data.len();
+        true
+    }
+
+    /// The oldest buffered bytes that are contiguous in memory.
+    fn front(&self) -> &[u8] {
+        let end = core::cmp::min(self.head + self.len, self.buf.len());
+        &self.buf[self.head..end]
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.head = (self.head + n) % self.buf.len();
+        self.len -= n;
+        if self.len == 0 {
+            self.head = 0;
+        }
+    }
+
+    /// Drop everything appended after the buffer held `len` bytes.
+    fn truncate(&mut self, len: usize) {
+        self.len = len;
+    }
+}
+
+/// Formats into the ring, failing once it is full.
+struct RingWriter<'a> {
+    ring: &'a mut Ring,
+}
+
+impl fmt::Write for RingWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.ring.push(s.as_bytes()) { Ok(()) } else { Err(fmt::Error) }
+    }
+}
+
+struct Inner<W> {
+    ring: Ring,
+    port: W,
+}
+
+impl<W: Write + WriteReady> Inner<W> {
+    /// Write buffered bytes while the port can take them without blocking.
+    fn drain(&mut self) -> Result<(), W::Error> {
+        while self.ring.len > 0 && self.port.write_ready()? {
+            let n = self.port.write(self.ring.front())?;
+            if n == 0 {
+                break;
+            }
+            self.ring.consume(n);
+        }
+        Ok(())
+    }
+
+    /// Write out everything, blocking as needed.
+    fn drain_all(&mut self) -> Result<(), W::Error> {
+        while self.ring.len > 0 {
+            let n = self.port.write(self.ring.front())?;
+            if n == 0 {
+                break;
+            }
+            self.ring.consume(n);
+        }
+        self.port.flush()
+    }
+}
+
+pub struct UartConsole<W> {
+    inner: Mutex<Inner<W>>,
+    dropped: AtomicU64,
+}
+
+impl<W: Write + WriteReady> UartConsole<W> {
+    pub fn new(port: W) -> Self {
+        Self::with_capacity(port, DEFAULT_CONSOLE_CAPACITY)
+    }
+
+    pub fn with_capacity(port: W, capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner { ring: Ring::new(capacity.max(1)), port }),
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Queue a message and push out what the port accepts. Returns false if it was dropped.
+    pub fn write_str(&self, s: &str) -> bool {
+        let mut inner = self.inner.lock();
+        let queued = inner.ring.push(s.as_bytes());
+        if !queued {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        let _ = inner.drain();
+        queued
+    }
+
+    /// Queue a formatted message, all or nothing, and push out what the port accepts.
+    /// This makes `write!`/`writeln!` work on a shared console; they return the same flag.
+    pub fn write_fmt(&self, args: fmt::Arguments) -> bool {
+        let mut inner = self.inner.lock();
+        let mark = inner.ring.len;
+        let queued = fmt::write(&mut RingWriter { ring: &mut inner.ring }, args).is_ok();
+        if !queued {
+            inner.ring.truncate(mark);
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        // Port errors are not the caller's problem; the bytes stay queued for the next try.
+        let _ = inner.drain();
+        queued
+    }
+
+    /// Push out buffered output without blocking.
+    pub fn poll(&self) -> Result<(), W::Error> {
+        self.inner.lock().drain()
+    }
+
+    /// Write out all buffered output, blocking until the port has taken it.
+    pub fn flush(&self) -> Result<(), W::Error> {
+        self.inner.lock().drain_all()
+    }
+
+    /// Flush from a panic handler. The panicking code may hold the lock, so this gives up
+    /// and returns false rather than deadlock if the lock stays taken.
+    pub fn flush_on_panic(&self) -> bool {
+        for _ in 0..PANIC_LOCK_ATTEMPTS {
+            if let Some(mut inner) = self.inner.try_lock() {
+                return inner.drain_all().is_ok();
+            }
+            core::hint::spin_loop();
+        }
+        false
+    }
+
+    /// Messages dropped because the ring was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Bytes waiting to be written.
+    pub fn pending(&self) -> usize {
+        self.inner.lock().ring.len
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner.into_inner().port
+    }
+}
+
+impl<W: Write + WriteReady + Send + 'static> UartConsole<W> {
+    /// Make this console the target of [`print!`](crate::print) and
+    /// [`println!`](crate::println). Returns false if another console already is.
+    pub fn set_global(&'static self) -> bool {
+        let mut global = GLOBAL.lock();
+        if global.is_some() {
+            return false;
+        }
+        *global = Some(self);
+        true
+    }
+}
+
+impl<W: Write + WriteReady + Send> GlobalConsole for UartConsole<W> {
+    fn print(&self, args: fmt::Arguments) -> bool {
+        self.write_fmt(args)
+    }
+
+    fn flush_on_panic(&self) -> bool {
+        UartConsole::flush_on_panic(self)
+    }
+}
+
+/// Queue a message on the global console. Returns false if it was dropped or no console is
+/// set. This backs `print!` and is the function to point other print hooks at.
+pub fn print(args: fmt::Arguments) -> bool {
+    // Copy the console out so the lock is not held while formatting.
+    let console = *GLOBAL.lock();
+    console.is_some_and(|c| c.print(args))
+}
+
+/// [`UartConsole::flush_on_panic`] for the global console, if one is set.
+pub fn flush_global_on_panic() -> bool {
+    let console = GLOBAL.try_lock().and_then(|g| *g);
+    console.is_some_and(|c| c.flush_on_panic())
+}
+
+/// Print to the global console set with [`UartConsole::set_global`]. Evaluates to false if the
+/// message was dropped.
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::uart::console::print(format_args!($($arg)*))
+    };
+}
+
+/// Like [`print!`](crate::print), ending the line with CR LF.
+#[macro_export]
+macro_rules! println {
+    () => {
+        $crate::print!("\r\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::uart::console::print(format_args!("{}\r\n", format_args!($($arg)*)))
+    };
+}
+
+#[cfg(feature = "log")]
+pub use self::logger::UartLogger;
+
+#[cfg(feature = "log")]
+mod logger {
+    use super::UartConsole;
+    use alloc::boxed::Box;
+    use embedded_io::{Write, WriteReady};
+    use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+
+    /// A `log` backend writing to a [`UartConsole`].
+    pub struct UartLogger<W> {
+        console: UartConsole<W>,
+        level: LevelFilter,
+        /// Current time in microseconds.
+        clock: fn() -> u64,
+    }
+
+    impl<W: Write + WriteReady> UartLogger<W> {
+        pub fn new(console: UartConsole<W>, level: LevelFilter, clock: fn() -> u64) -> Self {
+            Self { console, level, clock }
+        }
+
+        pub fn console(&self) -> &UartConsole<W> {
+            &self.console
+        }
+    }
+
+    impl<W: Write + WriteReady + Send + 'static> UartLogger<W> {
+        /// Install the logger globally. The returned reference stays valid for the life of the
+        /// program, e.g. for `console().flush_on_panic()` in the panic handler.
+        pub fn init(self) -> Result<&'static Self, SetLoggerError> {
+            let logger: &'static Self = Box::leak(Box::new(self));
+            log::set_logger(logger)?;
+            log::set_max_level(logger.level);
+            Ok(logger)
+        }
+    }
+
+    impl<W: Write + WriteReady + Send> Log for UartLogger<W> {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= self.level
+        }
+
+        fn log(&self, record: &Record) {
+            if !self.enabled(record.metadata()) {
+                return;
+            }
+            let us = (self.clock)();
+            self.console.write_fmt(format_args!(
+                "[{:>5}.{:06}] {:<5} {}: {}\r\n",
+                us / 1_000_000,
+                us % 1_000_000,
+                record.level(),
+                record.target(),
+                record.args()
+            ));
+        }
+
+        fn flush(&self) {
+            let _ = self.console.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::boxed::Box;
+    use alloc::rc::Rc;
+    use alloc::sync::Arc;
+    use core::cell::{Cell, RefCell};
+
+    /// A port taking at most `chunk` bytes per write, and nothing while not ready.
+    #[derive(Clone)]
+    struct Port {
+        out: Rc<RefCell<Vec<u8>>>,
+        ready: Rc<Cell<bool>>,
+        chunk: usize,
+    }
+
+    impl Port {
+        fn new(chunk: usize) -> Self {
+            Self { out: Rc::default(), ready: Rc::new(Cell::new(false)), chunk }
+        }
+    }
+
+    impl embedded_io::ErrorType for Port {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Write for Port {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            let n = core::cmp::min(buf.len(), self.chunk);
+            self.out.borrow_mut().extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    impl WriteReady for Port {
+        fn write_ready(&mut self) -> Result<bool, Self::Error> {
+            Ok(self.ready.get())
+        }
+    }
+
+    fn contents(ring: &mut Ring) -> Vec<u8> {
+        let mut out = Vec::new();
+        while ring.len > 0 {
+            let n = ring.front().len();
+            out.extend_from_slice(ring.front());
+            ring.consume(n);
+        }
+        out
+    }
+
+    #[test]
+    fn ring_wraps_around() {
+        let mut ring = Ring::new(8);
+        assert!(ring.push(b"abcdef"));
+        ring.consume(4);
+        assert!(ring.push(b"ghijk"));
+        // "ef" then "gh" fill the end of the buffer, "ijk" wraps to the start.
+        assert_eq!(ring.front(), b"efgh");
+        assert!(!ring.push(b"lm"));
+        assert!(ring.push(b"l"));
+        assert_eq!(contents(&mut ring), b"efghijkl");
+        assert_eq!(ring.head, 0);
+    }
+
+    #[test]
+    fn ring_push_is_all_or_nothing() {
+        let mut ring = Ring::new(4);
+        assert!(!ring.push(b"abcde"));
+        assert_eq!(ring.len, 0);
+        assert!(ring.push(b"abcd"));
+        assert!(!ring.push(b"e"));
+        assert_eq!(contents(&mut ring), b"abcd");
+    }
+
+    #[test]
+    fn write_fmt_drops_whole_messages() {
+        let port = Port::new(usize::MAX);
+        let console = UartConsole::with_capacity(port.clone(), 16);
+        assert!(console.write_fmt(format_args!("{}-{}", "one", 1)));
+        // Formats in pieces; the part that fit must not stay queued.
+        assert!(!console.write_fmt(format_args!("{}{}", "0123456789", "abcdef")));
+        assert!(!console.write_str("too long for the ring"));
+        assert_eq!(console.dropped(), 2);
+        assert_eq!(console.pending(), 5);
+
+        port.ready.set(true);
+        assert!(console.write_str("|two"));
+        assert_eq!(console.pending(), 0);
+        assert_eq!(*port.out.borrow(), b"one-1|two");
+        assert_eq!(console.dropped(), 2);
+    }
+
+    #[test]
+    fn drains_without_blocking_and_flushes() {
+        let port = Port::new(3);
+        let console = UartConsole::with_capacity(port.clone(), 8);
+        assert!(console.write_str("abcdef"));
+        assert_eq!(console.poll(), Ok(()));
+        assert!(port.out.borrow().is_empty());
+
+        port.ready.set(true);
+        console.poll().unwrap();
+        assert_eq!(*port.out.borrow(), b"abcdef");
+        port.ready.set(false);
+        // Wraps in the ring; flush writes it out even though the port is not ready.
+        assert!(console.write_str("ghijklm"));
+        console.flush().unwrap();
+        assert_eq!(*port.out.borrow(), b"abcdefghijklm");
+        assert!(console.flush_on_panic());
+    }
+
+    /// A port that can back the global console, which must be `Send`.
+    struct SharedPort(Arc<Mutex<Vec<u8>>>);
+
+    impl embedded_io::ErrorType for SharedPort {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Write for SharedPort {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.0.lock().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    impl WriteReady for SharedPort {
+        fn write_ready(&mut self) -> Result<bool, Self::Error> {
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn print_macros_use_the_global_console() {
+        // The only test touching the global console, which cannot be unset.
+        assert!(!crate::print!("lost"));
+        assert!(!flush_global_on_panic());
+
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let console = Box::leak(Box::new(UartConsole::new(SharedPort(out.clone()))));
+        assert!(console.set_global());
+        let other = Box::leak(Box::new(UartConsole::new(SharedPort(Arc::default()))));
+        assert!(!other.set_global());
+
+        assert!(crate::print!("{}-", 1));
+        assert!(crate::println!("{}", "two"));
+        assert!(crate::println!());
+        assert!(flush_global_on_panic());
+        assert_eq!(*out.lock(), b"1-two\r\n\r\n");
+    }
+}
@@ -0,0 +1,592 @@
+//! Packet framing over serial links.
+//!
+//! Two codecs are provided: SLIP (RFC 1055) and the HDLC-like byte stuffing used by PPP
+//! (RFC 1662) with a 16-bit FCS. [`FramedLink`] runs a codec over a byte stream such as
+//! [`UartStream`](super::stream::UartStream) and exposes it as a [`NetIo`] device. Frames carry
+//! whatever the peer sends, typically bare IP packets without an Ethernet header.
+
+use super::from_io_error;
+use crate::interface::NetIo;
+use crate::net::recv::RecvQueue;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use embedded_io::{Read, ReadReady, Write};
+use glenda::error::Error;
+use glenda::io::uring::IoUringCqe;
+
+/// Largest frame accepted by default, excluding framing overhead.
+pub const DEFAULT_MAX_FRAME: usize = 1500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// The frame exceeded the codec's maximum length.
+    TooLong,
+    /// The frame was shorter than its trailer.
+    TooShort,
+    /// An escape byte was followed by an invalid byte, or the sender aborted the frame.
+    BadEscape,
+    /// The frame check sequence did not match.
+    BadFcs,
+}
+
+pub trait FrameCodec {
+    /// Append the encoded form of `frame` to `out`.
+    fn encode(&self, frame: &[u8], out: &mut Vec<u8>);
+    /// Feed one received byte. Returns the frame it completes, or the error that dropped it.
+    fn decode(&mut self, b: u8) -> Option<Result<Vec<u8>, FrameError>>;
+    /// Discard any partially received frame.
+    fn reset(&mut self);
+}
+
+pub const SLIP_END: u8 = 0xc0;
+pub const SLIP_ESC: u8 = 0xdb;
+pub const SLIP_ESC_END: u8 = 0xdc;
+pub const SLIP_ESC_ESC: u8 = 0xdd;
+
+/// SLIP framing. Each frame is also preceded by END to flush line noise at the receiver.
+pub struct SlipCodec {
+    max_len: usize,
+    buf: Vec<u8>,
+    escaped: bool,
+    /// Set after an error until the next END.
+    discard: bool,
+}
+
+impl Default for SlipCodec {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_FRAME)
+    }
+}
+
+impl SlipCodec {
+    pub fn new(max_len: usize) -> Self {
+        Self { max_len, buf: Vec::new(), escaped: false, discard: false }
+    }
+
+    fn fail(&mut self, e: FrameError) -> Option<Result<Vec<u8>, FrameError>> {
+        self.buf.clear();
+        self.escaped = false;
+        self.discard = true;
+        Some(Err(e))
+    }
+}
+
+impl FrameCodec for SlipCodec {
+    fn encode(&self, frame: &[u8], out: &mut Vec<u8>) {
+        out.push(SLIP_END);
+        for &b in frame {
+            match b {
+                SLIP_END => out.extend_from_slice(&[SLIP_ESC, SLIP_ESC_END]),
+                SLIP_ESC => out.extend_from_slice(&[SLIP_ESC, SLIP_ESC_ESC]),
+                _ => out.push(b),
+            }
+        }
+        out.push(SLIP_END);
+    }
+
+    fn decode(&mut self, b: u8) -> Option<Result<Vec<u8>, FrameError>> {
+        if b == SLIP_END {
+            let discard = core::mem::replace(&mut self.discard, false);
+            self.escaped = false;
+            if discard || self.buf.is_empty() {
+                self.buf.clear();
+                return None;
+            }
+            return Some(Ok(core::mem::take(&mut self.buf)));
+        }
+        if self.discard {
+            return None;
+        }
+        let b = if core::mem::replace(&mut self.escaped, false) {
+            match b {
+                SLIP_ESC_END => SLIP_END,
+                SLIP_ESC_ESC => SLIP_ESC,
+                _ => return self.fail(FrameError::BadEscape),
+            }
+        } else if b == SLIP_ESC {
+            self.escaped = true;
+            return None;
+        } else {
+            b
+        };
+        if self.buf.len() >= self.max_len {
+            return self.fail(FrameError::TooLong);
+        }
+        self.buf.push(b);
+        None
+    }
+
+    fn reset(&mut self) {
+        self.buf.clear();
+        self.escaped = false;
+        self.discard = false;
+    }
+}
+
+pub const HDLC_FLAG: u8 = 0x7e;
+pub const HDLC_ESC: u8 = 0x7d;
+pub const HDLC_XOR: u8 = 0x20;
+/// Initial FCS-16 value.
+pub const FCS16_INIT: u16 = 0xffff;
+/// FCS-16 computed over a frame including its FCS when the frame is intact.
+pub const FCS16_GOOD: u16 = 0xf0b8;
+
+/// Update an FCS-16 (CRC-16/X.25, reflected polynomial 0x8408) with `data`.
+pub fn fcs16(mut fcs: u16, data: &[u8]) -> u16 {
+    for &b in data {
+        fcs ^= b as u16;
+        for _ in 0..8 {
+            fcs = if fcs & 1 != 0 { (fcs >> 1) ^ 0x8408 } else { fcs >> 1 };
+        }
+    }
+    fcs
+}
+
+/// HDLC-like framing as used by PPP: 0x7E flags, 0x7D escapes and a trailing FCS-16.
+pub struct HdlcCodec {
+    max_len: usize,
+    /// Async control character map: bit n set means byte n is escaped on transmit.
+    accm: u32,
+    buf: Vec<u8>,
+    escaped: bool,
+    discard: bool,
+}
+
+impl Default for HdlcCodec {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_FRAME)
+    }
+}
+
+impl HdlcCodec {
+    /// A codec escaping every control character, as required before ACCM negotiation.
+    pub fn new(max_len: usize) -> Self {
+        Self { max_len, accm: u32::MAX, buf: Vec::new(), escaped: false, discard: false }
+    }
+
+    pub fn accm(&self) -> u32 {
+        self.accm
+    }
+
+    pub fn set_accm(&mut self, accm: u32) {
+        self.accm = accm;
+    }
+
+    fn needs_escape(&self, b: u8) -> bool {
+        b == HDLC_FLAG || b == HDLC_ESC || (b < 0x20 && self.accm & (1 << b) != 0)
+    }
+
+    fn push_escaped(&self, b: u8, out: &mut Vec<u8>) {
+        if self.needs_escape(b) {
+            out.extend_from_slice(&[HDLC_ESC, b ^ HDLC_XOR]);
+        } else {
+            out.push(b);
+        }
+    }
+
+    fn fail(&mut self, e: FrameError) -> Option<Result<Vec<u8>, FrameError>> {
+        self.buf.clear();
+        self.escaped = false;
+        self.discard = true;
+        Some(Err(e))
+    }
+}
+
+impl FrameCodec for HdlcCodec {
+    fn encode(&self, frame: &[u8], out: &mut Vec<u8>) {
+        out.push(HDLC_FLAG);
+        for &b in frame {
+            self.push_escaped(b, out);
+        }
+        let fcs = !fcs16(FCS16_INIT, frame);
+        for b in fcs.to_le_bytes() {
+            self.push_escaped(b, out);
+        }
+        out.push(HDLC_FLAG);
+    }
+
+    fn decode(&mut self, b: u8) -> Option<Result<Vec<u8>, FrameError>> {
+        if b == HDLC_FLAG {
+            let discard = core::mem::replace(&mut self.discard, false);
+            // ESC followed by a flag is the abort sequence.
+            if core::mem::replace(&mut self.escaped, false) && !discard {
+                self.buf.clear();
+                return Some(Err(FrameError::BadEscape));
+            }
+            let frame = core::mem::take(&mut self.buf);
+            if discard || frame.is_empty() {
+                return None;
+            }
+            if frame.len() < 3 {
+                return Some(Err(FrameError::TooShort));
+            }
+            if fcs16(FCS16_INIT, &frame) != FCS16_GOOD {
+                return Some(Err(FrameError::BadFcs));
+            }
+            let mut frame = frame;
+            frame.truncate(frame.len() - 2);
+            return Some(Ok(frame));
+        }
+        if self.discard {
+            return None;
+        }
+        let b = if core::mem::replace(&mut self.escaped, false) {
+            b ^ HDLC_XOR
+        } else if b == HDLC_ESC {
+            self.escaped = true;
+            return None;
+        } else {
+            b
+        };
+        // Room for the frame plus its FCS.
+        if self.buf.len() >= self.max_len + 2 {
+            return self.fail(FrameError::TooLong);
+        }
+        self.buf.push(b);
+        None
+    }
+
+    fn reset(&mut self) {
+        self.buf.clear();
+        self.escaped = false;
+        self.discard = false;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    pub tx_frames: u64,
+    pub rx_frames: u64,
+    /// Frames dropped by the codec (bad FCS, escape or length).
+    pub rx_errors: u64,
+    /// Good frames dropped because no receive buffer was posted in time.
+    pub rx_dropped: u64,
+}
+
+/// Maximum number of decoded frames held while no receive buffer is posted.
+const RX_BACKLOG: usize = 32;
+
+/// A framed serial link usable as a [`NetIo`] device.
+pub struct FramedLink<S, C> {
+    port: RefCell<S>,
+    codec: RefCell<C>,
+    tx_buf: RefCell<Vec<u8>>,
+    frames: RefCell<VecDeque<Vec<u8>>>,
+    recv: RefCell<RecvQueue>,
+    stats: Cell<LinkStats>,
+}
+
+impl<S, C> FramedLink<S, C>
+where
+    S: Read + ReadReady + Write,
+    C: FrameCodec,
+{
+    pub fn new(port: S, codec: C) -> Self {
+        Self {
+            port: RefCell::new(port),
+            codec: RefCell::new(codec),
+            tx_buf: RefCell::new(Vec::new()),
+            frames: RefCell::new(VecDeque::new()),
+            recv: RefCell::new(RecvQueue::default()),
+            stats: Cell::new(LinkStats::default()),
+        }
+    }
+
+    pub fn stats(&self) -> LinkStats {
+        self.stats.get()
+    }
+
+    pub fn codec_mut(&mut self) -> &mut C {
+        self.codec.get_mut()
+    }
+
+    pub fn into_inner(self) -> (S, C) {
+        (self.port.into_inner(), self.codec.into_inner())
+    }
+
+    /// Read whatever the port has buffered and decode it into frames.
+    pub fn poll(&self) -> Result<(), Error> {
+        let mut port = self.port.borrow_mut();
+        let mut codec = self.codec.borrow_mut();
+        let mut frames = self.frames.borrow_mut();
+        let mut stats = self.stats.get();
+        let mut buf = [0u8; 64];
+        while port.read_ready().map_err(from_io_error)? {
+            let n = port.read(&mut buf).map_err(from_io_error)?;
+            if n == 0 {
+                break;
+            }
+            for &b in &buf[..n] {
+                match codec.decode(b) {
+                    Some(Ok(frame)) if frames.len() < RX_BACKLOG => {
+                        stats.rx_frames += 1;
+                        frames.push_back(frame);
+                    }
+                    Some(Ok(_)) => stats.rx_dropped += 1,
+                    Some(Err(_)) => stats.rx_errors += 1,
+                    None => {}
+                }
+            }
+        }
+        self.stats.set(stats);
+        drop((port, codec, frames));
+        self.deliver();
+        Ok(())
+    }
+
+    /// Copy decoded frames into posted buffers.
+    fn deliver(&self) {
+        let mut frames = self.frames.borrow_mut();
+        let mut recv = self.recv.borrow_mut();
+        while recv.has_buffer() {
+            let Some(frame) = frames.pop_front() else {
+                break;
+            };
+            recv.deliver(&frame);
+        }
+    }
+}
+
+impl<S, C> NetIo for FramedLink<S, C>
+where
+    S: Read + ReadReady + Write,
+    C: FrameCodec,
+{
+    fn send_packet(&self, buf: &[u8]) -> Result<(), Error> {
+        let mut tx = self.tx_buf.borrow_mut();
+        tx.clear();
+        self.codec.borrow().encode(buf, &mut tx);
+        let mut port = self.port.borrow_mut();
+        port.write_all(&tx).map_err(from_io_error)?;
+        port.flush().map_err(from_io_error)?;
+        let mut stats = self.stats.get();
+        stats.tx_frames += 1;
+        self.stats.set(stats);
+        Ok(())
+    }
+
+    unsafe fn submit_recv(&self, buf: &mut [u8], id: u64) -> Result<(), Error> {
+        unsafe { self.recv.borrow_mut().post(buf, id)? };
+        self.deliver();
+        Ok(())
+    }
+
+    fn peek_cqe(&self) -> Option<IoUringCqe> {
+        let queued = self.recv.borrow_mut().pop_completion();
+        if queued.is_some() {
+            return queued;
+        }
+        // peek_cqe has no way to report port errors; call `poll` directly to see them.
+        let _ = self.poll();
+        self.recv.borrow_mut().pop_completion()
+    }
+
+    fn take_recv_cqe(&self, id: u64) -> Option<IoUringCqe> {
+        let queued = self.recv.borrow_mut().take_completion(id);
+        if queued.is_some() {
+            return queued;
+        }
+        let _ = self.poll();
+        self.recv.borrow_mut().take_completion(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::rc::Rc;
+    use alloc::vec;
+
+    fn decode_all<C: FrameCodec>(codec: &mut C, data: &[u8]) -> Vec<Result<Vec<u8>, FrameError>> {
+        data.iter().filter_map(|&b| codec.decode(b)).collect()
+    }
+
+    fn round_trip<C: FrameCodec>(codec: &mut C, frame: &[u8]) {
+        let mut encoded = Vec::new();
+        codec.encode(frame, &mut encoded);
+        assert_eq!(decode_all(codec, &encoded), [Ok(frame.to_vec())]);
+    }
+
+    #[test]
+    fn fcs16_reference_vector() {
+        // CRC-16/X.25 check value.
+        assert_eq!(!fcs16(FCS16_INIT, b"123456789"), 0x906e);
+        let mut frame = b"123456789".to_vec();
+        frame.extend_from_slice(&0x906e_u16.to_le_bytes());
+        assert_eq!(fcs16(FCS16_INIT, &frame), FCS16_GOOD);
+    }
+
+    #[test]
+    fn slip_escapes_special_bytes() {
+        let codec = SlipCodec::default();
+        let mut encoded = Vec::new();
+        codec.encode(&[1, SLIP_END, SLIP_ESC, SLIP_ESC_END], &mut encoded);
+        let expected =
+            [SLIP_END, 1, SLIP_ESC, SLIP_ESC_END, SLIP_ESC, SLIP_ESC_ESC, SLIP_ESC_END, SLIP_END];
+        assert_eq!(encoded, expected);
+
+        let all: Vec<u8> = (0..=255).collect();
+        let mut codec = SlipCodec::new(all.len());
+        round_trip(&mut codec, &all);
+        round_trip(&mut codec, &[SLIP_END]);
+    }
+
+    #[test]
+    fn slip_drops_bad_frames() {
+        let mut codec = SlipCodec::new(4);
+        // Empty frames between ENDs are line noise.
+        assert!(decode_all(&mut codec, &[SLIP_END, SLIP_END]).is_empty());
+        let data = [1, SLIP_ESC, 0, 2, SLIP_END, 3, SLIP_END];
+        assert_eq!(decode_all(&mut codec, &data), [Err(FrameError::BadEscape), Ok(vec![3])]);
+        let data = [1, 2, 3, 4, 5, 6, SLIP_END, 7, SLIP_END];
+        assert_eq!(decode_all(&mut codec, &data), [Err(FrameError::TooLong), Ok(vec![7])]);
+
+        // A truncated frame is discarded by a reset.
+        assert!(decode_all(&mut codec, &[8, 9]).is_empty());
+        codec.reset();
+        assert_eq!(decode_all(&mut codec, &[10, SLIP_END]), [Ok(vec![10])]);
+    }
+
+    #[test]
+    fn hdlc_escapes_per_accm() {
+        let mut codec = HdlcCodec::default();
+        let mut encoded = Vec::new();
+        codec.encode(&[HDLC_FLAG, HDLC_ESC, 0x01, 0x41], &mut encoded);
+        let escaped = [HDLC_FLAG, HDLC_ESC, 0x5e, HDLC_ESC, 0x5d, HDLC_ESC, 0x21, 0x41];
+        assert_eq!(encoded[..escaped.len()], escaped);
+        assert_eq!(encoded.last(), Some(&HDLC_FLAG));
+        // Only the flags are unescaped flag bytes, and no control characters go out raw.
+        let body = &encoded[1..encoded.len() - 1];
+        assert!(body.iter().all(|&b| b != HDLC_FLAG && b >= 0x20));
+
+        codec.set_accm(0);
+        encoded.clear();
+        codec.encode(&[0x01], &mut encoded);
+        assert_eq!(encoded[..2], [HDLC_FLAG, 0x01]);
+
+        let all: Vec<u8> = (0..=255).collect();
+        for accm in [u32::MAX, 0] {
+            let mut codec = HdlcCodec::new(all.len());
+            codec.set_accm(accm);
+            round_trip(&mut codec, &all);
+            round_trip(&mut codec, b"123456789");
+        }
+    }
+
+    #[test]
+    fn hdlc_drops_bad_frames() {
+        let mut codec = HdlcCodec::new(4);
+        let mut encoded = Vec::new();
+        codec.encode(b"good", &mut encoded);
+
+        let mut corrupt = encoded.clone();
+        corrupt[2] ^= 0x01;
+        assert_eq!(decode_all(&mut codec, &corrupt), [Err(FrameError::BadFcs)]);
+
+        // Fewer bytes than an FCS plus one byte of data.
+        let data = [HDLC_FLAG, 0x41, 0x42, HDLC_FLAG];
+        assert_eq!(decode_all(&mut codec, &data), [Err(FrameError::TooShort)]);
+        let truncated = &encoded[..encoded.len() - 2];
+        assert_eq!(decode_all(&mut codec, truncated), []);
+        assert_eq!(decode_all(&mut codec, &[HDLC_FLAG]), [Err(FrameError::BadFcs)]);
+
+        // ESC followed by a flag aborts the frame.
+        let data = [HDLC_FLAG, 0x41, HDLC_ESC, HDLC_FLAG];
+        assert_eq!(decode_all(&mut codec, &data), [Err(FrameError::BadEscape)]);
+
+        let mut long = Vec::new();
+        codec.encode(b"too long", &mut long);
+        assert_eq!(decode_all(&mut codec, &long), [Err(FrameError::TooLong)]);
+        assert_eq!(decode_all(&mut codec, &encoded), [Ok(b"good".to_vec())]);
+    }
+
+    type Bytes = Rc<RefCell<VecDeque<u8>>>;
+
+    /// One end of a byte pipe pair.
+    struct Pipe {
+        rx: Bytes,
+        tx: Bytes,
+    }
+
+    fn pipes() -> (Pipe, Pipe) {
+        let (a, b) = (Bytes::default(), Bytes::default());
+        (Pipe { rx: a.clone(), tx: b.clone() }, Pipe { rx: b, tx: a })
+    }
+
+    impl embedded_io::ErrorType for Pipe {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let mut rx = self.rx.borrow_mut();
+            let n = core::cmp::min(buf.len(), rx.len());
+            for (dst, src) in buf.iter_mut().zip(rx.drain(..n)) {
+                *dst = src;
+            }
+            Ok(n)
+        }
+    }
+
+    impl ReadReady for Pipe {
+        fn read_ready(&mut self) -> Result<bool, Self::Error> {
+            Ok(!self.rx.borrow().is_empty())
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.tx.borrow_mut().extend(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn framed_link_carries_frames() {
+        let (a, b) = pipes();
+        let noise = b.rx.clone();
+        let a = FramedLink::new(a, HdlcCodec::default());
+        let b = FramedLink::new(b, HdlcCodec::default());
+
+        a.send_packet(b"first").unwrap();
+        noise.borrow_mut().extend([HDLC_FLAG, 0x41, 0x42, 0x43, HDLC_FLAG]);
+        a.send_packet(&[HDLC_FLAG, 0x00, HDLC_ESC]).unwrap();
+
+        let mut buf = [0u8; 16];
+        unsafe { b.submit_recv(&mut buf, 7).unwrap() };
+        let cqe = b.take_recv_cqe(7).unwrap();
+        assert_eq!(&buf[..cqe.res as usize], b"first");
+        unsafe { b.submit_recv(&mut buf, 8).unwrap() };
+        let cqe = b.peek_cqe().unwrap();
+        assert_eq!(cqe.user_data, 8);
+        assert_eq!(&buf[..cqe.res as usize], [HDLC_FLAG, 0x00, HDLC_ESC]);
+        assert!(b.peek_cqe().is_none());
+
+        assert_eq!(a.stats(), LinkStats { tx_frames: 2, ..Default::default() });
+        assert_eq!(b.stats(), LinkStats { rx_frames: 2, rx_errors: 1, ..Default::default() });
+    }
+
+    #[test]
+    fn framed_link_drops_frames_beyond_the_backlog() {
+        let (a, b) = pipes();
+        let a = FramedLink::new(a, SlipCodec::default());
+        let b = FramedLink::new(b, SlipCodec::default());
+        for i in 0..RX_BACKLOG as u8 + 1 {
+            a.send_packet(&[i]).unwrap();
+        }
+        b.poll().unwrap();
+        let stats = b.stats();
+        assert_eq!((stats.rx_frames, stats.rx_dropped), (RX_BACKLOG as u64, 1));
+
+        // The oldest frames are kept.
+        let mut buf = [0u8; 4];
+        unsafe { b.submit_recv(&mut buf, 0).unwrap() };
+        assert_eq!(b.peek_cqe().unwrap().res, 1);
+        assert_eq!(buf[0], 0);
+    }
+}
@@ -0,0 +1,667 @@
+//! Terminal line discipline for interactive consoles.
+//!
+//! [`LineDiscipline`] sits between a serial port and an application, much like termios. In raw
+//! mode input bytes are passed through after newline mapping. In canonical mode input is
+//! collected into lines that can be edited with backspace, Ctrl-U (kill line), Ctrl-W (erase
+//! word) and the up/down arrow keys (history) before Enter hands them to the reader. Signal
+//! characters such as Ctrl-C are reported through a callback instead of being queued. The `eof`
+//! character, Ctrl-D by default, hands over a partial line without a newline, or on an empty line
+//! signals end of file.
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use embedded_io::{Read, ReadReady, Write};
+
+pub const CTRL_C: u8 = 0x03;
+pub const CTRL_D: u8 = 0x04;
+pub const CTRL_U: u8 = 0x15;
+pub const CTRL_W: u8 = 0x17;
+pub const CTRL_Z: u8 = 0x1a;
+pub const CTRL_BACKSLASH: u8 = 0x1c;
+pub const BACKSPACE: u8 = 0x08;
+pub const DELETE: u8 = 0x7f;
+pub const ESC: u8 = 0x1b;
+
+/// Default number of lines kept in the history.
+pub const DEFAULT_HISTORY_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// `intr` character, Ctrl-C by default.
+    Interrupt,
+    /// `quit` character, Ctrl-\ by default.
+    Quit,
+    /// `susp` character, Ctrl-Z by default.
+    Suspend,
+}
+
+/// Callback invoked when a signal character is received.
+pub type SignalCallback = Arc<dyn Fn(Signal) + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Termios {
+    /// Collect input into editable lines instead of passing bytes through.
+    pub canonical: bool,
+    /// Echo input back to the port.
+    pub echo: bool,
+    /// Turn `intr`, `quit` and `susp` into signals.
+    pub isig: bool,
+    /// Map CR to NL on input.
+    pub icrnl: bool,
+    /// Map NL to CR on input.
+    pub inlcr: bool,
+    /// Drop CR on input.
+    pub igncr: bool,
+    /// Map NL to CR NL on output.
+    pub onlcr: bool,
+    pub intr: u8,
+    pub quit: u8,
+    pub susp: u8,
+    pub erase: u8,
+    pub kill: u8,
+    pub werase: u8,
+    pub eof: u8,
+}
+
+impl Default for Termios {
+    /// Canonical mode with echo, signals and the usual serial console newline mapping.
+    fn default() -> Self {
+        Self {
+            canonical: true,
+            echo: true,
+            isig: true,
+            icrnl: true,
+            inlcr: false,
+            igncr: false,
+            onlcr: true,
+            intr: CTRL_C,
+            quit: CTRL_BACKSLASH,
+            susp: CTRL_Z,
+            erase: DELETE,
+            kill: CTRL_U,
+            werase: CTRL_W,
+            eof: CTRL_D,
+        }
+    }
+}
+
+impl Termios {
+    /// Raw mode: no line editing, echo, signals or newline mapping.
+    pub fn raw() -> Self {
+        Self {
+            canonical: false,
+            echo: false,
+            isig: false,
+            icrnl: false,
+            inlcr: false,
+            igncr: false,
+            onlcr: false,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EscState {
+    None,
+    Esc,
+    Csi,
+}
+
+pub struct LineDiscipline {
+    termios: Termios,
+    /// Line being edited in canonical mode.
+    line: Vec<u8>,
+    /// Completed lines (canonical) or bytes (raw) waiting to be read.
+    input: VecDeque<u8>,
+    /// Stream offsets at which the `eof` character was received, and whether the line was
+    /// empty. A read stops at a mark; an empty one is reported as end of file.
+    eof_marks: VecDeque<(usize, bool)>,
+    /// Bytes taken from `input` so far, wrapping.
+    consumed: usize,
+    history: VecDeque<Vec<u8>>,
+    history_len: usize,
+    /// Position while browsing history, 0 being the most recent entry.
+    history_pos: Option<usize>,
+    /// The line being edited before browsing started.
+    saved_line: Vec<u8>,
+    esc: EscState,
+    signal_cb: Option<SignalCallback>,
+    echo_buf: Vec<u8>,
+}
+
+impl Default for LineDiscipline {
+    fn default() -> Self {
+        Self::new(Termios::default())
+    }
+}
+
+impl LineDiscipline {
+    pub fn new(termios: Termios) -> Self {
+        Self {
+            termios,
+            line: Vec::new(),
+            input: VecDeque::new(),
+            eof_marks: VecDeque::new(),
+            consumed: 0,
+            history: VecDeque::new(),
+            history_len: DEFAULT_HISTORY_LEN,
+            history_pos: None,
+            saved_line: Vec::new(),
+            esc: EscState::None,
+            signal_cb: None,
+            echo_buf: Vec::new(),
+        }
+    }
+
+    pub fn termios(&self) -> &Termios {
+        &self.termios
+    }
+
+    /// Change the settings. Switching out of canonical mode releases the partial line.
+    pub fn set_termios(&mut self, termios: Termios) {
+        if self.termios.canonical && !termios.canonical {
+            self.input.extend(self.line.drain(..));
+            self.history_pos = None;
+        }
+        self.termios = termios;
+        self.esc = EscState::None;
+    }
+
+    /// Set how many lines the history keeps. Zero disables history.
+    pub fn set_history_len(&mut self, len: usize) {
+        self.history_len = len;
+        while self.history.len() > len {
+            self.history.pop_back();
+        }
+    }
+
+    pub fn history(&self) -> impl Iterator<Item = &[u8]> + '_ {
+        self.history.iter().map(|l| l.as_slice())
+    }
+
+    pub fn on_signal(&mut self, cb: SignalCallback) {
+        self.signal_cb = Some(cb);
+    }
+
+    /// The line currently being edited.
+    pub fn pending_line(&self) -> &[u8] {
+        &self.line
+    }
+
+    /// Whether [`read`](Self::read) would return data or report end of file.
+    pub fn has_input(&self) -> bool {
+        !self.input.is_empty() || !self.eof_marks.is_empty()
+    }
+
+    /// Whether the next [`read`](Self::read) reports end of file.
+    pub fn at_eof(&self) -> bool {
+        self.eof_marks.front() == Some(&(self.consumed, true))
+    }
+
+    /// Process received bytes, writing any echo to `echo`.
+    pub fn receive<W: Write>(&mut self, data: &[u8], echo: &mut W) -> Result<(), W::Error> {
+        for &b in data {
+            self.receive_byte(b);
+        }
+        let ret = echo.write_all(&self.echo_buf);
+        self.echo_buf.clear();
+        ret
+    }
+
+    /// Copy input into `buf`. In canonical mode at most one line is returned, including its
+    /// newline if it has one. Returns 0 if nothing is ready, and once for each end of file;
+    /// [`at_eof`](Self::at_eof) tells the two apart.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        if self.at_eof() {
+            self.eof_marks.pop_front();
+            return 0;
+        }
+        let mut n = 0;
+        while n < buf.len() {
+            let Some(b) = self.input.pop_front() else {
+                break;
+            };
+            buf[n] = b;
+            n += 1;
+            self.consumed = self.consumed.wrapping_add(1);
+            if let Some(&(end, eof)) = self.eof_marks.front()
+                && end == self.consumed
+            {
+                // An end of file is left for the next read.
+                if !eof {
+                    self.eof_marks.pop_front();
+                }
+                break;
+            }
+            if self.termios.canonical && b == b'\n' {
+                break;
+            }
+        }
+        n
+    }
+
+    /// Take the next completed line without its newline. A line handed over with the `eof`
+    /// character is returned as is; an end of file is left for [`read`](Self::read).
+    pub fn read_line(&mut self) -> Option<Vec<u8>> {
+        if self.at_eof() {
+            return None;
+        }
+        let newline = self.input.iter().position(|&b| b == b'\n');
+        let mark = match self.eof_marks.front() {
+            Some(&(end, false)) => Some(end.wrapping_sub(self.consumed)),
+            _ => None,
+        };
+        let (len, skip) = match (newline, mark) {
+            (Some(n), Some(m)) if m <= n => (m, 0),
+            (Some(n), _) => (n, 1),
+            (None, m) => (m?, 0),
+        };
+        let line = self.input.drain(..len).collect();
+        self.input.drain(..skip);
+        self.consumed = self.consumed.wrapping_add(len + skip);
+        if skip == 0 {
+            self.eof_marks.pop_front();
+        }
+        Some(line)
+    }
+
+    /// Write output to `out`, applying output newline mapping.
+    pub fn write<W: Write>(&self, out: &mut W, data: &[u8]) -> Result<(), W::Error> {
+        if !self.termios.onlcr {
+            return out.write_all(data);
+        }
+        for chunk in data.split_inclusive(|&b| b == b'\n') {
+            match chunk.strip_suffix(b"\n") {
+                Some(text) => {
+                    out.write_all(text)?;
+                    out.write_all(b"\r\n")?;
+                }
+                None => out.write_all(chunk)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Move everything readable from `port` through the discipline, echoing back to it.
+    pub fn poll<P: Read + ReadReady + Write>(&mut self, port: &mut P) -> Result<(), P::Error> {
+        let mut buf = [0u8; 64];
+        while port.read_ready()? {
+            let n = port.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            self.receive(&buf[..n], port)?;
+        }
+        Ok(())
+    }
+
+    fn receive_byte(&mut self, mut b: u8) {
+        let t = self.termios;
+        if b == b'\r' {
+            if t.igncr {
+                return;
+            }
+            if t.icrnl {
+                b = b'\n';
+            }
+        } else if b == b'\n' && t.inlcr {
+            b = b'\r';
+        }
+
+        if t.isig {
+            let signal = match b {
+                _ if b == t.intr => Some(Signal::Interrupt),
+                _ if b == t.quit => Some(Signal::Quit),
+                _ if b == t.susp => Some(Signal::Suspend),
+                _ => None,
+            };
+            if let Some(signal) = signal {
+                self.signal(b, signal);
+                return;
+            }
+        }
+
+        if !t.canonical {
+            self.input.push_back(b);
+            if t.echo {
+                self.echo_char(b);
+            }
+            return;
+        }
+
+        match self.esc {
+            EscState::Esc => {
+                self.esc = if b == b'[' { EscState::Csi } else { EscState::None };
+                return;
+            }
+            EscState::Csi => {
+                // Parameter and intermediate bytes continue the sequence.
+                if (0x20..0x40).contains(&b) {
+                    return;
+                }
+                self.esc = EscState::None;
+                match b {
+                    b'A' => self.history_up(),
+                    b'B' => self.history_down(),
+                    _ => {}
+                }
+                return;
+            }
+            EscState::None => {}
+        }
+
+        match b {
+            ESC => self.esc = EscState::Esc,
+            b'\n' => {
+                self.echo_raw(b"\r\n");
+                let line = core::mem::take(&mut self.line);
+                self.push_history(&line);
+                self.input.extend(line);
+                self.input.push_back(b'\n');
+                self.history_pos = None;
+            }
+            _ if b == t.erase || b == BACKSPACE => {
+                if self.line.pop().is_some() {
+                    self.echo_raw(b"\x08 \x08");
+                }
+            }
+            _ if b == t.kill => self.erase_chars(self.line.len()),
+            _ if b == t.werase => {
+                let trimmed = self.line.iter().rposition(|&c| c != b' ').map_or(0, |i| i + 1);
+                let start =
+                    self.line[..trimmed].iter().rposition(|&c| c == b' ').map_or(0, |i| i + 1);
+                self.erase_chars(self.line.len() - start);
+            }
+            _ if b == t.eof => {
+                // Hand over the line without a terminator; on an empty line this is end of file.
+                let line = core::mem::take(&mut self.line);
+                let empty = line.is_empty();
+                self.input.extend(line);
+                let end = self.consumed.wrapping_add(self.input.len());
+                self.eof_marks.push_back((end, empty));
+                self.history_pos = None;
+            }
+            _ => {
+                self.line.push(b);
+                self.echo_char(b);
+            }
+        }
+    }
+
+    fn signal(&mut self, b: u8, signal: Signal) {
+        if self.termios.echo {
+            self.echo_char(b);
+            self.echo_raw(b"\r\n");
+        }
+        self.line.clear();
+        self.history_pos = None;
+        self.esc = EscState::None;
+        if let Some(cb) = &self.signal_cb {
+            cb(signal);
+        }
+    }
+
+    fn push_history(&mut self, line: &[u8]) {
+        if self.history_len == 0 || line.is_empty() {
+            return;
+        }
+        if self.history.front().is_some_and(|l| l.as_slice() == line) {
+            return;
+        }
+        self.history.push_front(line.to_vec());
+        self.history.truncate(self.history_len);
+    }
+
+    fn history_up(&mut self) {
+        let next = self.history_pos.map_or(0, |p| p + 1);
+        if next >= self.history.len() {
+            return;
+        }
+        if self.history_pos.is_none() {
+            self.saved_line = self.line.clone();
+        }
+        self.history_pos = Some(next);
+        let line = self.history[next].clone();
+        self.replace_line(line);
+    }
+
+    fn history_down(&mut self) {
+        let Some(pos) = self.history_pos else {
+            return;
+        };
+        let line = if pos == 0 {
+            self.history_pos = None;
+            core::mem::take(&mut self.saved_line)
+        } else {
+            self.history_pos = Some(pos - 1);
+            self.history[pos - 1].clone()
+        };
+        self.replace_line(line);
+    }
+
+    fn replace_line(&mut self, line: Vec<u8>) {
+        self.erase_chars(self.line.len());
+        for &b in &line {
+            self.echo_char(b);
+        }
+        self.line = line;
+    }
+
+    /// Remove `n` characters from the end of the line and from the screen.
+    fn erase_chars(&mut self, n: usize) {
+        let start = self.line.len() - n;
+        self.line.truncate(start);
+        if self.termios.echo {
+            for _ in 0..n {
+                self.echo_buf.extend_from_slice(b"\x08 \x08");
+            }
+        }
+    }
+
+    /// Echo a received byte, showing control characters as `^X`.
+    fn echo_char(&mut self, b: u8) {
+        if !self.termios.echo {
+            return;
+        }
+        match b {
+            b'\n' if self.termios.onlcr => self.echo_buf.extend_from_slice(b"\r\n"),
+            b'\n' | b'\r' | b'\t' => self.echo_buf.push(b),
+            0..0x20 => self.echo_buf.extend_from_slice(&[b'^', b + 0x40]),
+            DELETE => self.echo_buf.extend_from_slice(b"^?"),
+            _ => self.echo_buf.push(b),
+        }
+    }
+
+    fn echo_raw(&mut self, s: &[u8]) {
+        if self.termios.echo {
+            self.echo_buf.extend_from_slice(s);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use spin::Mutex;
+
+    struct Echo(Vec<u8>);
+
+    impl embedded_io::ErrorType for Echo {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Write for Echo {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.0.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    fn feed(l: &mut LineDiscipline, data: &[u8]) -> Vec<u8> {
+        let mut echo = Echo(Vec::new());
+        l.receive(data, &mut echo).unwrap();
+        echo.0
+    }
+
+    #[test]
+    fn canonical_editing() {
+        let mut l = LineDiscipline::default();
+        feed(&mut l, b"helx\x7flo");
+        assert_eq!(l.pending_line(), b"hello");
+        assert!(!l.has_input());
+        feed(&mut l, b" big wor\x17world\x08d\r");
+        assert_eq!(l.read_line().unwrap(), b"hello big world");
+        feed(&mut l, b"gone\x15kept\n");
+        let mut buf = [0u8; 16];
+        assert_eq!(l.read(&mut buf), 5);
+        assert_eq!(&buf[..5], b"kept\n");
+        assert_eq!(l.read(&mut buf), 0);
+    }
+
+    #[test]
+    fn read_stops_at_each_line() {
+        let mut l = LineDiscipline::default();
+        feed(&mut l, b"one\ntwo\n");
+        let mut buf = [0u8; 16];
+        assert_eq!(l.read(&mut buf), 4);
+        assert_eq!(l.read(&mut buf[..2]), 2);
+        assert_eq!(l.read(&mut buf), 2);
+        assert_eq!(&buf[..2], b"o\n");
+    }
+
+    #[test]
+    fn echo() {
+        let mut l = LineDiscipline::default();
+        assert_eq!(feed(&mut l, b"ab\x7f\x01\r"), b"ab\x08 \x08^A\r\n");
+        assert_eq!(feed(&mut l, b"xy\x15"), b"xy\x08 \x08\x08 \x08");
+        assert_eq!(feed(&mut l, b"\x7f"), b"");
+
+        l.set_termios(Termios { echo: false, ..Termios::default() });
+        assert_eq!(feed(&mut l, b"quiet\r"), b"");
+        assert_eq!(l.read_line().unwrap(), b"a\x01");
+        assert_eq!(l.read_line().unwrap(), b"quiet");
+    }
+
+    #[test]
+    fn newline_mapping() {
+        let mut l = LineDiscipline::new(Termios::raw());
+        feed(&mut l, b"a\r\nb");
+        let mut buf = [0u8; 8];
+        assert_eq!(l.read(&mut buf), 4);
+        assert_eq!(&buf[..4], b"a\r\nb");
+
+        l.set_termios(Termios { icrnl: true, ..Termios::raw() });
+        feed(&mut l, b"\r");
+        assert_eq!(l.read(&mut buf), 1);
+        assert_eq!(buf[0], b'\n');
+
+        l.set_termios(Termios { igncr: true, icrnl: true, ..Termios::raw() });
+        feed(&mut l, b"\r\n");
+        assert_eq!(l.read(&mut buf), 1);
+        assert_eq!(buf[0], b'\n');
+
+        l.set_termios(Termios { inlcr: true, ..Termios::raw() });
+        feed(&mut l, b"\n");
+        assert_eq!(l.read(&mut buf), 1);
+        assert_eq!(buf[0], b'\r');
+
+        let mut out = Echo(Vec::new());
+        l.write(&mut out, b"x\ny\n").unwrap();
+        assert_eq!(out.0, b"x\ny\n");
+        l.set_termios(Termios::default());
+        out.0.clear();
+        l.write(&mut out, b"x\ny\n").unwrap();
+        assert_eq!(out.0, b"x\r\ny\r\n");
+    }
+
+    #[test]
+    fn signals() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut l = LineDiscipline::default();
+        let log = seen.clone();
+        l.on_signal(Arc::new(move |s| log.lock().push(s)));
+
+        assert_eq!(feed(&mut l, b"abc\x03"), b"abc^C\r\n");
+        assert_eq!(l.pending_line(), b"");
+        assert!(!l.has_input());
+        feed(&mut l, b"\x1c\x1a");
+        assert_eq!(*seen.lock(), vec![Signal::Interrupt, Signal::Quit, Signal::Suspend]);
+
+        // Without isig the characters are ordinary input.
+        l.set_termios(Termios { isig: false, ..Termios::raw() });
+        feed(&mut l, b"\x03");
+        assert_eq!(seen.lock().len(), 3);
+        let mut buf = [0u8; 4];
+        assert_eq!(l.read(&mut buf), 1);
+        assert_eq!(buf[0], CTRL_C);
+    }
+
+    #[test]
+    fn history() {
+        let mut l = LineDiscipline::default();
+        l.set_history_len(2);
+        feed(&mut l, b"one\rtwo\rtwo\r\rthree\r");
+        assert_eq!(l.history().collect::<Vec<_>>(), [b"three".as_slice(), b"two"]);
+        while l.read_line().is_some() {}
+
+        feed(&mut l, b"draft\x1b[A");
+        assert_eq!(l.pending_line(), b"three");
+        feed(&mut l, b"\x1b[A\x1b[A");
+        assert_eq!(l.pending_line(), b"two");
+        feed(&mut l, b"\x1b[B");
+        assert_eq!(l.pending_line(), b"three");
+        feed(&mut l, b"\x1b[B");
+        assert_eq!(l.pending_line(), b"draft");
+
+        feed(&mut l, b"\x15\x1b[A!\r");
+        assert_eq!(l.read_line().unwrap(), b"three!");
+        assert_eq!(l.history().next().unwrap(), b"three!");
+    }
+
+    #[test]
+    fn eof_on_an_empty_line() {
+        let mut l = LineDiscipline::default();
+        let mut buf = [0u8; 8];
+        assert!(!l.at_eof());
+        feed(&mut l, b"\x04");
+        assert!(l.has_input());
+        assert!(l.at_eof());
+        assert_eq!(l.read(&mut buf), 0);
+        assert!(!l.has_input());
+        assert!(!l.at_eof());
+
+        // An end of file after a line is reported once the line has been read.
+        feed(&mut l, b"last\r\x04");
+        assert_eq!(l.read(&mut buf), 5);
+        assert!(l.at_eof());
+        assert_eq!(l.read_line(), None);
+        assert_eq!(l.read(&mut buf), 0);
+        assert!(!l.has_input());
+    }
+
+    #[test]
+    fn eof_hands_over_a_partial_line() {
+        let mut l = LineDiscipline::default();
+        let mut buf = [0u8; 8];
+        feed(&mut l, b"ab\x04cd\r");
+        assert_eq!(l.read(&mut buf), 2);
+        assert_eq!(&buf[..2], b"ab");
+        assert!(!l.at_eof());
+        assert_eq!(l.read(&mut buf), 3);
+        assert_eq!(&buf[..3], b"cd\n");
+
+        feed(&mut l, b"ab\x04\x04cd\r");
+        assert_eq!(l.read_line().unwrap(), b"ab");
+        assert_eq!(l.read_line(), None);
+        assert_eq!(l.read(&mut buf), 0);
+        assert_eq!(l.read_line().unwrap(), b"cd");
+        assert!(!l.has_input());
+    }
+}
@@ -0,0 +1,275 @@
+//! In-memory loopback UART for exercising serial protocols without hardware.
+//!
+//! [`LoopbackUart`] implements both [`UartDriver`] and the `embedded-io` traits. A single port
+//! receives what it sends; a pair is wired back to back like a null-modem cable, with DTR
+//! feeding the peer's DSR and DCD and RTS feeding its CTS. Everything runs in the caller's
+//! thread, so reads never block: an empty line reads as 0 bytes.
+
+use super::IoError;
+use crate::interface::UartDriver;
+use crate::protocol::uart::{
+    EVENT_BREAK, MODEM_CTS, MODEM_DCD, MODEM_DSR, MODEM_DTR, MODEM_RTS, Rs485Config, UartConfig,
+    UartStats,
+};
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use glenda::error::Error;
+
+/// Bytes buffered per direction before further input is dropped.
+pub const DEFAULT_LINE_CAPACITY: usize = 4096;
+
+/// One direction of the cable.
+struct Line {
+    data: VecDeque<u8>,
+    capacity: usize,
+    /// DTR/RTS levels driven by the sending end.
+    lines: u32,
+    /// Breaks sent towards the receiving end.
+    breaks: u64,
+    dropped: u64,
+}
+
+impl Line {
+    fn new(capacity: usize) -> Self {
+        Self { data: VecDeque::new(), capacity, lines: 0, breaks: 0, dropped: 0 }
+    }
+
+    fn push(&mut self, b: u8) {
+        if self.data.len() < self.capacity {
+            self.data.push_back(b);
+        } else {
+            self.dropped += 1;
+        }
+    }
+}
+
+/// Settings and counters of one end.
+#[derive(Default)]
+struct State {
+    config: UartConfig,
+    rs485: Rs485Config,
+    event_mask: u32,
+    rx_threshold: u32,
+    rx_bytes: u64,
+    tx_bytes: u64,
+}
+
+/// A software UART end. Clones share the same end.
+#[derive(Clone)]
+pub struct LoopbackUart {
+    tx: Rc<RefCell<Line>>,
+    rx: Rc<RefCell<Line>>,
+    state: Rc<RefCell<State>>,
+}
+
+impl Default for LoopbackUart {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoopbackUart {
+    /// A port whose output is its own input.
+    pub fn new() -> Self {
+        let line = Rc::new(RefCell::new(Line::new(DEFAULT_LINE_CAPACITY)));
+        Self { tx: line.clone(), rx: line, state: Rc::new(RefCell::new(State::default())) }
+    }
+
+    /// Two ports connected back to back.
+    pub fn pair() -> (Self, Self) {
+        Self::pair_with_capacity(DEFAULT_LINE_CAPACITY)
+    }
+
+    pub fn pair_with_capacity(capacity: usize) -> (Self, Self) {
+        let a_to_b = Rc::new(RefCell::new(Line::new(capacity)));
+        let b_to_a = Rc::new(RefCell::new(Line::new(capacity)));
+        let a = Self {
+            tx: a_to_b.clone(),
+            rx: b_to_a.clone(),
+            state: Rc::new(RefCell::new(State::default())),
+        };
+        let b = Self { tx: b_to_a, rx: a_to_b, state: Rc::new(RefCell::new(State::default())) };
+        (a, b)
+    }
+
+    /// Bytes waiting to be read.
+    pub fn available(&self) -> usize {
+        self.rx.borrow().data.len()
+    }
+
+    /// Inject bytes as if the peer had sent them, e.g. to simulate line noise.
+    pub fn inject(&self, data: &[u8]) {
+        let mut rx = self.rx.borrow_mut();
+        for &b in data {
+            rx.push(b);
+        }
+    }
+
+    /// Event mask last set with `set_event_mask`.
+    pub fn event_mask(&self) -> u32 {
+        self.state.borrow().event_mask
+    }
+
+    pub fn rx_threshold(&self) -> u32 {
+        self.state.borrow().rx_threshold
+    }
+
+    fn write_bytes(&self, data: &[u8]) {
+        let mut tx = self.tx.borrow_mut();
+        for &b in data {
+            tx.push(b);
+        }
+        self.state.borrow_mut().tx_bytes += data.len() as u64;
+    }
+
+    fn read_bytes(&self, buf: &mut [u8]) -> usize {
+        let mut rx = self.rx.borrow_mut();
+        let n = core::cmp::min(buf.len(), rx.data.len());
+        for (dst, src) in buf.iter_mut().zip(rx.data.drain(..n)) {
+            *dst = src;
+        }
+        self.state.borrow_mut().rx_bytes += n as u64;
+        n
+    }
+}
+
+impl UartDriver for LoopbackUart {
+    fn put_char(&mut self, c: u8) {
+        self.write_bytes(&[c]);
+    }
+
+    fn get_char(&mut self) -> Option<u8> {
+        let mut b = [0u8];
+        (self.read_bytes(&mut b) == 1).then_some(b[0])
+    }
+
+    fn put_str(&mut self, s: &str) {
+        self.write_bytes(s.as_bytes());
+    }
+
+    fn set_baud_rate(&mut self, baud: u32) {
+        self.state.borrow_mut().config.baud_rate = baud;
+    }
+
+    fn config(&self) -> Result<UartConfig, Error> {
+        Ok(self.state.borrow().config)
+    }
+
+    fn set_config(&mut self, config: &UartConfig) -> Result<(), Error> {
+        if config.baud_rate == 0 || !(5..=8).contains(&config.data_bits) {
+            return Err(Error::InvalidArgs);
+        }
+        self.state.borrow_mut().config = *config;
+        Ok(())
+    }
+
+    fn modem_lines(&self) -> Result<u32, Error> {
+        let own = self.tx.borrow().lines;
+        let peer = self.rx.borrow().lines;
+        let mut lines = own & (MODEM_DTR | MODEM_RTS);
+        if peer & MODEM_RTS != 0 {
+            lines |= MODEM_CTS;
+        }
+        if peer & MODEM_DTR != 0 {
+            lines |= MODEM_DSR | MODEM_DCD;
+        }
+        Ok(lines)
+    }
+
+    fn set_modem_lines(&mut self, mask: u32, levels: u32) -> Result<(), Error> {
+        if mask & !(MODEM_DTR | MODEM_RTS) != 0 {
+            return Err(Error::InvalidArgs);
+        }
+        let mut tx = self.tx.borrow_mut();
+        tx.lines = (tx.lines & !mask) | (levels & mask);
+        Ok(())
+    }
+
+    fn send_break(&mut self, duration_ms: u32) -> Result<(), Error> {
+        if duration_ms == 0 {
+            return Err(Error::InvalidArgs);
+        }
+        self.tx.borrow_mut().breaks += 1;
+        Ok(())
+    }
+
+    fn set_break(&mut self, enable: bool) -> Result<(), Error> {
+        if enable {
+            self.tx.borrow_mut().breaks += 1;
+        }
+        Ok(())
+    }
+
+    fn stats(&self) -> Result<UartStats, Error> {
+        let state = self.state.borrow();
+        let rx = self.rx.borrow();
+        Ok(UartStats {
+            rx_bytes: state.rx_bytes,
+            tx_bytes: state.tx_bytes,
+            breaks: rx.breaks,
+            rx_dropped: rx.dropped,
+            ..Default::default()
+        })
+    }
+
+    fn set_event_mask(&mut self, mask: u32) -> Result<(), Error> {
+        // Only breaks can happen on a perfect line.
+        self.state.borrow_mut().event_mask = mask & EVENT_BREAK;
+        Ok(())
+    }
+
+    fn set_rx_threshold(&mut self, bytes: u32) -> Result<(), Error> {
+        self.state.borrow_mut().rx_threshold = bytes;
+        Ok(())
+    }
+
+    fn rs485_config(&self) -> Result<Rs485Config, Error> {
+        Ok(self.state.borrow().rs485)
+    }
+
+    fn set_rs485_config(&mut self, config: &Rs485Config) -> Result<(), Error> {
+        if config.address_filter.is_some() && !config.address_mode {
+            return Err(Error::InvalidArgs);
+        }
+        self.state.borrow_mut().rs485 = *config;
+        Ok(())
+    }
+
+    fn send_address(&mut self, address: u8) -> Result<(), Error> {
+        let rs485 = self.state.borrow().rs485;
+        if !rs485.enabled || !rs485.address_mode {
+            return Err(Error::InvalidArgs);
+        }
+        // The 9th bit has no in-memory representation; the address goes out as a plain byte.
+        self.write_bytes(&[address]);
+        Ok(())
+    }
+}
+
+impl embedded_io::ErrorType for LoopbackUart {
+    type Error = IoError;
+}
+
+impl embedded_io::Read for LoopbackUart {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        Ok(self.read_bytes(buf))
+    }
+}
+
+impl embedded_io::ReadReady for LoopbackUart {
+    fn read_ready(&mut self) -> Result<bool, IoError> {
+        Ok(self.available() > 0)
+    }
+}
+
+impl embedded_io::Write for LoopbackUart {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, IoError> {
+        self.write_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,42 @@
+//! Serial helpers layered on top of the UART driver protocol.
+
+pub mod console;
+pub mod framing;
+pub mod ldisc;
+pub mod loopback;
+pub mod modbus;
+pub mod stream;
+pub mod xmodem;
+
+use glenda::error::Error;
+
+/// Wraps the driver [`Error`] so it can be used as an `embedded-io` error.
+#[derive(Debug)]
+pub struct IoError(pub Error);
+
+impl From<Error> for IoError {
+    fn from(e: Error) -> Self {
+        Self(e)
+    }
+}
+
+impl embedded_io::Error for IoError {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        match &self.0 {
+            Error::InvalidArgs => embedded_io::ErrorKind::InvalidInput,
+            Error::NotInitialized => embedded_io::ErrorKind::NotConnected,
+            Error::OutOfMemory => embedded_io::ErrorKind::OutOfMemory,
+            _ => embedded_io::ErrorKind::Other,
+        }
+    }
+}
+
+/// Map an `embedded-io` error back onto the driver [`Error`].
+pub(crate) fn from_io_error<E: embedded_io::Error>(e: E) -> Error {
+    match e.kind() {
+        embedded_io::ErrorKind::InvalidInput => Error::InvalidArgs,
+        embedded_io::ErrorKind::NotConnected => Error::NotInitialized,
+        embedded_io::ErrorKind::OutOfMemory => Error::OutOfMemory,
+        _ => Error::Generic,
+    }
+}
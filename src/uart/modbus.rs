@@ -0,0 +1,881 @@
+//! Modbus RTU master and slave.
+//!
+//! Frames are delimited by line silence: a frame ends once no byte has arrived for 3.5
+//! character times at the configured baud ([`ModbusTiming::from_config`]). Both roles run over
+//! any `embedded-io` port, e.g. a [`UartStream`](super::stream::UartStream), a
+//! [`DriverPort`](super::stream::DriverPort) or a [`LoopbackUart`](super::loopback::LoopbackUart).
+//! `clock` returns the current time in microseconds. For RS-485, configure the port with
+//! `UartDriver::set_rs485_config` first; the driver handles the transceiver direction.
+//!
+//! [`ModbusMaster`] is split-phase: [`send_request`](ModbusMaster::send_request) and
+//! [`poll`](ModbusMaster::poll) never block, and the typed helpers wrap them in a blocking
+//! transaction. [`ModbusSlave`] serves a [`RegisterMap`] from [`poll`](ModbusSlave::poll).
+
+use super::from_io_error;
+use crate::protocol::uart::{Parity, StopBits, UartConfig};
+use alloc::vec;
+use alloc::vec::Vec;
+use embedded_io::{Read, ReadReady, Write};
+use glenda::error::Error;
+
+pub const FC_READ_COILS: u8 = 0x01;
+pub const FC_READ_DISCRETE_INPUTS: u8 = 0x02;
+pub const FC_READ_HOLDING_REGISTERS: u8 = 0x03;
+pub const FC_READ_INPUT_REGISTERS: u8 = 0x04;
+pub const FC_WRITE_SINGLE_COIL: u8 = 0x05;
+pub const FC_WRITE_SINGLE_REGISTER: u8 = 0x06;
+pub const FC_WRITE_MULTIPLE_COILS: u8 = 0x0f;
+pub const FC_WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+/// Set on the function code of an exception response.
+pub const FC_EXCEPTION: u8 = 0x80;
+
+pub const EXCEPTION_ILLEGAL_FUNCTION: u8 = 0x01;
+pub const EXCEPTION_ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+pub const EXCEPTION_ILLEGAL_DATA_VALUE: u8 = 0x03;
+
+/// Slave address that every slave accepts and none answers.
+pub const BROADCAST: u8 = 0;
+/// Largest RTU frame: address, 253-byte PDU and CRC.
+pub const MAX_FRAME_LEN: usize = 256;
+
+const MAX_READ_BITS: u16 = 2000;
+const MAX_READ_REGISTERS: u16 = 125;
+const MAX_WRITE_BITS: u16 = 1968;
+const MAX_WRITE_REGISTERS: u16 = 123;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModbusError {
+    /// No (complete) response arrived in time.
+    Timeout,
+    /// The response failed its CRC check.
+    Crc,
+    /// The slave answered with this exception code.
+    Exception(u8),
+    /// The response did not match the request.
+    Protocol,
+    /// A request is already waiting for its response.
+    Busy,
+    /// The port failed or the request was invalid.
+    Io(Error),
+}
+
+impl From<Error> for ModbusError {
+    fn from(e: Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// CRC-16/MODBUS (reflected polynomial 0xA001, initial value 0xFFFF). Sent low byte first.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xffffu16;
+    for &b in data {
+        crc ^= b as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xa001 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModbusTiming {
+    /// Silence ending a frame (3.5 character times).
+    pub frame_gap_us: u64,
+    /// How long the master waits for the first byte of a response.
+    pub response_timeout_us: u64,
+}
+
+impl ModbusTiming {
+    /// Timing for `config`. Above 19200 baud the spec fixes the gap at 1750us.
+    pub fn from_config(config: &UartConfig) -> Self {
+        let frame_gap_us = if config.baud_rate == 0 || config.baud_rate > 19200 {
+            1750
+        } else {
+            let parity = if config.parity == Parity::None { 0 } else { 1 };
+            let stop_half_bits = match config.stop_bits {
+                StopBits::One => 2,
+                StopBits::OneAndHalf => 3,
+                StopBits::Two => 4,
+            };
+            let half_bits = 2 * (1 + config.data_bits as u64 + parity) + stop_half_bits;
+            // 3.5 characters, rounded up.
+            (half_bits * 7 * 1_000_000).div_ceil(4 * config.baud_rate as u64)
+        };
+        Self { frame_gap_us, response_timeout_us: 1_000_000 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request<'a> {
+    ReadCoils { addr: u16, count: u16 },
+    ReadDiscreteInputs { addr: u16, count: u16 },
+    ReadHoldingRegisters { addr: u16, count: u16 },
+    ReadInputRegisters { addr: u16, count: u16 },
+    WriteSingleCoil { addr: u16, value: bool },
+    WriteSingleRegister { addr: u16, value: u16 },
+    WriteMultipleCoils { addr: u16, values: &'a [bool] },
+    WriteMultipleRegisters { addr: u16, values: &'a [u16] },
+}
+
+impl Request<'_> {
+    pub fn function(&self) -> u8 {
+        match self {
+            Self::ReadCoils { .. } => FC_READ_COILS,
+            Self::ReadDiscreteInputs { .. } => FC_READ_DISCRETE_INPUTS,
+            Self::ReadHoldingRegisters { .. } => FC_READ_HOLDING_REGISTERS,
+            Self::ReadInputRegisters { .. } => FC_READ_INPUT_REGISTERS,
+            Self::WriteSingleCoil { .. } => FC_WRITE_SINGLE_COIL,
+            Self::WriteSingleRegister { .. } => FC_WRITE_SINGLE_REGISTER,
+            Self::WriteMultipleCoils { .. } => FC_WRITE_MULTIPLE_COILS,
+            Self::WriteMultipleRegisters { .. } => FC_WRITE_MULTIPLE_REGISTERS,
+        }
+    }
+
+    /// Append the PDU (function code and data) to `out`.
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), Error> {
+        out.push(self.function());
+        match *self {
+            Self::ReadCoils { addr, count } | Self::ReadDiscreteInputs { addr, count } => {
+                if count == 0 || count > MAX_READ_BITS {
+                    return Err(Error::InvalidArgs);
+                }
+                put_u16s(out, &[addr, count]);
+            }
+            Self::ReadHoldingRegisters { addr, count }
+            | Self::ReadInputRegisters { addr, count } => {
+                if count == 0 || count > MAX_READ_REGISTERS {
+                    return Err(Error::InvalidArgs);
+                }
+                put_u16s(out, &[addr, count]);
+            }
+            Self::WriteSingleCoil { addr, value } => {
+                put_u16s(out, &[addr, if value { 0xff00 } else { 0 }]);
+            }
+            Self::WriteSingleRegister { addr, value } => put_u16s(out, &[addr, value]),
+            Self::WriteMultipleCoils { addr, values } => {
+                if values.is_empty() || values.len() > MAX_WRITE_BITS as usize {
+                    return Err(Error::InvalidArgs);
+                }
+                put_u16s(out, &[addr, values.len() as u16]);
+                out.push(values.len().div_ceil(8) as u8);
+                pack_bits(out, values);
+            }
+            Self::WriteMultipleRegisters { addr, values } => {
+                if values.is_empty() || values.len() > MAX_WRITE_REGISTERS as usize {
+                    return Err(Error::InvalidArgs);
+                }
+                put_u16s(out, &[addr, values.len() as u16]);
+                out.push((values.len() * 2) as u8);
+                put_u16s(out, values);
+            }
+        }
+        Ok(())
+    }
+
+    /// What a valid response must look like.
+    fn expect(&self) -> Expect {
+        match *self {
+            Self::ReadCoils { count, .. } | Self::ReadDiscreteInputs { count, .. } => {
+                Expect::Bits(count)
+            }
+            Self::ReadHoldingRegisters { count, .. } | Self::ReadInputRegisters { count, .. } => {
+                Expect::Registers(count)
+            }
+            Self::WriteSingleCoil { addr, value } => {
+                Expect::Echo(addr, if value { 0xff00 } else { 0 })
+            }
+            Self::WriteSingleRegister { addr, value } => Expect::Echo(addr, value),
+            Self::WriteMultipleCoils { addr, values } => Expect::Echo(addr, values.len() as u16),
+            Self::WriteMultipleRegisters { addr, values } => {
+                Expect::Echo(addr, values.len() as u16)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    /// Coils or discrete inputs, one per requested address.
+    Bits(Vec<bool>),
+    /// Holding or input registers.
+    Registers(Vec<u16>),
+    /// A write was applied. Holds the address and the value (single writes) or count written.
+    Written { addr: u16, value: u16 },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Expect {
+    Bits(u16),
+    Registers(u16),
+    Echo(u16, u16),
+}
+
+struct Pending {
+    slave: u8,
+    function: u8,
+    expect: Expect,
+    sent_at: u64,
+}
+
+fn put_u16s(out: &mut Vec<u8>, values: &[u16]) {
+    for v in values {
+        out.extend_from_slice(&v.to_be_bytes());
+    }
+}
+
+fn get_u16(buf: &[u8], off: usize) -> u16 {
+    u16::from_be_bytes([buf[off], buf[off + 1]])
+}
+
+fn pack_bits(out: &mut Vec<u8>, bits: &[bool]) {
+    for chunk in bits.chunks(8) {
+        out.push(chunk.iter().enumerate().fold(0u8, |acc, (i, &b)| acc | ((b as u8) << i)));
+    }
+}
+
+fn append_crc(frame: &mut Vec<u8>) {
+    let crc = crc16(frame);
+    frame.extend_from_slice(&crc.to_le_bytes());
+}
+
+/// Collects bytes into frames delimited by inter-frame silence.
+struct FrameReceiver {
+    buf: Vec<u8>,
+    last_byte_at: u64,
+    gap_us: u64,
+    overflow: bool,
+}
+
+impl FrameReceiver {
+    fn new(gap_us: u64) -> Self {
+        Self { buf: Vec::new(), last_byte_at: 0, gap_us, overflow: false }
+    }
+
+    /// Read what the port has and return a frame once the line has gone quiet.
+    /// Frames that overflow `MAX_FRAME_LEN` are dropped.
+    fn poll<P: Read + ReadReady>(
+        &mut self,
+        port: &mut P,
+        now: u64,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let mut chunk = [0u8; 64];
+        while port.read_ready().map_err(from_io_error)? {
+            let n = port.read(&mut chunk).map_err(from_io_error)?;
+            if n == 0 {
+                break;
+            }
+            if self.buf.len() + n > MAX_FRAME_LEN {
+                self.overflow = true;
+                self.buf.clear();
+            }
+            if !self.overflow {
+                self.buf.extend_from_slice(&chunk[..n]);
+            }
+            self.last_byte_at = now;
+        }
+        let idle = now.wrapping_sub(self.last_byte_at) >= self.gap_us;
+        if !idle {
+            return Ok(None);
+        }
+        let overflow = core::mem::replace(&mut self.overflow, false);
+        if overflow || self.buf.is_empty() {
+            self.buf.clear();
+            return Ok(None);
+        }
+        Ok(Some(core::mem::take(&mut self.buf)))
+    }
+
+    /// Whether bytes of an unfinished frame are buffered.
+    fn receiving(&self) -> bool {
+        !self.buf.is_empty() || self.overflow
+    }
+}
+
+pub struct ModbusMaster<P, C> {
+    port: P,
+    clock: C,
+    timing: ModbusTiming,
+    rx: FrameReceiver,
+    pending: Option<Pending>,
+}
+
+impl<P, C> ModbusMaster<P, C>
+where
+    P: Read + ReadReady + Write,
+    C: Fn() -> u64,
+{
+    pub fn new(port: P, clock: C, timing: ModbusTiming) -> Self {
+        Self { port, clock, timing, rx: FrameReceiver::new(timing.frame_gap_us), pending: None }
+    }
+
+    pub fn port_mut(&mut self) -> &mut P {
+        &mut self.port
+    }
+
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+
+    /// Send `request` to `slave` without waiting for the response. Broadcasts complete
+    /// immediately since slaves do not answer them.
+    pub fn send_request(&mut self, slave: u8, request: &Request) -> Result<(), ModbusError> {
+        if self.pending.is_some() {
+            return Err(ModbusError::Busy);
+        }
+        if slave == BROADCAST && request.function() <= FC_READ_INPUT_REGISTERS {
+            return Err(ModbusError::Io(Error::InvalidArgs));
+        }
+        let mut frame = vec![slave];
+        request.encode(&mut frame)?;
+        append_crc(&mut frame);
+
+        // Drop leftovers of a late response so they do not prefix ours.
+        let _ = self.rx.poll(&mut self.port, (self.clock)())?;
+        self.rx.buf.clear();
+        self.port.write_all(&frame).map_err(from_io_error)?;
+        self.port.flush().map_err(from_io_error)?;
+        if slave != BROADCAST {
+            self.pending = Some(Pending {
+                slave,
+                function: request.function(),
+                expect: request.expect(),
+                sent_at: (self.clock)(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether a request is waiting for its response.
+    pub fn is_busy(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Check for the response to the outstanding request. Returns `None` while still waiting.
+    pub fn poll(&mut self) -> Result<Option<Response>, ModbusError> {
+        let Some(pending) = self.pending.as_ref() else {
+            return Ok(None);
+        };
+        let now = (self.clock)();
+        let Some(frame) = self.rx.poll(&mut self.port, now)? else {
+            if !self.rx.receiving()
+                && now.wrapping_sub(pending.sent_at) >= self.timing.response_timeout_us
+            {
+                self.pending = None;
+                return Err(ModbusError::Timeout);
+            }
+            return Ok(None);
+        };
+        let Some(pending) = self.pending.take() else {
+            return Ok(None);
+        };
+        Self::parse_response(&pending, &frame).map(Some)
+    }
+
+    /// Send `request` and wait for its response. A broadcast returns `Written` with zero fields
+    /// as soon as it is sent.
+    pub fn transact(&mut self, slave: u8, request: &Request) -> Result<Response, ModbusError> {
+        self.send_request(slave, request)?;
+        if slave == BROADCAST {
+            return Ok(Response::Written { addr: 0, value: 0 });
+        }
+        loop {
+            if let Some(response) = self.poll()? {
+                return Ok(response);
+            }
+        }
+    }
+
+    pub fn read_coils(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        count: u16,
+    ) -> Result<Vec<bool>, ModbusError> {
+        match self.transact(slave, &Request::ReadCoils { addr, count })? {
+            Response::Bits(bits) => Ok(bits),
+            _ => Err(ModbusError::Protocol),
+        }
+    }
+
+    pub fn read_discrete_inputs(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        count: u16,
+    ) -> Result<Vec<bool>, ModbusError> {
+        match self.transact(slave, &Request::ReadDiscreteInputs { addr, count })? {
+            Response::Bits(bits) => Ok(bits),
+            _ => Err(ModbusError::Protocol),
+        }
+    }
+
+    pub fn read_holding_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        count: u16,
+    ) -> Result<Vec<u16>, ModbusError> {
+        match self.transact(slave, &Request::ReadHoldingRegisters { addr, count })? {
+            Response::Registers(regs) => Ok(regs),
+            _ => Err(ModbusError::Protocol),
+        }
+    }
+
+    pub fn read_input_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        count: u16,
+    ) -> Result<Vec<u16>, ModbusError> {
+        match self.transact(slave, &Request::ReadInputRegisters { addr, count })? {
+            Response::Registers(regs) => Ok(regs),
+            _ => Err(ModbusError::Protocol),
+        }
+    }
+
+    pub fn write_single_coil(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        value: bool,
+    ) -> Result<(), ModbusError> {
+        self.transact(slave, &Request::WriteSingleCoil { addr, value }).map(|_| ())
+    }
+
+    pub fn write_single_register(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        value: u16,
+    ) -> Result<(), ModbusError> {
+        self.transact(slave, &Request::WriteSingleRegister { addr, value }).map(|_| ())
+    }
+
+    pub fn write_multiple_coils(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        values: &[bool],
+    ) -> Result<(), ModbusError> {
+        self.transact(slave, &Request::WriteMultipleCoils { addr, values }).map(|_| ())
+    }
+
+    pub fn write_multiple_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        values: &[u16],
+    ) -> Result<(), ModbusError> {
+        self.transact(slave, &Request::WriteMultipleRegisters { addr, values }).map(|_| ())
+    }
+
+    fn parse_response(pending: &Pending, frame: &[u8]) -> Result<Response, ModbusError> {
+        if frame.len() < 4 {
+            return Err(ModbusError::Protocol);
+        }
+        let (body, crc) = frame.split_at(frame.len() - 2);
+        if crc16(body) != u16::from_le_bytes([crc[0], crc[1]]) {
+            return Err(ModbusError::Crc);
+        }
+        if body[0] != pending.slave {
+            return Err(ModbusError::Protocol);
+        }
+        let function = body[1];
+        let data = &body[2..];
+        if function == pending.function | FC_EXCEPTION {
+            return Err(ModbusError::Exception(data.first().copied().unwrap_or(0)));
+        }
+        if function != pending.function {
+            return Err(ModbusError::Protocol);
+        }
+        match pending.expect {
+            Expect::Bits(count) => {
+                let len = (count as usize).div_ceil(8);
+                if data.len() != len + 1 || data[0] as usize != len {
+                    return Err(ModbusError::Protocol);
+                }
+                let bits = (0..count as usize).map(|i| data[1 + i / 8] & (1 << (i % 8)) != 0);
+                Ok(Response::Bits(bits.collect()))
+            }
+            Expect::Registers(count) => {
+                let len = count as usize * 2;
+                if data.len() != len + 1 || data[0] as usize != len {
+                    return Err(ModbusError::Protocol);
+                }
+                Ok(Response::Registers(
+                    (0..count as usize).map(|i| get_u16(data, 1 + i * 2)).collect(),
+                ))
+            }
+            Expect::Echo(addr, value) => {
+                if data.len() != 4 || get_u16(data, 0) != addr || get_u16(data, 2) != value {
+                    return Err(ModbusError::Protocol);
+                }
+                Ok(Response::Written { addr, value })
+            }
+        }
+    }
+}
+
+/// The data model served by a [`ModbusSlave`], each table starting at address 0.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegisterMap {
+    pub coils: Vec<bool>,
+    pub discrete_inputs: Vec<bool>,
+    pub holding_registers: Vec<u16>,
+    pub input_registers: Vec<u16>,
+}
+
+impl RegisterMap {
+    /// A zeroed map with the given table sizes.
+    pub fn new(coils: usize, discrete_inputs: usize, holding: usize, input: usize) -> Self {
+        Self {
+            coils: vec![false; coils],
+            discrete_inputs: vec![false; discrete_inputs],
+            holding_registers: vec![0; holding],
+            input_registers: vec![0; input],
+        }
+    }
+
+    /// Apply the request PDU `pdu` and append the response PDU to `out`. Protocol errors
+    /// become exception responses.
+    pub fn handle(&mut self, pdu: &[u8], out: &mut Vec<u8>) {
+        let function = pdu.first().copied().unwrap_or(0);
+        let start = out.len();
+        out.push(function);
+        if let Err(code) = self.apply(function, pdu.get(1..).unwrap_or(&[]), out) {
+            out.truncate(start);
+            out.extend_from_slice(&[function | FC_EXCEPTION, code]);
+        }
+    }
+
+    fn apply(&mut self, function: u8, data: &[u8], out: &mut Vec<u8>) -> Result<(), u8> {
+        let known = matches!(
+            function,
+            FC_READ_COILS
+                | FC_READ_DISCRETE_INPUTS
+                | FC_READ_HOLDING_REGISTERS
+                | FC_READ_INPUT_REGISTERS
+                | FC_WRITE_SINGLE_COIL
+                | FC_WRITE_SINGLE_REGISTER
+                | FC_WRITE_MULTIPLE_COILS
+                | FC_WRITE_MULTIPLE_REGISTERS
+        );
+        if !known {
+            return Err(EXCEPTION_ILLEGAL_FUNCTION);
+        }
+        if data.len() < 4 {
+            return Err(EXCEPTION_ILLEGAL_DATA_VALUE);
+        }
+        let addr = get_u16(data, 0) as usize;
+        let arg = get_u16(data, 2);
+        match function {
+            FC_READ_COILS | FC_READ_DISCRETE_INPUTS => {
+                if arg == 0 || arg > MAX_READ_BITS {
+                    return Err(EXCEPTION_ILLEGAL_DATA_VALUE);
+                }
+                let table =
+                    if function == FC_READ_COILS { &self.coils } else { &self.discrete_inputs };
+                let bits =
+                    table.get(addr..addr + arg as usize).ok_or(EXCEPTION_ILLEGAL_DATA_ADDRESS)?;
+                out.push((arg as usize).div_ceil(8) as u8);
+                pack_bits(out, bits);
+            }
+            FC_READ_HOLDING_REGISTERS | FC_READ_INPUT_REGISTERS => {
+                if arg == 0 || arg > MAX_READ_REGISTERS {
+                    return Err(EXCEPTION_ILLEGAL_DATA_VALUE);
+                }
+                let table = if function == FC_READ_HOLDING_REGISTERS {
+                    &self.holding_registers
+                } else {
+                    &self.input_registers
+                };
+                let regs =
+                    table.get(addr..addr + arg as usize).ok_or(EXCEPTION_ILLEGAL_DATA_ADDRESS)?;
+                out.push((arg * 2) as u8);
+                put_u16s(out, regs);
+            }
+            FC_WRITE_SINGLE_COIL => {
+                let value = match arg {
+                    0xff00 => true,
+                    0 => false,
+                    _ => return Err(EXCEPTION_ILLEGAL_DATA_VALUE),
+                };
+                *self.coils.get_mut(addr).ok_or(EXCEPTION_ILLEGAL_DATA_ADDRESS)? = value;
+                out.extend_from_slice(&data[..4]);
+            }
+            FC_WRITE_SINGLE_REGISTER => {
+                *self.holding_registers.get_mut(addr).ok_or(EXCEPTION_ILLEGAL_DATA_ADDRESS)? = arg;
+                out.extend_from_slice(&data[..4]);
+            }
+            FC_WRITE_MULTIPLE_COILS => {
+                let count = arg as usize;
+                let len = count.div_ceil(8);
+                if count == 0
+                    || arg > MAX_WRITE_BITS
+                    || data.len() != 5 + len
+                    || data[4] as usize != len
+                {
+                    return Err(EXCEPTION_ILLEGAL_DATA_VALUE);
+                }
+                let coils =
+                    self.coils.get_mut(addr..addr + count).ok_or(EXCEPTION_ILLEGAL_DATA_ADDRESS)?;
+                for (i, coil) in coils.iter_mut().enumerate() {
+                    *coil = data[5 + i / 8] & (1 << (i % 8)) != 0;
+                }
+                out.extend_from_slice(&data[..4]);
+            }
+            _ => {
+                let count = arg as usize;
+                if count == 0
+                    || arg > MAX_WRITE_REGISTERS
+                    || data.len() != 5 + count * 2
+                    || data[4] as usize != count * 2
+                {
+                    return Err(EXCEPTION_ILLEGAL_DATA_VALUE);
+                }
+                let regs = self
+                    .holding_registers
+                    .get_mut(addr..addr + count)
+                    .ok_or(EXCEPTION_ILLEGAL_DATA_ADDRESS)?;
+                for (i, reg) in regs.iter_mut().enumerate() {
+                    *reg = get_u16(data, 5 + i * 2);
+                }
+                out.extend_from_slice(&data[..4]);
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct ModbusSlave<P, C> {
+    port: P,
+    clock: C,
+    address: u8,
+    rx: FrameReceiver,
+    map: RegisterMap,
+}
+
+impl<P, C> ModbusSlave<P, C>
+where
+    P: Read + ReadReady + Write,
+    C: Fn() -> u64,
+{
+    /// A slave answering to `address` (1-247).
+    pub fn new(
+        port: P,
+        clock: C,
+        timing: ModbusTiming,
+        address: u8,
+        map: RegisterMap,
+    ) -> Result<Self, Error> {
+        if address == BROADCAST || address > 247 {
+            return Err(Error::InvalidArgs);
+        }
+        Ok(Self { port, clock, address, rx: FrameReceiver::new(timing.frame_gap_us), map })
+    }
+
+    pub fn address(&self) -> u8 {
+        self.address
+    }
+
+    pub fn map(&self) -> &RegisterMap {
+        &self.map
+    }
+
+    pub fn map_mut(&mut self) -> &mut RegisterMap {
+        &mut self.map
+    }
+
+    pub fn into_inner(self) -> (P, RegisterMap) {
+        (self.port, self.map)
+    }
+
+    /// Serve at most one request. Returns the function code of a request addressed to us,
+    /// including broadcasts. Frames with a bad CRC or for other slaves are ignored.
+    pub fn poll(&mut self) -> Result<Option<u8>, Error> {
+        let Some(frame) = self.rx.poll(&mut self.port, (self.clock)())? else {
+            return Ok(None);
+        };
+        if frame.len() < 4 {
+            return Ok(None);
+        }
+        let (body, crc) = frame.split_at(frame.len() - 2);
+        if crc16(body) != u16::from_le_bytes([crc[0], crc[1]]) {
+            return Ok(None);
+        }
+        let slave = body[0];
+        if slave != self.address && slave != BROADCAST {
+            return Ok(None);
+        }
+        let mut response = vec![self.address];
+        self.map.handle(&body[1..], &mut response);
+        if slave != BROADCAST {
+            append_crc(&mut response);
+            self.port.write_all(&response).map_err(from_io_error)?;
+            self.port.flush().map_err(from_io_error)?;
+        }
+        Ok(Some(body[1]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uart::loopback::LoopbackUart;
+    use alloc::boxed::Box;
+    use alloc::rc::Rc;
+    use core::cell::Cell;
+
+    const SLAVE: u8 = 7;
+
+    type Clock = Rc<Cell<u64>>;
+
+    struct Bus {
+        now: Clock,
+        master: ModbusMaster<LoopbackUart, Box<dyn Fn() -> u64>>,
+        slave: ModbusSlave<LoopbackUart, Box<dyn Fn() -> u64>>,
+    }
+
+    fn clock(now: &Clock) -> Box<dyn Fn() -> u64> {
+        let now = now.clone();
+        Box::new(move || now.get())
+    }
+
+    impl Bus {
+        fn new() -> Self {
+            let timing =
+                ModbusTiming::from_config(&UartConfig { baud_rate: 9600, ..Default::default() });
+            let (a, b) = LoopbackUart::pair();
+            let now = Clock::default();
+            let mut map = RegisterMap::new(20, 10, 10, 4);
+            map.discrete_inputs[3] = true;
+            map.input_registers[1] = 0xbeef;
+            Self {
+                master: ModbusMaster::new(a, clock(&now), timing),
+                slave: ModbusSlave::new(b, clock(&now), timing, SLAVE, map).unwrap(),
+                now,
+            }
+        }
+
+        fn wait_gap(&self) {
+            self.now.set(self.now.get() + 5000);
+        }
+
+        /// Send `request`, let the slave answer it after the frame gap and collect the response.
+        fn transact(&mut self, request: Request) -> Result<Option<Response>, ModbusError> {
+            self.master.send_request(SLAVE, &request)?;
+            assert_eq!(self.slave.poll(), Ok(None));
+            self.wait_gap();
+            assert_eq!(self.slave.poll(), Ok(Some(request.function())));
+            assert_eq!(self.master.poll(), Ok(None));
+            self.wait_gap();
+            self.master.poll()
+        }
+    }
+
+    #[test]
+    fn timing_counts_stop_bits_in_half_bits() {
+        let gap = |data_bits, parity, stop_bits| {
+            let config =
+                UartConfig { baud_rate: 9600, data_bits, parity, stop_bits, ..Default::default() };
+            ModbusTiming::from_config(&config).frame_gap_us
+        };
+        // 3.5 characters of 10, 12, 7.5 and 11 bits.
+        assert_eq!(gap(8, Parity::None, StopBits::One), 3646);
+        assert_eq!(gap(8, Parity::Even, StopBits::Two), 4375);
+        assert_eq!(gap(5, Parity::None, StopBits::OneAndHalf), 2735);
+        assert_eq!(gap(8, Parity::Odd, StopBits::One), 4011);
+        let fast = UartConfig { baud_rate: 115200, ..Default::default() };
+        assert_eq!(ModbusTiming::from_config(&fast).frame_gap_us, 1750);
+    }
+
+    #[test]
+    fn crc_matches_reference() {
+        assert_eq!(crc16(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x0a]).to_le_bytes(), [0xc5, 0xcd]);
+    }
+
+    #[test]
+    fn registers() {
+        let mut bus = Bus::new();
+        let write = Request::WriteMultipleRegisters { addr: 2, values: &[1, 2, 3] };
+        assert_eq!(bus.transact(write), Ok(Some(Response::Written { addr: 2, value: 3 })));
+        let write = Request::WriteSingleRegister { addr: 9, value: 42 };
+        assert_eq!(bus.transact(write), Ok(Some(Response::Written { addr: 9, value: 42 })));
+        let read = Request::ReadHoldingRegisters { addr: 1, count: 5 };
+        assert_eq!(bus.transact(read), Ok(Some(Response::Registers(vec![0, 1, 2, 3, 0]))));
+        let read = Request::ReadInputRegisters { addr: 0, count: 4 };
+        assert_eq!(bus.transact(read), Ok(Some(Response::Registers(vec![0, 0xbeef, 0, 0]))));
+        assert_eq!(bus.slave.map().holding_registers[9], 42);
+    }
+
+    #[test]
+    fn coils_and_inputs() {
+        let mut bus = Bus::new();
+        let write = Request::WriteSingleCoil { addr: 0, value: true };
+        assert_eq!(bus.transact(write), Ok(Some(Response::Written { addr: 0, value: 0xff00 })));
+        let coils = [true, false, true, true, false, false, false, false, true, true];
+        let write = Request::WriteMultipleCoils { addr: 5, values: &coils };
+        assert_eq!(bus.transact(write), Ok(Some(Response::Written { addr: 5, value: 10 })));
+
+        let read = Request::ReadCoils { addr: 4, count: 12 };
+        let Ok(Some(Response::Bits(bits))) = bus.transact(read) else {
+            panic!("no coils read");
+        };
+        assert_eq!(bits.len(), 12);
+        assert!(!bits[0]);
+        assert_eq!(&bits[1..11], &coils);
+        let read = Request::ReadDiscreteInputs { addr: 0, count: 10 };
+        let inputs = (0..10).map(|i| i == 3).collect();
+        assert_eq!(bus.transact(read), Ok(Some(Response::Bits(inputs))));
+    }
+
+    #[test]
+    fn exception() {
+        let mut bus = Bus::new();
+        let read = Request::ReadInputRegisters { addr: 3, count: 4 };
+        assert_eq!(bus.transact(read), Err(ModbusError::Exception(EXCEPTION_ILLEGAL_DATA_ADDRESS)));
+        assert!(!bus.master.is_busy());
+    }
+
+    #[test]
+    fn bad_crc() {
+        let mut bus = Bus::new();
+        // A corrupted request is ignored by the slave.
+        let mut frame = vec![SLAVE, FC_READ_COILS, 0, 0, 0, 1];
+        append_crc(&mut frame);
+        frame[5] ^= 1;
+        bus.master.port_mut().write_all(&frame).unwrap();
+        assert_eq!(bus.slave.poll(), Ok(None));
+        bus.wait_gap();
+        assert_eq!(bus.slave.poll(), Ok(None));
+
+        // A corrupted response is reported.
+        bus.master.send_request(SLAVE, &Request::ReadCoils { addr: 0, count: 1 }).unwrap();
+        assert_eq!(bus.slave.poll(), Ok(None));
+        bus.wait_gap();
+        assert_eq!(bus.slave.poll(), Ok(Some(FC_READ_COILS)));
+        let port = bus.master.port_mut();
+        let mut response = vec![0u8; port.available()];
+        port.read_exact(&mut response).unwrap();
+        response[3] ^= 1;
+        port.inject(&response);
+        assert_eq!(bus.master.poll(), Ok(None));
+        bus.wait_gap();
+        assert_eq!(bus.master.poll(), Err(ModbusError::Crc));
+    }
+
+    #[test]
+    fn timeout() {
+        let mut bus = Bus::new();
+        bus.master.send_request(SLAVE, &Request::ReadCoils { addr: 0, count: 1 }).unwrap();
+        assert_eq!(
+            bus.master.send_request(SLAVE, &Request::ReadCoils { addr: 0, count: 1 }),
+            Err(ModbusError::Busy)
+        );
+        bus.now.set(999_999);
+        assert_eq!(bus.master.poll(), Ok(None));
+        bus.now.set(1_000_000);
+        assert_eq!(bus.master.poll(), Err(ModbusError::Timeout));
+        assert!(!bus.master.is_busy());
+    }
+}
@@ -0,0 +1,323 @@
+//! Buffered UART stream over the driver ring.
+//!
+//! [`UartStream`] splits the client's shared memory into a receive area and a transmit ring.
+//! One read is kept posted at all times and its data moved into a software RX buffer, so input
+//! is not lost between calls. Writes copy into the transmit ring and return as soon as some
+//! bytes are queued. Failed completions are reported by the next call.
+
+use super::IoError;
+use crate::client::uart::UartClient;
+use crate::interface::UartDriver;
+use alloc::collections::VecDeque;
+use core::fmt;
+use glenda::error::Error;
+
+/// Default software RX buffer size in bytes.
+pub const DEFAULT_RX_CAPACITY: usize = 4096;
+
+const USER_DATA_RX: u64 = 1 << 63;
+const USER_DATA_TX: u64 = 1 << 62;
+
+pub struct UartStream {
+    client: UartClient,
+    shm_vaddr: usize,
+    shm_client_vaddr: usize,
+    /// RX area at the start of the SHM.
+    rx_chunk: usize,
+    rx: VecDeque<u8>,
+    rx_capacity: usize,
+    rx_posted: bool,
+    /// TX ring following the RX area.
+    tx_off: usize,
+    tx_size: usize,
+    tx_head: usize,
+    tx_used: usize,
+    /// Lengths of in-flight writes, oldest first.
+    tx_inflight: VecDeque<usize>,
+    error: Option<Error>,
+}
+
+impl UartStream {
+    /// Take over a connected client. Its ring and SHM must not be used by anything else.
+    pub fn new(client: UartClient) -> Result<Self, Error> {
+        Self::with_rx_capacity(client, DEFAULT_RX_CAPACITY)
+    }
+
+    pub fn with_rx_capacity(client: UartClient, rx_capacity: usize) -> Result<Self, Error> {
+        let shm = client.shm().ok_or(Error::NotInitialized)?;
+        let size = shm.size();
+        if size < 2 || rx_capacity == 0 {
+            return Err(Error::InvalidArgs);
+        }
+        let rx_chunk = size / 2;
+        let mut stream = Self {
+            shm_vaddr: shm.vaddr(),
+            shm_client_vaddr: shm.client_vaddr(),
+            client,
+            rx_chunk,
+            rx: VecDeque::with_capacity(rx_capacity),
+            rx_capacity,
+            rx_posted: false,
+            tx_off: rx_chunk,
+            tx_size: size - rx_chunk,
+            tx_head: 0,
+            tx_used: 0,
+            tx_inflight: VecDeque::new(),
+            error: None,
+        };
+        stream.post_read()?;
+        Ok(stream)
+    }
+
+    pub fn client(&self) -> &UartClient {
+        &self.client
+    }
+
+    pub fn into_inner(self) -> UartClient {
+        self.client
+    }
+
+    /// Bytes buffered and readable without blocking.
+    pub fn available(&mut self) -> Result<usize, Error> {
+        self.poll()?;
+        Ok(self.rx.len())
+    }
+
+    /// Read buffered bytes without blocking. Returns 0 if none are available.
+    pub fn try_read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.poll()?;
+        let n = core::cmp::min(buf.len(), self.rx.len());
+        for (dst, src) in buf.iter_mut().zip(self.rx.drain(..n)) {
+            *dst = src;
+        }
+        if n > 0 {
+            self.post_read()?;
+        }
+        Ok(n)
+    }
+
+    /// Queue as much of `buf` as fits in the TX ring without blocking. Returns the bytes taken.
+    pub fn try_write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.poll()?;
+        let (off, space) = self.tx_space();
+        let n = core::cmp::min(buf.len(), space);
+        if n == 0 {
+            return Ok(0);
+        }
+        // The TX ring lies inside the SHM mapping and the chunk is not in flight.
+        unsafe {
+            let dst = (self.shm_vaddr + self.tx_off + off) as *mut u8;
+            core::ptr::copy_nonoverlapping(buf.as_ptr(), dst, n);
+        }
+        let addr = (self.shm_client_vaddr + self.tx_off + off) as u64;
+        self.client.write_async(addr, n as u32, USER_DATA_TX)?;
+        self.tx_used += n;
+        self.tx_inflight.push_back(n);
+        Ok(n)
+    }
+
+    /// Read at least one byte, blocking until data arrives.
+    pub fn read_blocking(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let n = self.try_read(buf)?;
+            if n > 0 {
+                return Ok(n);
+            }
+            self.client.wait_for_completions()?;
+        }
+    }
+
+    /// Write at least one byte, blocking while the TX ring is full.
+    pub fn write_blocking(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let n = self.try_write(buf)?;
+            if n > 0 {
+                return Ok(n);
+            }
+            self.client.wait_for_completions()?;
+        }
+    }
+
+    pub fn write_all_blocking(&mut self, mut buf: &[u8]) -> Result<(), Error> {
+        while !buf.is_empty() {
+            let n = self.write_blocking(buf)?;
+            buf = &buf[n..];
+        }
+        Ok(())
+    }
+
+    /// Wait until every queued byte has been handed to the hardware.
+    pub fn flush_blocking(&mut self) -> Result<(), Error> {
+        loop {
+            self.poll()?;
+            if self.tx_inflight.is_empty() {
+                return Ok(());
+            }
+            self.client.wait_for_completions()?;
+        }
+    }
+
+    /// Reap completions, moving received data into the RX buffer.
+    pub fn poll(&mut self) -> Result<(), Error> {
+        while let Some(cqe) = self.client.peek_cqe() {
+            if cqe.user_data & USER_DATA_RX != 0 {
+                self.rx_posted = false;
+                if cqe.res < 0 {
+                    self.error.get_or_insert(Error::Generic);
+                    continue;
+                }
+                let n = core::cmp::min(cqe.res as usize, self.rx_chunk);
+                // The driver has finished writing the RX area.
+                let data = unsafe { core::slice::from_raw_parts(self.shm_vaddr as *const u8, n) };
+                self.rx.extend(data);
+            } else if cqe.user_data & USER_DATA_TX != 0 {
+                let Some(len) = self.tx_inflight.pop_front() else {
+                    continue;
+                };
+                self.tx_used -= len;
+                self.tx_head += len;
+                if self.tx_head >= self.tx_size {
+                    self.tx_head -= self.tx_size;
+                }
+                if self.tx_used == 0 {
+                    self.tx_head = 0;
+                }
+                if cqe.res < 0 {
+                    self.error.get_or_insert(Error::Generic);
+                }
+            }
+        }
+        self.post_read()?;
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Keep one read posted while the RX buffer has room for its data.
+    fn post_read(&mut self) -> Result<(), Error> {
+        let room = self.rx_capacity.saturating_sub(self.rx.len());
+        if self.rx_posted || room == 0 {
+            return Ok(());
+        }
+        let len = core::cmp::min(room, self.rx_chunk);
+        self.client.read_async(self.shm_client_vaddr as u64, len as u32, USER_DATA_RX)?;
+        self.rx_posted = true;
+        Ok(())
+    }
+
+    /// Offset and length of the largest contiguous free block at the ring tail.
+    fn tx_space(&self) -> (usize, usize) {
+        let tail = self.tx_head + self.tx_used;
+        if tail < self.tx_size {
+            (tail, self.tx_size - tail)
+        } else {
+            let tail = tail - self.tx_size;
+            (tail, self.tx_head - tail)
+        }
+    }
+}
+
+impl fmt::Write for UartStream {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_all_blocking(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+impl embedded_io::ErrorType for UartStream {
+    type Error = IoError;
+}
+
+impl embedded_io::Read for UartStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        Ok(self.read_blocking(buf)?)
+    }
+}
+
+impl embedded_io::ReadReady for UartStream {
+    fn read_ready(&mut self) -> Result<bool, IoError> {
+        Ok(self.available()? > 0)
+    }
+}
+
+impl embedded_io::Write for UartStream {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, IoError> {
+        Ok(self.write_blocking(buf)?)
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        Ok(self.flush_blocking()?)
+    }
+}
+
+impl embedded_io::WriteReady for UartStream {
+    fn write_ready(&mut self) -> Result<bool, IoError> {
+        self.poll()?;
+        Ok(self.tx_space().1 > 0)
+    }
+}
+
+/// Adapts a [`UartDriver`] to the `embedded-io` traits, one driver call per byte.
+///
+/// `read_ready` probes the driver with `get_char`, so it only returns promptly if the driver
+/// does. Prefer [`UartStream`] where the ring is available.
+pub struct DriverPort<U: UartDriver> {
+    uart: U,
+    peeked: Option<u8>,
+}
+
+impl<U: UartDriver> DriverPort<U> {
+    pub const fn new(uart: U) -> Self {
+        Self { uart, peeked: None }
+    }
+
+    pub fn into_inner(self) -> U {
+        self.uart
+    }
+}
+
+impl<U: UartDriver> embedded_io::ErrorType for DriverPort<U> {
+    type Error = IoError;
+}
+
+impl<U: UartDriver> embedded_io::Read for DriverPort<U> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some(b) = self.peeked.take().or_else(|| self.uart.get_char()) {
+                buf[0] = b;
+                return Ok(1);
+            }
+        }
+    }
+}
+
+impl<U: UartDriver> embedded_io::ReadReady for DriverPort<U> {
+    fn read_ready(&mut self) -> Result<bool, IoError> {
+        if self.peeked.is_none() {
+            self.peeked = self.uart.get_char();
+        }
+        Ok(self.peeked.is_some())
+    }
+}
+
+impl<U: UartDriver> embedded_io::Write for DriverPort<U> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, IoError> {
+        for &b in buf {
+            self.uart.put_char(b);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), IoError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,606 @@
+//! XMODEM-CRC, XMODEM-1K and YMODEM batch file transfer.
+//!
+//! [`Xmodem`] runs either role over any `embedded-io` port: a [`UartStream`] on the driver ring,
+//! or a [`DriverPort`] around a plain [`UartDriver`](crate::interface::UartDriver). `clock`
+//! returns the current time in milliseconds and drives all timeouts. Received data goes to a
+//! [`ByteSink`], so a `BlockSink` writes it straight to a block device.
+//!
+//! [`UartStream`]: super::stream::UartStream
+//! [`DriverPort`]: super::stream::DriverPort
+
+use super::from_io_error;
+use crate::sink::ByteSink;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write as _;
+use embedded_io::{Read, ReadReady, Write};
+use glenda::error::Error;
+
+pub const SOH: u8 = 0x01;
+pub const STX: u8 = 0x02;
+pub const EOT: u8 = 0x04;
+pub const ACK: u8 = 0x06;
+pub const NAK: u8 = 0x15;
+pub const CAN: u8 = 0x18;
+/// Sent by the receiver instead of NAK to request CRC mode.
+pub const CRC_REQUEST: u8 = b'C';
+/// Padding for the last block.
+pub const SUB: u8 = 0x1a;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferError {
+    /// The peer stopped responding or a block failed too often.
+    Timeout,
+    /// The peer sent CAN CAN.
+    Cancelled,
+    /// The peer broke the protocol, e.g. with an out-of-sequence block.
+    Protocol,
+    /// The port or sink failed.
+    Io(Error),
+}
+
+impl From<Error> for TransferError {
+    fn from(e: Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XmodemConfig {
+    /// How long to wait for a packet or a reply to one.
+    pub timeout_ms: u64,
+    /// How long to wait for each further byte within a packet.
+    pub byte_timeout_ms: u64,
+    /// Attempts per block before giving up.
+    pub retries: u32,
+    /// Send 1024-byte blocks when enough data remains (XMODEM-1K). YMODEM always does.
+    pub block_1k: bool,
+}
+
+impl Default for XmodemConfig {
+    fn default() -> Self {
+        Self { timeout_ms: 10_000, byte_timeout_ms: 1_000, retries: 10, block_1k: true }
+    }
+}
+
+/// A file announced in a YMODEM header block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YmodemFile {
+    pub name: String,
+    /// Exact length, if the sender gave one. Padding past it is dropped.
+    pub size: Option<u64>,
+}
+
+/// CRC-16/XMODEM (polynomial 0x1021, initial value 0).
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+enum Packet {
+    Data {
+        num: u8,
+        len: usize,
+    },
+    Eot,
+    Cancel,
+    /// Nothing, or nothing valid, arrived in time.
+    Bad,
+}
+
+pub struct Xmodem<P, C> {
+    port: P,
+    clock: C,
+    config: XmodemConfig,
+    buf: Vec<u8>,
+}
+
+impl<P, C> Xmodem<P, C>
+where
+    P: Read + ReadReady + Write,
+    C: Fn() -> u64,
+{
+    pub fn new(port: P, clock: C, config: XmodemConfig) -> Self {
+        Self { port, clock, config, buf: Vec::new() }
+    }
+
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+
+    /// Send `data` as a single XMODEM transfer.
+    pub fn send(&mut self, data: &[u8]) -> Result<(), TransferError> {
+        let crc = self.wait_start()?;
+        self.send_blocks(data, self.config.block_1k, crc)?;
+        self.send_eot()
+    }
+
+    /// Receive a single XMODEM-CRC transfer into `sink`. Returns the bytes written, which
+    /// include the padding of the last block.
+    pub fn receive<S: ByteSink>(&mut self, sink: &mut S) -> Result<u64, TransferError> {
+        self.receive_blocks(sink, None, false)
+    }
+
+    /// Send a YMODEM batch of `(name, data)` files. Fails before sending anything if a name
+    /// does not fit in a header block.
+    pub fn send_batch(&mut self, files: &[(&str, &[u8])]) -> Result<(), TransferError> {
+        let mut headers = Vec::with_capacity(files.len());
+        for &(name, data) in files {
+            let mut header = Vec::new();
+            header.extend_from_slice(name.as_bytes());
+            header.push(0);
+            let mut size = String::new();
+            let _ = write!(size, "{}", data.len());
+            header.extend_from_slice(size.as_bytes());
+            header.push(0);
+            if header.len() > 1024 {
+                return Err(TransferError::Io(Error::InvalidArgs));
+            }
+            headers.push(header);
+        }
+
+        for (header, &(_, data)) in headers.iter().zip(files) {
+            let crc = self.wait_start()?;
+            // Header blocks are padded with zeros rather than SUB.
+            let size = if header.len() > 128 { 1024 } else { 128 };
+            self.send_block(0, header, size, 0, crc)?;
+            let crc = self.wait_start()?;
+            self.send_blocks(data, true, crc)?;
+            self.send_eot()?;
+        }
+        // An empty header block ends the batch.
+        let crc = self.wait_start()?;
+        self.send_block(0, &[], 128, 0, crc)
+    }
+
+    /// Receive a YMODEM batch. `open` is called with each announced file and returns the sink
+    /// its data goes to; sinks are flushed once their file is complete. Returns the file count.
+    pub fn receive_batch<S, F>(&mut self, mut open: F) -> Result<u32, TransferError>
+    where
+        S: ByteSink,
+        F: FnMut(&YmodemFile) -> Result<S, Error>,
+    {
+        let mut files = 0;
+        loop {
+            let Some(file) = self.receive_header()? else {
+                return Ok(files);
+            };
+            let mut sink = match open(&file) {
+                Ok(sink) => sink,
+                Err(e) => {
+                    self.cancel();
+                    return Err(e.into());
+                }
+            };
+            self.receive_blocks(&mut sink, file.size, true)?;
+            files += 1;
+        }
+    }
+
+    /// Wait for the receiver's start byte. Returns whether it asked for CRC mode.
+    fn wait_start(&mut self) -> Result<bool, TransferError> {
+        let deadline = (self.clock)() + self.config.timeout_ms * self.config.retries as u64;
+        loop {
+            let timeout = deadline.saturating_sub((self.clock)());
+            match self.read_byte(timeout)? {
+                Some(CRC_REQUEST) => return Ok(true),
+                Some(NAK) => return Ok(false),
+                Some(CAN) if self.read_byte(self.config.byte_timeout_ms)? == Some(CAN) => {
+                    return Err(TransferError::Cancelled);
+                }
+                Some(_) => {}
+                None => return Err(TransferError::Timeout),
+            }
+        }
+    }
+
+    fn send_blocks(&mut self, data: &[u8], block_1k: bool, crc: bool) -> Result<(), TransferError> {
+        let mut num = 1u8;
+        let mut rest = data;
+        while !rest.is_empty() {
+            let size = if block_1k && rest.len() > 128 { 1024 } else { 128 };
+            let n = core::cmp::min(size, rest.len());
+            self.send_block(num, &rest[..n], size, SUB, crc)?;
+            rest = &rest[n..];
+            num = num.wrapping_add(1);
+        }
+        Ok(())
+    }
+
+    /// Send one block of `size` bytes, padding `data` (at most `size` bytes) with `pad`, until
+    /// it is acknowledged.
+    fn send_block(
+        &mut self,
+        num: u8,
+        data: &[u8],
+        size: usize,
+        pad: u8,
+        crc: bool,
+    ) -> Result<(), TransferError> {
+        let mut packet = Vec::with_capacity(size + 5);
+        packet.extend_from_slice(&[if size == 1024 { STX } else { SOH }, num, !num]);
+        packet.extend_from_slice(data);
+        packet.resize(size + 3, pad);
+        let body = &packet[3..];
+        if crc {
+            let sum = crc16(body);
+            packet.extend_from_slice(&sum.to_be_bytes());
+        } else {
+            let sum = body.iter().fold(0u8, |acc, &b| acc.wrapping_add(b));
+            packet.push(sum);
+        }
+
+        for _ in 0..self.config.retries {
+            self.write(&packet)?;
+            match self.read_byte(self.config.timeout_ms)? {
+                Some(ACK) => return Ok(()),
+                Some(CAN) if self.read_byte(self.config.byte_timeout_ms)? == Some(CAN) => {
+                    return Err(TransferError::Cancelled);
+                }
+                _ => {}
+            }
+        }
+        self.cancel();
+        Err(TransferError::Timeout)
+    }
+
+    fn send_eot(&mut self) -> Result<(), TransferError> {
+        for _ in 0..self.config.retries {
+            self.write(&[EOT])?;
+            // YMODEM receivers NAK the first EOT; just send it again.
+            if self.read_byte(self.config.timeout_ms)? == Some(ACK) {
+                return Ok(());
+            }
+        }
+        Err(TransferError::Timeout)
+    }
+
+    /// Receive the YMODEM header block. Returns `None` for the empty block ending a batch.
+    fn receive_header(&mut self) -> Result<Option<YmodemFile>, TransferError> {
+        for _ in 0..self.config.retries {
+            self.write(&[CRC_REQUEST])?;
+            match self.read_packet()? {
+                Packet::Data { num: 0, len } => {
+                    self.write(&[ACK])?;
+                    let block = &self.buf[..len];
+                    let name_end = block.iter().position(|&b| b == 0).unwrap_or(len);
+                    if name_end == 0 {
+                        return Ok(None);
+                    }
+                    let name = String::from_utf8_lossy(&block[..name_end]).into_owned();
+                    let field = block.get(name_end + 1..).unwrap_or(&[]);
+                    let digits = field.iter().take_while(|b| b.is_ascii_digit()).count();
+                    let size =
+                        core::str::from_utf8(&field[..digits]).ok().and_then(|s| s.parse().ok());
+                    return Ok(Some(YmodemFile { name, size }));
+                }
+                Packet::Cancel => return Err(TransferError::Cancelled),
+                Packet::Eot => self.write(&[ACK])?,
+                _ => {}
+            }
+        }
+        self.cancel();
+        Err(TransferError::Timeout)
+    }
+
+    /// Receive data blocks into `sink` until EOT. `limit` truncates the block padding.
+    fn receive_blocks<S: ByteSink>(
+        &mut self,
+        sink: &mut S,
+        limit: Option<u64>,
+        ymodem: bool,
+    ) -> Result<u64, TransferError> {
+        let mut expected = 1u8;
+        let mut started = false;
+        let mut errors = 0;
+        let mut written = 0u64;
+        let mut eot_seen = false;
+        loop {
+            if !started {
+                self.write(&[CRC_REQUEST])?;
+            }
+            match self.read_packet()? {
+                Packet::Data { num, len } if num == expected => {
+                    let take = match limit {
+                        Some(limit) => core::cmp::min(len as u64, limit - written) as usize,
+                        None => len,
+                    };
+                    if let Err(e) = sink.write_all(&self.buf[..take]) {
+                        self.cancel();
+                        return Err(e.into());
+                    }
+                    written += take as u64;
+                    self.write(&[ACK])?;
+                    expected = expected.wrapping_add(1);
+                    started = true;
+                    errors = 0;
+                }
+                // Our ACK was lost and the sender repeated the block.
+                Packet::Data { num, .. } if started && num == expected.wrapping_sub(1) => {
+                    self.write(&[ACK])?;
+                }
+                // Same for the YMODEM header, repeated before the first data block.
+                Packet::Data { num: 0, .. } if ymodem && !started => {
+                    self.write(&[ACK])?;
+                }
+                Packet::Data { .. } => {
+                    self.cancel();
+                    return Err(TransferError::Protocol);
+                }
+                Packet::Eot if ymodem && !eot_seen => {
+                    eot_seen = true;
+                    started = true;
+                    self.write(&[NAK])?;
+                }
+                Packet::Eot => {
+                    self.write(&[ACK])?;
+                    sink.flush()?;
+                    return Ok(written);
+                }
+                Packet::Cancel => return Err(TransferError::Cancelled),
+                Packet::Bad => {
+                    errors += 1;
+                    if errors > self.config.retries {
+                        self.cancel();
+                        return Err(TransferError::Timeout);
+                    }
+                    if started {
+                        self.purge()?;
+                        self.write(&[NAK])?;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Read one packet into `buf`, checking its sequence complement and CRC.
+    fn read_packet(&mut self) -> Result<Packet, TransferError> {
+        let size = match self.read_byte(self.config.timeout_ms)? {
+            Some(SOH) => 128,
+            Some(STX) => 1024,
+            Some(EOT) => return Ok(Packet::Eot),
+            Some(CAN) if self.read_byte(self.config.byte_timeout_ms)? == Some(CAN) => {
+                return Ok(Packet::Cancel);
+            }
+            _ => return Ok(Packet::Bad),
+        };
+        // Sequence number, its complement, data and CRC.
+        self.buf.resize(size + 4, 0);
+        for i in 0..size + 4 {
+            match self.read_byte(self.config.byte_timeout_ms)? {
+                Some(b) => self.buf[i] = b,
+                None => return Ok(Packet::Bad),
+            }
+        }
+        let num = self.buf[0];
+        let sum = u16::from_be_bytes([self.buf[size + 2], self.buf[size + 3]]);
+        if num != !self.buf[1] || crc16(&self.buf[2..size + 2]) != sum {
+            return Ok(Packet::Bad);
+        }
+        self.buf.copy_within(2..size + 2, 0);
+        Ok(Packet::Data { num, len: size })
+    }
+
+    fn read_byte(&mut self, timeout_ms: u64) -> Result<Option<u8>, TransferError> {
+        let start = (self.clock)();
+        loop {
+            if self.port.read_ready().map_err(from_io_error)? {
+                let mut b = [0u8];
+                if self.port.read(&mut b).map_err(from_io_error)? == 1 {
+                    return Ok(Some(b[0]));
+                }
+            }
+            if (self.clock)().wrapping_sub(start) >= timeout_ms {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Drop input until the line has been quiet for a byte timeout.
+    fn purge(&mut self) -> Result<(), TransferError> {
+        while self.read_byte(self.config.byte_timeout_ms)?.is_some() {}
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), TransferError> {
+        self.port.write_all(data).map_err(from_io_error)?;
+        self.port.flush().map_err(from_io_error)?;
+        Ok(())
+    }
+
+    /// Tell the peer to abort. Best effort, the transfer has already failed.
+    fn cancel(&mut self) {
+        let _ = self.write(&[CAN, CAN]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use alloc::collections::VecDeque;
+    use alloc::sync::Arc;
+    use alloc::vec;
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::Instant;
+
+    type Queue = Arc<Mutex<VecDeque<u8>>>;
+
+    /// One end of an in-memory line that the two sides can drive from different threads.
+    struct Pipe {
+        rx: Queue,
+        tx: Queue,
+        /// Corrupt every nth byte sent, if non-zero.
+        corrupt_every: usize,
+        sent: usize,
+    }
+
+    impl embedded_io::ErrorType for Pipe {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let mut rx = self.rx.lock().unwrap();
+            let n = core::cmp::min(buf.len(), rx.len());
+            for (dst, src) in buf.iter_mut().zip(rx.drain(..n)) {
+                *dst = src;
+            }
+            Ok(n)
+        }
+    }
+
+    impl ReadReady for Pipe {
+        fn read_ready(&mut self) -> Result<bool, Self::Error> {
+            Ok(!self.rx.lock().unwrap().is_empty())
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            let mut tx = self.tx.lock().unwrap();
+            for &b in buf {
+                self.sent += 1;
+                let corrupt =
+                    self.corrupt_every != 0 && self.sent.is_multiple_of(self.corrupt_every);
+                tx.push_back(if corrupt { b ^ 0x55 } else { b });
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    /// A sender end that corrupts every `corrupt_every`th byte, and the receiver end.
+    fn pair(corrupt_every: usize) -> (Pipe, Pipe) {
+        let (a, b) = (Queue::default(), Queue::default());
+        let sender = Pipe { rx: a.clone(), tx: b.clone(), corrupt_every, sent: 0 };
+        (sender, Pipe { rx: b, tx: a, corrupt_every: 0, sent: 0 })
+    }
+
+    fn xmodem(port: Pipe, block_1k: bool) -> Xmodem<Pipe, impl Fn() -> u64> {
+        let start = Instant::now();
+        let config = XmodemConfig { timeout_ms: 200, byte_timeout_ms: 50, retries: 10, block_1k };
+        Xmodem::new(port, move || start.elapsed().as_millis() as u64, config)
+    }
+
+    fn round_trip(data: &[u8], block_1k: bool, corrupt_every: usize) -> Vec<u8> {
+        let (tx, rx) = pair(corrupt_every);
+        let sent = data.to_vec();
+        let sender = thread::spawn(move || xmodem(tx, block_1k).send(&sent));
+        let mut out = Vec::new();
+        let n = xmodem(rx, block_1k).receive(&mut out).unwrap();
+        sender.join().unwrap().unwrap();
+        assert_eq!(n as usize, out.len());
+        out
+    }
+
+    fn block(num: u8, data: &[u8], size: usize) -> Vec<u8> {
+        let mut packet = vec![if size == 1024 { STX } else { SOH }, num, !num];
+        packet.extend_from_slice(data);
+        packet.resize(size + 3, 0);
+        let crc = crc16(&packet[3..]);
+        packet.extend_from_slice(&crc.to_be_bytes());
+        packet
+    }
+
+    type Received = Vec<(YmodemFile, Vec<u8>)>;
+
+    /// Collects the files of a YMODEM batch.
+    #[derive(Clone, Default)]
+    struct Files(Arc<Mutex<Received>>);
+
+    impl ByteSink for Files {
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+            self.0.lock().unwrap().last_mut().unwrap().1.extend_from_slice(buf);
+            Ok(())
+        }
+    }
+
+    impl Files {
+        fn open(&self, file: &YmodemFile) -> Result<Self, Error> {
+            self.0.lock().unwrap().push((file.clone(), Vec::new()));
+            Ok(self.clone())
+        }
+    }
+
+    #[test]
+    fn crc_128_byte_blocks() {
+        let data: Vec<u8> = (0..300u32).map(|i| i as u8).collect();
+        let out = round_trip(&data, false, 0);
+        // Three 128-byte blocks, the last padded with SUB.
+        assert_eq!(out.len(), 384);
+        assert_eq!(&out[..300], &data[..]);
+        assert!(out[300..].iter().all(|&b| b == SUB));
+    }
+
+    #[test]
+    fn crc_1k_blocks_survive_corruption() {
+        let data: Vec<u8> = (0..5000u32).map(|i| (i * 7) as u8).collect();
+        let out = round_trip(&data, true, 3001);
+        // Four 1K blocks, then 128-byte blocks for the last 904 bytes.
+        assert_eq!(out.len(), 4096 + 1024);
+        assert_eq!(&out[..5000], &data[..]);
+        assert!(out[5000..].iter().all(|&b| b == SUB));
+    }
+
+    #[test]
+    fn ymodem_batch() {
+        let (tx, rx) = pair(0);
+        let sender = thread::spawn(move || {
+            let big = [1u8; 1500];
+            let files: [(&str, &[u8]); 3] = [("a.bin", &big), ("empty", &[]), ("b.txt", b"hello")];
+            xmodem(tx, true).send_batch(&files)
+        });
+        let files = Files::default();
+        let n = xmodem(rx, true).receive_batch(|file| files.open(file)).unwrap();
+        sender.join().unwrap().unwrap();
+
+        assert_eq!(n, 3);
+        let files = files.0.lock().unwrap();
+        let expect = |name: &str, data: &[u8]| {
+            (YmodemFile { name: name.into(), size: Some(data.len() as u64) }, data.to_vec())
+        };
+        assert_eq!(files[0], expect("a.bin", &[1u8; 1500]));
+        assert_eq!(files[1], expect("empty", &[]));
+        assert_eq!(files[2], expect("b.txt", b"hello"));
+    }
+
+    #[test]
+    fn repeated_ymodem_header_is_acked() {
+        let (mut sender, rx) = pair(0);
+        // The sender missed the ACK for the header and repeats it before the data.
+        let header = block(0, b"f\x003\x00", 128);
+        for packet in [&header, &header, &block(1, b"abc", 128)] {
+            sender.write_all(packet).unwrap();
+        }
+        sender.write_all(&[EOT, EOT]).unwrap();
+        sender.write_all(&block(0, &[], 128)).unwrap();
+
+        let files = Files::default();
+        assert_eq!(xmodem(rx, true).receive_batch(|file| files.open(file)), Ok(1));
+        assert_eq!(files.0.lock().unwrap()[0].1, b"abc");
+        let replies: Vec<u8> = sender.rx.lock().unwrap().drain(..).collect();
+        assert_eq!(replies, [b'C', ACK, b'C', ACK, b'C', ACK, NAK, ACK, b'C', ACK]);
+    }
+
+    #[test]
+    fn oversized_ymodem_header_is_rejected() {
+        let (tx, _rx) = pair(0);
+        let name = "n".repeat(1023);
+        let mut x = xmodem(tx, true);
+        let files: [(&str, &[u8]); 1] = [(&name, b"data")];
+        assert_eq!(x.send_batch(&files), Err(TransferError::Io(Error::InvalidArgs)));
+        assert!(x.into_inner().tx.lock().unwrap().is_empty());
+    }
+}